@@ -0,0 +1,73 @@
+use reed_solomon_rs::fec::fec::*;
+use reed_solomon_rs::math::addmul::addmul;
+use reed_solomon_rs::math::matmul::{matmul_tiled, tile_size};
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+/*
+Compares the cache-blocked encode kernel used by `FEC::encode` against the
+previous loop, which computed every parity piece in its own pass over all
+k input blocks (streaming the input n - k times).
+*/
+
+// The encode loop as it was before the tiled kernel: one full pass per parity piece.
+fn encode_per_parity(fec: &FEC, input: &[u8], parity: &mut [Vec<u8>]) {
+    let k = fec.k;
+    let block_size = input.len() / k;
+
+    for (i, out) in parity.iter_mut().enumerate() {
+        out.fill(0);
+        for j in 0..k {
+            addmul(
+                out,
+                &input[j * block_size..(j + 1) * block_size],
+                fec.enc_matrix[(k + i) * k + j],
+            );
+        }
+    }
+}
+
+fn benchmark_encode_kernels(c: &mut Criterion) {
+    let configs = [(10, 14), (16, 32), (32, 48)];
+    let data_sizes = [64 * 1024, 1024 * 1024, 8 * 1024 * 1024];
+
+    for &(required, total) in &configs {
+        let fec = FEC::new(required, total).expect("FEC init failed");
+
+        for &data_size in &data_sizes {
+            let size = data_size - data_size % required;
+            let data: Vec<u8> = (0..size).map(|i| (i * 31 + 7) as u8).collect();
+
+            let mut group =
+                c.benchmark_group(format!("Encode kernel {}B r{} t{}", size, required, total));
+            group.throughput(Throughput::Bytes(size as u64));
+
+            group.bench_function("per-parity loop", |b| {
+                let mut parity = vec![vec![0u8; size / required]; total - required];
+                b.iter(|| encode_per_parity(&fec, &data, &mut parity));
+            });
+
+            group.bench_function("tiled kernel", |b| {
+                let mut parity = vec![vec![0u8; size / required]; total - required];
+                let inputs: Vec<&[u8]> = data.chunks(size / required).collect();
+                let matrix = &fec.enc_matrix[required * required..];
+                b.iter(|| matmul_tiled(matrix, &inputs, &mut parity, tile_size(total)));
+            });
+
+            // full encode, including the copies of the data pieces into their shares
+            group.bench_function("tiled encode", |b| {
+                b.iter(|| {
+                    fec.encode(&data, |s: Share| {
+                        criterion::black_box(s);
+                    })
+                    .expect("Encoding failed");
+                });
+            });
+
+            group.finish();
+        }
+    }
+}
+
+criterion_group!(encode_kernel_benches, benchmark_encode_kernels);
+criterion_main!(encode_kernel_benches);
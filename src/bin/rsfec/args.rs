@@ -0,0 +1,170 @@
+use crate::status::{CliError, CliResult};
+use std::str::FromStr;
+
+/// `Flag` declares an option a subcommand accepts.
+pub struct Flag {
+    pub long: &'static str,
+    pub short: Option<char>,
+    pub takes_value: bool,
+}
+
+impl Flag {
+    pub const fn value(long: &'static str, short: Option<char>) -> Flag {
+        Flag {
+            long,
+            short,
+            takes_value: true,
+        }
+    }
+
+    pub const fn switch(long: &'static str) -> Flag {
+        Flag {
+            long,
+            short: None,
+            takes_value: false,
+        }
+    }
+}
+
+/// `Args` holds the parsed arguments of a subcommand.
+#[derive(Debug, Default)]
+pub struct Args {
+    pub positional: Vec<String>,
+    values: Vec<(&'static str, String)>,
+    switches: Vec<&'static str>,
+}
+
+impl Args {
+    /// `parse()` parses `args` against `flags`. Options may be given as `--name value`,
+    /// `--name=value` or `-x value`; everything after `--` is positional.
+    pub fn parse(args: &[String], flags: &[Flag]) -> CliResult<Args> {
+        let mut parsed = Args::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                parsed.positional.extend(args.by_ref().cloned());
+                break;
+            }
+
+            let (flag, inline) = if let Some(long) = arg.strip_prefix("--") {
+                let (name, inline) = match long.split_once('=') {
+                    Some((name, value)) => (name, Some(value.to_string())),
+                    None => (long, None),
+                };
+                let flag = flags
+                    .iter()
+                    .find(|f| f.long == name)
+                    .ok_or_else(|| CliError::usage(format!("unknown option --{}", name)))?;
+                (flag, inline)
+            } else if arg.len() == 2 && arg.starts_with('-') {
+                let short = arg.chars().nth(1);
+                let flag = flags
+                    .iter()
+                    .find(|f| f.short.is_some() && f.short == short)
+                    .ok_or_else(|| CliError::usage(format!("unknown option {}", arg)))?;
+                (flag, None)
+            } else {
+                parsed.positional.push(arg.clone());
+                continue;
+            };
+
+            if flag.takes_value {
+                let value = match inline {
+                    Some(value) => value,
+                    None => args
+                        .next()
+                        .cloned()
+                        .ok_or_else(|| CliError::usage(format!("--{} needs a value", flag.long)))?,
+                };
+                parsed.values.push((flag.long, value));
+            } else if inline.is_some() {
+                return Err(CliError::usage(format!(
+                    "--{} does not take a value",
+                    flag.long
+                )));
+            } else {
+                parsed.switches.push(flag.long);
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// `value()` returns the last value given for option `name`.
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .rev()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// `parse_value()` parses the value of option `name`, if given.
+    pub fn parse_value<T: FromStr>(&self, name: &str) -> CliResult<Option<T>> {
+        self.value(name)
+            .map(|v| {
+                v.parse()
+                    .map_err(|_| CliError::usage(format!("invalid value {:?} for --{}", v, name)))
+            })
+            .transpose()
+    }
+
+    /// `required_value()` parses the value of option `name`, which must be given.
+    pub fn required_value<T: FromStr>(&self, name: &str) -> CliResult<T> {
+        self.parse_value(name)?
+            .ok_or_else(|| CliError::usage(format!("--{} is required", name)))
+    }
+
+    pub fn switch(&self, name: &str) -> bool {
+        self.switches.contains(&name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FLAGS: &[Flag] = &[
+        Flag::value("required", Some('k')),
+        Flag::value("total", Some('n')),
+        Flag::switch("force"),
+    ];
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_flags_and_positionals() {
+        let parsed = Args::parse(
+            &args(&[
+                "in.txt",
+                "--required",
+                "4",
+                "-n",
+                "8",
+                "--force",
+                "--",
+                "--out",
+            ]),
+            FLAGS,
+        )
+        .unwrap();
+        assert_eq!(parsed.positional, vec!["in.txt", "--out"]);
+        assert_eq!(parsed.required_value::<usize>("required").unwrap(), 4);
+        assert_eq!(parsed.required_value::<usize>("total").unwrap(), 8);
+        assert!(parsed.switch("force"));
+
+        let parsed = Args::parse(&args(&["--total=5"]), FLAGS).unwrap();
+        assert_eq!(parsed.parse_value::<usize>("total").unwrap(), Some(5));
+        assert!(parsed.required_value::<usize>("required").is_err());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Args::parse(&args(&["--bogus"]), FLAGS).is_err());
+        assert!(Args::parse(&args(&["--required"]), FLAGS).is_err());
+        assert!(Args::parse(&args(&["--force=yes"]), FLAGS).is_err());
+        let parsed = Args::parse(&args(&["-k", "four"]), FLAGS).unwrap();
+        assert!(parsed.parse_value::<usize>("required").is_err());
+    }
+}
@@ -0,0 +1,1367 @@
+use crate::args::{Args, Flag};
+use crate::corrupt::{self, Plan, Rng};
+use crate::report::{
+    object_status_name, HeaderReport, Report, ScrubReport, ShareReport, ShareStatus, StripeFailure,
+};
+use crate::shares::{self, share_path, split_share_name, Recovery, ShareSet};
+use crate::status::{CliError, CliResult, Status};
+use reed_solomon_rs::fec::fec::{MatrixKind, FEC};
+use reed_solomon_rs::fec::striped::StripedCodec;
+use reed_solomon_rs::format::archive::{self, MANIFEST_FILE, PACK_FILE};
+use reed_solomon_rs::format::multiplex::{MultiplexReader, MultiplexWriter};
+use reed_solomon_rs::format::share_file::ShareWriter;
+use reed_solomon_rs::store::fs::FsStore;
+use reed_solomon_rs::store::scrub::{self, ScrubOptions};
+use reed_solomon_rs::stream::encoder::StreamEncoder;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Bytes per piece of a stripe when `--stripe-size` is not given.
+const DEFAULT_PIECE_SIZE: usize = 64 * 1024;
+
+/// Path argument standing for stdin or stdout.
+const STDIO: &str = "-";
+
+const ENCODE_FLAGS: &[Flag] = &[
+    Flag::value("required", Some('k')),
+    Flag::value("total", Some('n')),
+    Flag::value("stripe-size", None),
+    Flag::value("matrix", None),
+    Flag::value("output", Some('o')),
+    Flag::value("name", None),
+    Flag::switch("stream"),
+    Flag::switch("force"),
+];
+
+const DECODE_FLAGS: &[Flag] = &[
+    Flag::value("output", Some('o')),
+    Flag::switch("stream"),
+    Flag::switch("force"),
+];
+
+const CORRUPT_FLAGS: &[Flag] = &[
+    Flag::value("seed", None),
+    Flag::value("delete", None),
+    Flag::value("truncate", None),
+    Flag::value("swap", None),
+    Flag::value("flip", None),
+    Flag::value("burst", None),
+    Flag::value("burst-len", None),
+];
+
+const ARCHIVE_FLAGS: &[Flag] = &[
+    Flag::value("required", Some('k')),
+    Flag::value("total", Some('n')),
+    Flag::value("stripe-size", None),
+    Flag::value("matrix", None),
+    Flag::switch("force"),
+];
+
+const RESTORE_FLAGS: &[Flag] = &[Flag::value("output", Some('o')), Flag::switch("force")];
+
+const SCRUB_FLAGS: &[Flag] = &[
+    Flag::switch("dry-run"),
+    Flag::value("rate", None),
+    Flag::value("cursor", None),
+    Flag::value("max-objects", None),
+];
+
+/// Bytes overwritten by a burst when `--burst-len` is not given.
+const DEFAULT_BURST_LEN: u64 = 64;
+
+fn parse_matrix(name: &str) -> CliResult<MatrixKind> {
+    match name {
+        "vandermonde" => Ok(MatrixKind::Vandermonde),
+        "cauchy" => Ok(MatrixKind::Cauchy),
+        _ => Err(CliError::usage(format!(
+            "unknown matrix {:?}, expected vandermonde or cauchy",
+            name
+        ))),
+    }
+}
+
+fn matrix_name(matrix: MatrixKind) -> &'static str {
+    match matrix {
+        MatrixKind::Vandermonde => "vandermonde",
+        MatrixKind::Cauchy => "cauchy",
+    }
+}
+
+fn single_positional<'a>(args: &'a Args, what: &str) -> CliResult<&'a str> {
+    match args.positional.as_slice() {
+        [one] => Ok(one),
+        [] => Err(CliError::usage(format!("missing {}", what))),
+        _ => Err(CliError::usage(format!("expected a single {}", what))),
+    }
+}
+
+/// `display_name()` names `path` in messages.
+fn display_name(path: &str) -> &str {
+    if path == STDIO {
+        "stdin"
+    } else {
+        path
+    }
+}
+
+/// `open_input()` opens `path` for reading, or stdin if it is `-`.
+fn open_input(path: &str) -> CliResult<Box<dyn Read>> {
+    if path == STDIO {
+        return Ok(Box::new(io::stdin().lock()));
+    }
+    let file = File::open(path).map_err(CliError::io(path))?;
+    Ok(Box::new(file))
+}
+
+/// `CopyError` tells which side of `copy()` failed.
+enum CopyError {
+    Read(io::Error),
+    Write(io::Error),
+}
+
+/// `copy()` is `io::copy()` for callers that report read and write errors differently.
+fn copy<R: Read, W: Write>(reader: &mut R, writer: &mut W) -> Result<u64, CopyError> {
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut total = 0;
+    loop {
+        let read = match reader.read(&mut buf) {
+            Ok(0) => return Ok(total),
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(CopyError::Read(e)),
+        };
+        writer.write_all(&buf[..read]).map_err(CopyError::Write)?;
+        total += read as u64;
+    }
+}
+
+/// `Output` is where recovered data goes. A file is written under a temporary name
+/// and only renamed into place by `commit()`, so a failed decode never leaves a
+/// truncated file behind. Stdout gets the data as it is recovered.
+struct Output {
+    writer: BufWriter<Box<dyn Write>>,
+    /// The temporary and the final path when writing to a file.
+    paths: Option<(PathBuf, PathBuf)>,
+}
+
+impl Output {
+    fn create(path: &str) -> CliResult<Output> {
+        if path == STDIO {
+            return Ok(Output {
+                writer: BufWriter::new(Box::new(io::stdout().lock())),
+                paths: None,
+            });
+        }
+        let partial = PathBuf::from(format!("{}.partial", path));
+        let file = File::create(&partial).map_err(CliError::io(partial.display()))?;
+        Ok(Output {
+            writer: BufWriter::new(Box::new(file)),
+            paths: Some((partial, PathBuf::from(path))),
+        })
+    }
+
+    fn name(&self) -> String {
+        match &self.paths {
+            Some((_, path)) => path.display().to_string(),
+            None => "stdout".to_string(),
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) -> CliResult<()> {
+        let name = self.name();
+        self.writer.write_all(data).map_err(CliError::io(name))
+    }
+
+    fn commit(mut self) -> CliResult<()> {
+        let name = self.name();
+        self.writer.flush().map_err(CliError::io(&name))?;
+        if let Some((partial, path)) = &self.paths {
+            fs::rename(partial, path).map_err(CliError::io(name))?;
+        }
+        Ok(())
+    }
+
+    fn discard(self) {
+        if let Some((partial, _)) = &self.paths {
+            let _ = fs::remove_file(partial);
+        }
+    }
+}
+
+/// `codec_args()` reads the code parameters shared by `encode` and `archive`.
+fn codec_args(args: &Args) -> CliResult<StripedCodec> {
+    let k: usize = args.required_value("required")?;
+    let n: usize = args.required_value("total")?;
+    let matrix = match args.value("matrix") {
+        Some(name) => parse_matrix(name)?,
+        None => MatrixKind::default(),
+    };
+    let stripe_size = args
+        .parse_value("stripe-size")?
+        .unwrap_or(k.saturating_mul(DEFAULT_PIECE_SIZE));
+
+    let fec = FEC::new_with_matrix(k, n, matrix).map_err(|e| CliError::invalid(e.to_string()))?;
+    StripedCodec::new(fec, stripe_size).map_err(|e| CliError::invalid(e.to_string()))
+}
+
+/// `encode()` erasure-codes a file, or stdin, into one share file per share number or
+/// into a single multiplexed share stream.
+pub fn encode(args: &[String], out: &mut Report) -> CliResult<Status> {
+    let args = Args::parse(args, ENCODE_FLAGS)?;
+    let input = single_positional(&args, "input file")?;
+    let codec = codec_args(&args)?;
+    let (k, n, stripe_size) = (codec.fec().k, codec.fec().n, codec.stripe_size());
+    out.k = Some(k);
+    out.n = Some(n);
+
+    if args.switch("stream") {
+        return encode_stream(&args, input, codec, out);
+    }
+
+    let stem = match args.value("name") {
+        Some(name) => name.to_string(),
+        None if input == STDIO => "stdin".to_string(),
+        None => Path::new(input)
+            .file_name()
+            .and_then(|s| s.to_str())
+            .ok_or_else(|| CliError::invalid(format!("{}: not a file name", input)))?
+            .to_string(),
+    };
+    let dir = match args.value("output") {
+        Some(dir) => PathBuf::from(dir),
+        None if input == STDIO => PathBuf::from("."),
+        None => Path::new(input)
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or(Path::new("."))
+            .to_path_buf(),
+    };
+    let mut source = open_input(input)?;
+    fs::create_dir_all(&dir).map_err(CliError::io(dir.display()))?;
+
+    if !args.switch("force") {
+        if let Some(path) = (0..n)
+            .map(|i| share_path(&dir, &stem, i))
+            .find(|p| p.exists())
+        {
+            return Err(CliError::invalid(format!(
+                "{} already exists, use --force to overwrite",
+                path.display()
+            )));
+        }
+    }
+
+    let mut writers = Vec::with_capacity(n);
+    for number in 0..n {
+        let path = share_path(&dir, &stem, number);
+        let file = File::create(&path).map_err(CliError::io(path.display()))?;
+        let writer = ShareWriter::new(BufWriter::new(file), codec.fec(), number, stripe_size)
+            .map_err(CliError::io(path.display()))?;
+        writers.push(writer);
+    }
+
+    let mut encoder = StreamEncoder::new(codec, writers).map_err(CliError::io("encoder"))?;
+    copy(&mut source, &mut encoder).map_err(|e| match e {
+        CopyError::Read(e) => CliError::io(display_name(input))(e),
+        CopyError::Write(e) => CliError::io(dir.display())(e),
+    })?;
+    let (writers, len) = encoder.finish().map_err(CliError::io(dir.display()))?;
+    for writer in writers {
+        writer.finish(len).map_err(CliError::io(dir.display()))?;
+    }
+
+    out.bytes = Some(len);
+    out.shares = (0..n)
+        .map(|number| ShareReport {
+            path: Some(share_path(&dir, &stem, number).display().to_string()),
+            ..ShareReport::new(number, ShareStatus::Ok)
+        })
+        .collect();
+    out.note(format_args!(
+        "encoded {} ({} bytes) into {} shares in {}, any {} of which recover it",
+        display_name(input),
+        len,
+        n,
+        dir.display(),
+        k
+    ));
+    Ok(Status::Ok)
+}
+
+/// `encode_stream()` writes all shares of the input, stripe by stripe, into one
+/// multiplexed stream, either a file or stdout.
+fn encode_stream(
+    args: &Args,
+    input: &str,
+    codec: StripedCodec,
+    out: &mut Report,
+) -> CliResult<Status> {
+    let (k, n) = (codec.fec().k, codec.fec().n);
+    let target = args.value("output").unwrap_or(STDIO);
+    let mut source = open_input(input)?;
+    let sink: Box<dyn Write> = if target == STDIO {
+        out.stdout_is_data = true;
+        Box::new(io::stdout().lock())
+    } else {
+        if !args.switch("force") && Path::new(target).exists() {
+            return Err(CliError::invalid(format!(
+                "{} already exists, use --force to overwrite",
+                target
+            )));
+        }
+        Box::new(File::create(target).map_err(CliError::io(target))?)
+    };
+    let target = if target == STDIO { "stdout" } else { target };
+
+    let mut writer =
+        MultiplexWriter::new(codec, BufWriter::new(sink)).map_err(CliError::io(target))?;
+    copy(&mut source, &mut writer).map_err(|e| match e {
+        CopyError::Read(e) => CliError::io(display_name(input))(e),
+        CopyError::Write(e) => CliError::io(target)(e),
+    })?;
+    let (mut sink, len) = writer.finish().map_err(CliError::io(target))?;
+    sink.flush().map_err(CliError::io(target))?;
+
+    out.bytes = Some(len);
+    out.note(format_args!(
+        "encoded {} ({} bytes) into a stream of {} shares in {}, any {} of which recover it",
+        display_name(input),
+        len,
+        n,
+        target,
+        k
+    ));
+    Ok(Status::Ok)
+}
+
+/// `report()` prints what was wrong with the shares and the stripes to stderr, keeping
+/// stdout free for recovered data, and records the status of every share in `out`.
+fn report(set: &ShareSet, recovery: &Recovery, out: &mut Report) {
+    out.k = Some(set.header.k);
+    out.n = Some(set.header.n);
+    out.bytes = Some(recovery.len);
+
+    let checksum_failures = set.checksum_failures();
+    let mut reports: Vec<ShareReport> = (0..set.header.n)
+        .map(|number| {
+            let path = set.shares.iter().find(|s| s.header.number == number);
+            let corrected_bytes = recovery.corrected.get(&number).copied().unwrap_or(0);
+            let status = if path.is_none() {
+                ShareStatus::Missing
+            } else if corrected_bytes > 0 {
+                ShareStatus::Corrected
+            } else if checksum_failures.contains(&number) {
+                ShareStatus::Invalid
+            } else {
+                ShareStatus::Ok
+            };
+            ShareReport {
+                path: path.map(|s| s.path.display().to_string()),
+                corrected_bytes,
+                ..ShareReport::new(number, status)
+            }
+        })
+        .collect();
+
+    for rejected in &set.rejected {
+        out.note(format_args!(
+            "ignored {}: {}",
+            rejected.path.display(),
+            rejected.reason
+        ));
+        reports.push(ShareReport {
+            number: None,
+            path: Some(rejected.path.display().to_string()),
+            reason: Some(rejected.reason.clone()),
+            ..ShareReport::new(0, ShareStatus::Invalid)
+        });
+    }
+    for number in &recovery.missing {
+        out.note(format_args!("share {}: missing", number));
+    }
+    for number in &recovery.damaged {
+        if checksum_failures.contains(number) {
+            out.note(format_args!(
+                "share {}: damaged (checksum mismatch)",
+                number
+            ));
+        } else {
+            out.note(format_args!(
+                "share {}: corrected ({} bytes)",
+                number, recovery.corrected[number]
+            ));
+        }
+    }
+    for (stripe, reason) in &recovery.failed_stripes {
+        out.note(format_args!("stripe {}: unrecoverable: {}", stripe, reason));
+        out.failed_stripes.push(StripeFailure {
+            stripe: *stripe,
+            reason: reason.clone(),
+        });
+    }
+    out.shares = reports;
+}
+
+/// `check_only()` decodes every stripe of the shares at `paths` without keeping the data.
+fn check_only(paths: &[String], out: &mut Report) -> CliResult<(ShareSet, Recovery)> {
+    let set = shares::load_set(&shares::expand_paths(paths)?)?;
+    let recovery = shares::recover(&set, false, |_, _| Ok(()))?;
+    report(&set, &recovery, out);
+    Ok((set, recovery))
+}
+
+/// `decode()` recovers the original data from its share files or from a multiplexed
+/// share stream, writing it stripe by stripe to a file or stdout.
+pub fn decode(args: &[String], out: &mut Report) -> CliResult<Status> {
+    let args = Args::parse(args, DECODE_FLAGS)?;
+    let target: String = args.required_value("output")?;
+    out.stdout_is_data = target == STDIO;
+    if target != STDIO && !args.switch("force") && Path::new(&target).exists() {
+        return Err(CliError::invalid(format!(
+            "{} already exists, use --force to overwrite",
+            target
+        )));
+    }
+    if args.switch("stream") {
+        return decode_stream(&args, &target, out);
+    }
+
+    let set = shares::load_set(&shares::expand_paths(&args.positional)?)?;
+    let mut output = Output::create(&target)?;
+    let recovery = match shares::recover(&set, true, |_, data| output.write(data)) {
+        Ok(recovery) => recovery,
+        Err(e) => {
+            output.discard();
+            return Err(e);
+        }
+    };
+    report(&set, &recovery, out);
+
+    let status = recovery.status();
+    if let Some((stripe, _)) = recovery.failed_stripes.first() {
+        output.discard();
+        return Err(CliError::unrecoverable(format!(
+            "stripe {} of {} could not be recovered",
+            stripe,
+            shares::stripes(&set.header)?.len()
+        )));
+    }
+    let name = output.name();
+    output.commit()?;
+    out.note(format_args!(
+        "recovered {} bytes from {} of {} shares into {}",
+        recovery.len,
+        set.shares.len(),
+        set.header.n,
+        name
+    ));
+    Ok(status)
+}
+
+/// `decode_stream()` recovers the data from a multiplexed share stream.
+fn decode_stream(args: &Args, target: &str, out: &mut Report) -> CliResult<Status> {
+    let input = single_positional(args, "share stream")?;
+    let name = display_name(input);
+    let source = BufReader::new(open_input(input)?);
+    let mut reader = MultiplexReader::new(source).map_err(|e| match e.kind() {
+        io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => {
+            CliError::invalid(format!("{}: not a valid share stream: {}", name, e))
+        }
+        _ => CliError::io(name)(e),
+    })?;
+    out.k = Some(reader.codec().fec().k);
+    out.n = Some(reader.codec().fec().n);
+
+    let mut output = Output::create(target)?;
+    if let Err(e) = copy(&mut reader, &mut output.writer) {
+        let err = match e {
+            CopyError::Read(e) if e.kind() == io::ErrorKind::InvalidData => {
+                CliError::unrecoverable(format!("{}: {}", name, e))
+            }
+            CopyError::Read(e) => CliError::io(name)(e),
+            CopyError::Write(e) => CliError::io(output.name())(e),
+        };
+        output.discard();
+        return Err(err);
+    }
+    let out_name = output.name();
+    output.commit()?;
+
+    let damaged = reader.damaged_chunks();
+    out.bytes = Some(reader.len());
+    out.damaged_chunks = Some(damaged);
+    if damaged > 0 {
+        out.note(format_args!("rebuilt {} damaged chunks", damaged));
+    }
+    out.note(format_args!(
+        "recovered {} bytes from {} into {}",
+        reader.len(),
+        name,
+        out_name
+    ));
+    Ok(if damaged > 0 {
+        Status::Recovered
+    } else {
+        Status::Ok
+    })
+}
+
+/// `verify()` checks that the share files are intact, without writing anything.
+pub fn verify(args: &[String], out: &mut Report) -> CliResult<Status> {
+    let args = Args::parse(args, &[])?;
+    let (set, recovery) = check_only(&args.positional, out)?;
+    let status = recovery.status();
+    match status {
+        Status::Ok => out.line(format_args!("all {} shares are intact", set.header.n)),
+        Status::Recovered => out.line(format_args!(
+            "{} of {} shares are missing or damaged; the data is recoverable",
+            recovery.missing.len() + recovery.damaged.len(),
+            set.header.n
+        )),
+        _ => out.line("the data is not recoverable"),
+    }
+    Ok(status)
+}
+
+/// `repair()` rewrites the missing and damaged share files from the intact ones.
+///
+/// A first pass finds the shares to rewrite. A second pass decodes the data again and
+/// re-encodes it stripe by stripe into temporary files, which replace the broken shares
+/// only once all of them were written.
+pub fn repair(args: &[String], out: &mut Report) -> CliResult<Status> {
+    let args = Args::parse(args, &[])?;
+    let (set, recovery) = check_only(&args.positional, out)?;
+    match recovery.status() {
+        Status::Ok => {
+            out.note(format_args!("all {} shares are intact", set.header.n));
+            return Ok(Status::Ok);
+        }
+        Status::Unrecoverable => {
+            return Err(CliError::unrecoverable(
+                "the data is not recoverable, nothing was repaired",
+            ))
+        }
+        _ => {}
+    }
+
+    let header = set.header;
+    let fec = shares::fec(&header)?;
+    let first = &set.shares[0].path;
+    let dir = first.parent().map(Path::to_path_buf).unwrap_or_default();
+    let stem = match split_share_name(first) {
+        Some((stem, _)) => stem,
+        None => first
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("repaired")
+            .to_string(),
+    };
+
+    // (number, final path, temporary path)
+    let targets: Vec<(usize, PathBuf, PathBuf)> = recovery
+        .missing
+        .iter()
+        .chain(&recovery.damaged)
+        .map(|&number| {
+            let path = set
+                .shares
+                .iter()
+                .find(|s| s.header.number == number)
+                .map(|s| s.path.clone())
+                .unwrap_or_else(|| share_path(&dir, &stem, number));
+            let partial = PathBuf::from(format!("{}.partial", path.display()));
+            (number, path, partial)
+        })
+        .collect();
+
+    let result = write_repairs(&set, &fec, &targets);
+    if result.is_err() {
+        for (_, _, partial) in &targets {
+            let _ = fs::remove_file(partial);
+        }
+    }
+    result?;
+
+    for (number, path, partial) in &targets {
+        fs::rename(partial, path).map_err(CliError::io(path.display()))?;
+        out.repaired.push(*number);
+        out.note(format_args!("share {}: rewrote {}", number, path.display()));
+    }
+    Ok(Status::Recovered)
+}
+
+/// `write_repairs()` writes the shares listed in `targets` to their temporary paths.
+fn write_repairs(
+    set: &ShareSet,
+    fec: &FEC,
+    targets: &[(usize, PathBuf, PathBuf)],
+) -> CliResult<()> {
+    let header = set.header;
+    let mut writers = Vec::with_capacity(targets.len());
+    for (number, _, partial) in targets {
+        let file = File::create(partial).map_err(CliError::io(partial.display()))?;
+        let writer = ShareWriter::new(BufWriter::new(file), fec, *number, header.stripe_size)
+            .map_err(CliError::io(partial.display()))?;
+        writers.push(writer);
+    }
+
+    let recovery = shares::recover(set, true, |_, data| {
+        let mut pieces = vec![Vec::new(); header.n];
+        fec.encode(data, |s| pieces[s.number] = s.data)
+            .map_err(|e| CliError::invalid(e.to_string()))?;
+        for (writer, (number, _, partial)) in writers.iter_mut().zip(targets) {
+            writer
+                .write_all(&pieces[*number])
+                .map_err(CliError::io(partial.display()))?;
+        }
+        Ok(())
+    })?;
+    if !recovery.failed_stripes.is_empty() {
+        return Err(CliError::unrecoverable(
+            "the shares changed while repairing, nothing was repaired",
+        ));
+    }
+
+    for (writer, (_, _, partial)) in writers.into_iter().zip(targets) {
+        writer
+            .finish(header.original_len)
+            .map_err(CliError::io(partial.display()))?;
+    }
+    Ok(())
+}
+
+/// `archive_error()` maps an error of the archive functions to an exit status: damage
+/// beyond repair is unrecoverable, other I/O errors are I/O errors.
+fn archive_error(e: Box<dyn Error>) -> CliError {
+    match e.downcast_ref::<io::Error>().map(io::Error::kind) {
+        Some(io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof) | None => {
+            CliError::unrecoverable(e.to_string())
+        }
+        Some(_) => CliError::new(Status::Io, e.to_string()),
+    }
+}
+
+/// `archive()` erasure-codes a directory tree into n output directories, given one by
+/// one or as a single parent directory that receives `000`, `001`, ...
+pub fn archive(args: &[String], out: &mut Report) -> CliResult<Status> {
+    let args = Args::parse(args, ARCHIVE_FLAGS)?;
+    let (src, dests) = match args.positional.split_first() {
+        Some((src, dests)) if !dests.is_empty() => (PathBuf::from(src), dests),
+        _ => {
+            return Err(CliError::usage(
+                "expected a directory and output directories",
+            ))
+        }
+    };
+    let codec = codec_args(&args)?;
+    let (k, n) = (codec.fec().k, codec.fec().n);
+    let outputs: Vec<PathBuf> = match dests {
+        [parent] => (0..n)
+            .map(|i| Path::new(parent).join(format!("{:03}", i)))
+            .collect(),
+        _ if dests.len() == n => dests.iter().map(PathBuf::from).collect(),
+        _ => {
+            return Err(CliError::usage(format!(
+                "expected 1 or {} output directories, got {}",
+                n,
+                dests.len()
+            )))
+        }
+    };
+
+    let root = src.canonicalize().map_err(CliError::io(src.display()))?;
+    for dir in &outputs {
+        if !args.switch("force") && dir.join(MANIFEST_FILE).exists() {
+            return Err(CliError::invalid(format!(
+                "{} already holds an archive, use --force to overwrite",
+                dir.display()
+            )));
+        }
+        fs::create_dir_all(dir).map_err(CliError::io(dir.display()))?;
+        let canonical = dir.canonicalize().map_err(CliError::io(dir.display()))?;
+        if canonical.starts_with(&root) {
+            return Err(CliError::invalid(format!(
+                "{} is inside the archived directory",
+                dir.display()
+            )));
+        }
+    }
+
+    let manifest = archive::archive_tree(&src, &outputs, codec).map_err(archive_error)?;
+    out.k = Some(k);
+    out.n = Some(n);
+    out.bytes = Some(manifest.pack_len);
+    out.files = Some(manifest.files());
+    out.shares = outputs
+        .iter()
+        .enumerate()
+        .map(|(number, dir)| ShareReport {
+            path: Some(dir.display().to_string()),
+            ..ShareReport::new(number, ShareStatus::Ok)
+        })
+        .collect();
+    out.note(format_args!(
+        "archived {} files ({} bytes) from {} into {} directories, any {} of which restore it",
+        manifest.files(),
+        manifest.pack_len,
+        src.display(),
+        n,
+        k
+    ));
+    Ok(Status::Ok)
+}
+
+/// `archive_dirs()` replaces every argument that is not an archive output directory
+/// with the output directories it contains.
+fn archive_dirs(args: &[String]) -> CliResult<Vec<PathBuf>> {
+    if args.is_empty() {
+        return Err(CliError::usage("no archive directories given"));
+    }
+    let is_output = |dir: &Path| dir.join(MANIFEST_FILE).exists() || dir.join(PACK_FILE).exists();
+    let mut dirs = Vec::new();
+    for arg in args {
+        let dir = PathBuf::from(arg);
+        if is_output(&dir) {
+            dirs.push(dir);
+            continue;
+        }
+        let mut children: Vec<PathBuf> = fs::read_dir(&dir)
+            .map_err(CliError::io(dir.display()))?
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.is_dir() && is_output(p))
+            .collect();
+        children.sort();
+        dirs.extend(children);
+    }
+    Ok(dirs)
+}
+
+/// `restore()` restores a directory tree from any k of its archive directories.
+pub fn restore(args: &[String], out: &mut Report) -> CliResult<Status> {
+    let args = Args::parse(args, RESTORE_FLAGS)?;
+    let target: PathBuf = args.required_value("output")?;
+    let dirs = archive_dirs(&args.positional)?;
+    if !args.switch("force") {
+        if let Ok(mut entries) = fs::read_dir(&target) {
+            if entries.next().is_some() {
+                return Err(CliError::invalid(format!(
+                    "{} is not empty, use --force to restore into it",
+                    target.display()
+                )));
+            }
+        }
+    }
+
+    let restored = archive::restore_tree(&dirs, &target).map_err(archive_error)?;
+    let manifest = &restored.manifest;
+    out.k = Some(restored.header.k);
+    out.n = Some(restored.header.n);
+    out.bytes = Some(manifest.pack_len);
+    out.files = Some(manifest.files());
+    out.shares = (0..restored.header.n)
+        .map(|number| {
+            let status = if restored.missing.contains(&number) {
+                ShareStatus::Missing
+            } else if restored.dropped.contains(&number) {
+                ShareStatus::Invalid
+            } else {
+                ShareStatus::Ok
+            };
+            ShareReport::new(number, status)
+        })
+        .collect();
+    for number in &restored.missing {
+        out.note(format_args!("share {}: missing", number));
+    }
+    for number in &restored.dropped {
+        out.note(format_args!(
+            "share {}: damaged, dropped while decoding",
+            number
+        ));
+    }
+    out.note(format_args!(
+        "restored {} files ({} bytes) into {}",
+        manifest.files(),
+        manifest.pack_len,
+        target.display()
+    ));
+    Ok(
+        if restored.missing.is_empty() && restored.dropped.is_empty() {
+            Status::Ok
+        } else {
+            Status::Recovered
+        },
+    )
+}
+
+/// `corrupt()` damages share files in reproducible ways, to rehearse recovery. It
+/// prints the seed and every change it made.
+pub fn corrupt(args: &[String], out: &mut Report) -> CliResult<Status> {
+    let args = Args::parse(args, CORRUPT_FLAGS)?;
+    let plan = Plan {
+        delete: args.parse_value("delete")?.unwrap_or(0),
+        truncate: args.parse_value("truncate")?.unwrap_or(0),
+        swap: args.parse_value("swap")?.unwrap_or(0),
+        flip: args.parse_value("flip")?.unwrap_or(0),
+        burst: args.parse_value("burst")?.unwrap_or(0),
+        burst_len: args.parse_value("burst-len")?.unwrap_or(DEFAULT_BURST_LEN),
+    };
+    if plan.delete + plan.truncate + plan.swap + plan.flip + plan.burst == 0 {
+        return Err(CliError::usage(
+            "nothing to do, give at least one of --delete, --truncate, --swap, --flip or --burst",
+        ));
+    }
+    let seed = match args.parse_value("seed")? {
+        Some(seed) => seed,
+        None => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64),
+    };
+
+    let paths = shares::expand_paths(&args.positional)?;
+    let damage = corrupt::plan_damage(&paths, &plan, &mut Rng::new(seed))?;
+    out.seed = Some(seed);
+    out.line(format_args!("seed {}", seed));
+    for change in &damage {
+        corrupt::apply_damage(change)?;
+        out.line(change);
+        out.damage.push(change.to_string());
+    }
+    Ok(Status::Ok)
+}
+
+/// `scrub()` checks the objects of a share store kept under one directory per disk,
+/// as laid out by `FsStore`, and rewrites their missing and damaged shares.
+pub fn scrub(args: &[String], out: &mut Report) -> CliResult<Status> {
+    let args = Args::parse(args, SCRUB_FLAGS)?;
+    if args.positional.is_empty() {
+        return Err(CliError::usage("no store directories given"));
+    }
+    let roots: Vec<PathBuf> = args.positional.iter().map(PathBuf::from).collect();
+    let store = FsStore::new(roots).map_err(CliError::io("store"))?;
+    let cursor: Option<PathBuf> = args.parse_value("cursor")?;
+    let start_after = match &cursor {
+        Some(path) => scrub::load_cursor(path).map_err(CliError::io(path.display()))?,
+        None => None,
+    };
+    let options = ScrubOptions {
+        dry_run: args.switch("dry-run"),
+        bytes_per_sec: args.parse_value("rate")?,
+        start_after,
+        max_objects: args.parse_value("max-objects")?,
+    };
+    if options.max_objects == Some(0) {
+        return Err(CliError::usage("--max-objects must be at least 1"));
+    }
+
+    let summary = scrub::scrub(&store, &options).map_err(CliError::io("store"))?;
+    if let Some(path) = &cursor {
+        scrub::save_cursor(path, summary.next_cursor.as_deref())
+            .map_err(CliError::io(path.display()))?;
+    }
+    for report in &summary.reports {
+        let mut line = format!("{}: {}", report.id, object_status_name(report.status));
+        for (what, numbers) in [
+            ("missing", &report.missing),
+            ("invalid", &report.invalid),
+            ("corrected", &report.corrected),
+            ("rewrote", &report.rewritten),
+        ] {
+            if !numbers.is_empty() {
+                line.push_str(&format!(", {} {:?}", what, numbers));
+            }
+        }
+        if let Some(error) = &report.error {
+            line.push_str(&format!(": {}", error));
+        }
+        out.note(line);
+    }
+    out.line(&summary);
+    out.bytes = Some(summary.bytes);
+    out.scrub = Some(ScrubReport::new(&summary));
+
+    Ok(if summary.failed > 0 {
+        Status::Io
+    } else if summary.unrecoverable > 0 {
+        Status::Unrecoverable
+    } else if summary.repaired + summary.damaged > 0 {
+        Status::Recovered
+    } else {
+        Status::Ok
+    })
+}
+
+/// `inspect()` prints the header of every share file.
+pub fn inspect(args: &[String], out: &mut Report) -> CliResult<Status> {
+    let args = Args::parse(args, &[])?;
+    let mut status = Status::Ok;
+    for path in shares::expand_paths(&args.positional)? {
+        match shares::load_share(&path) {
+            Ok(share) => {
+                let h = &share.header;
+                out.line(format_args!("{}:", path.display()));
+                out.line(format_args!("  version:         {}", h.version));
+                out.line(format_args!("  share:           {} of {}", h.number, h.n));
+                out.line(format_args!("  required:        {}", h.k));
+                out.line(format_args!("  matrix:          {}", matrix_name(h.matrix)));
+                out.line(format_args!("  stripe size:     {}", h.stripe_size));
+                out.line(format_args!("  original length: {}", h.original_len));
+                out.line(format_args!("  payload length:  {}", h.payload_len));
+                out.line(format_args!(
+                    "  checksum:        {:08x} ({})",
+                    h.payload_checksum,
+                    if share.checksum_ok { "ok" } else { "mismatch" }
+                ));
+                let status = if share.checksum_ok {
+                    ShareStatus::Ok
+                } else {
+                    ShareStatus::Invalid
+                };
+                out.k = Some(h.k);
+                out.n = Some(h.n);
+                out.shares.push(ShareReport {
+                    path: Some(path.display().to_string()),
+                    header: Some(HeaderReport::new(h, matrix_name(h.matrix))),
+                    ..ShareReport::new(h.number, status)
+                });
+            }
+            Err(reason) => {
+                out.line(format_args!(
+                    "{}: invalid share file: {}",
+                    path.display(),
+                    reason
+                ));
+                out.shares.push(ShareReport {
+                    number: None,
+                    path: Some(path.display().to_string()),
+                    reason: Some(reason),
+                    ..ShareReport::new(0, ShareStatus::Invalid)
+                });
+                status = Status::InvalidInput;
+            }
+        }
+    }
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reed_solomon_rs::format::share_file::{ShareFile, HEADER_LEN};
+
+    /// A scratch directory removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let dir = std::env::temp_dir().join(format!("rsfec-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+
+        fn path(&self, name: &str) -> String {
+            self.0.join(name).to_str().unwrap().to_string()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    // the commands with their text output silenced
+    fn encode(args: &[String]) -> CliResult<Status> {
+        super::encode(args, &mut Report::new("encode", true))
+    }
+
+    fn decode(args: &[String]) -> CliResult<Status> {
+        super::decode(args, &mut Report::new("decode", true))
+    }
+
+    fn verify(args: &[String]) -> CliResult<Status> {
+        super::verify(args, &mut Report::new("verify", true))
+    }
+
+    fn repair(args: &[String]) -> CliResult<Status> {
+        super::repair(args, &mut Report::new("repair", true))
+    }
+
+    fn inspect(args: &[String]) -> CliResult<Status> {
+        super::inspect(args, &mut Report::new("inspect", true))
+    }
+
+    fn corrupt(args: &[String]) -> CliResult<Status> {
+        super::corrupt(args, &mut Report::new("corrupt", true))
+    }
+
+    fn archive(args: &[String]) -> CliResult<Status> {
+        super::archive(args, &mut Report::new("archive", true))
+    }
+
+    fn restore(args: &[String]) -> CliResult<Status> {
+        super::restore(args, &mut Report::new("restore", true))
+    }
+
+    fn scrub(args: &[String]) -> CliResult<Status> {
+        super::scrub(args, &mut Report::new("scrub", true))
+    }
+
+    fn encode_sample(tmp: &TempDir) -> Vec<u8> {
+        let data: Vec<u8> = (0..5000u32).map(|i| (i * 13 + 7) as u8).collect();
+        fs::write(tmp.path("input.bin"), &data).unwrap();
+        let status = encode(&args(&[
+            "--required",
+            "3",
+            "--total",
+            "7",
+            "--stripe-size",
+            "999",
+            "-o",
+            &tmp.path("shares"),
+            &tmp.path("input.bin"),
+        ]))
+        .unwrap();
+        assert_eq!(status, Status::Ok);
+        data
+    }
+
+    #[test]
+    fn test_encode_decode_round_trip() {
+        let tmp = TempDir::new("round-trip");
+        let data = encode_sample(&tmp);
+
+        let status = decode(&args(&["-o", &tmp.path("out.bin"), &tmp.path("shares")])).unwrap();
+        assert_eq!(status, Status::Ok);
+        assert_eq!(fs::read(tmp.path("out.bin")).unwrap(), data);
+
+        // an existing output is only replaced with --force
+        fs::write(tmp.path("out.bin"), b"keep").unwrap();
+        let argv = ["-o", &tmp.path("out.bin"), &tmp.path("shares")];
+        let err = decode(&args(&argv)).unwrap_err();
+        assert_eq!(err.status, Status::InvalidInput);
+        assert_eq!(fs::read(tmp.path("out.bin")).unwrap(), b"keep");
+        let argv = ["--force", "-o", &tmp.path("out.bin"), &tmp.path("shares")];
+        assert_eq!(decode(&args(&argv)).unwrap(), Status::Ok);
+        assert_eq!(fs::read(tmp.path("out.bin")).unwrap(), data);
+        assert_eq!(verify(&args(&[&tmp.path("shares")])).unwrap(), Status::Ok);
+        assert_eq!(
+            inspect(&args(&[&tmp.path("shares/input.bin.000.share")])).unwrap(),
+            Status::Ok
+        );
+    }
+
+    #[test]
+    fn test_decode_and_repair_damaged_shares() {
+        let tmp = TempDir::new("repair");
+        let data = encode_sample(&tmp);
+
+        // one share missing, one with a flipped byte, one with a broken header
+        fs::remove_file(tmp.path("shares/input.bin.001.share")).unwrap();
+        let corrupt = tmp.path("shares/input.bin.004.share");
+        let mut bytes = fs::read(&corrupt).unwrap();
+        bytes[100] ^= 0xFF;
+        fs::write(&corrupt, bytes).unwrap();
+        fs::write(tmp.path("shares/input.bin.006.share"), b"garbage").unwrap();
+
+        let status = decode(&args(&["-o", &tmp.path("out.bin"), &tmp.path("shares")])).unwrap();
+        assert_eq!(status, Status::Recovered);
+        assert_eq!(fs::read(tmp.path("out.bin")).unwrap(), data);
+
+        assert_eq!(
+            repair(&args(&[&tmp.path("shares")])).unwrap(),
+            Status::Recovered
+        );
+        assert_eq!(verify(&args(&[&tmp.path("shares")])).unwrap(), Status::Ok);
+    }
+
+    #[test]
+    fn test_decode_prefers_verified_shares() {
+        let tmp = TempDir::new("verified");
+        let encode_into = |dir: &str, data: &[u8]| {
+            fs::write(tmp.path("input.bin"), data).unwrap();
+            let k = [
+                "-k",
+                "2",
+                "-n",
+                "6",
+                "-o",
+                &tmp.path(dir),
+                &tmp.path("input.bin"),
+            ];
+            encode(&args(&k)).unwrap();
+        };
+        let data = vec![0x11u8; 1000];
+        encode_into("shares", &data);
+        encode_into("other", &[0x22u8; 1000]);
+
+        // four shares carry the payload of another input under their own header, so
+        // together they outvote the two verified shares
+        for i in 0..4 {
+            let path = tmp.path(&format!("shares/input.bin.00{}.share", i));
+            let other = tmp.path(&format!("other/input.bin.00{}.share", i));
+            let mut bytes = fs::read(&path).unwrap();
+            bytes[HEADER_LEN..].copy_from_slice(&fs::read(other).unwrap()[HEADER_LEN..]);
+            fs::write(&path, bytes).unwrap();
+        }
+
+        let status = decode(&args(&["-o", &tmp.path("out.bin"), &tmp.path("shares")])).unwrap();
+        assert_eq!(status, Status::Recovered);
+        assert_eq!(fs::read(tmp.path("out.bin")).unwrap(), data);
+    }
+
+    #[test]
+    fn test_unrecoverable_and_invalid_input() {
+        let tmp = TempDir::new("unrecoverable");
+        encode_sample(&tmp);
+        for i in 0..5 {
+            fs::remove_file(tmp.path(&format!("shares/input.bin.00{}.share", i))).unwrap();
+        }
+
+        let err = decode(&args(&["-o", &tmp.path("out.bin"), &tmp.path("shares")])).unwrap_err();
+        assert_eq!(err.status, Status::Unrecoverable);
+        assert!(!Path::new(&tmp.path("out.bin")).exists());
+
+        let err = encode(&args(&[
+            "-k",
+            "3",
+            "-n",
+            "7",
+            "-o",
+            &tmp.path("shares"),
+            &tmp.path("input.bin"),
+        ]))
+        .unwrap_err();
+        assert_eq!(err.status, Status::InvalidInput);
+        let err = encode(&args(&["--required", "3", &tmp.path("input.bin")])).unwrap_err();
+        assert_eq!(err.status, Status::InvalidInput);
+        let err = encode(&args(&["-k", "3", "-n", "2", &tmp.path("input.bin")])).unwrap_err();
+        assert_eq!(err.status, Status::InvalidInput);
+        let err = decode(&args(&["-o", &tmp.path("out.bin"), &tmp.path("input.bin")])).unwrap_err();
+        assert_eq!(err.status, Status::InvalidInput);
+        assert!(!Path::new(&tmp.path("out.bin.partial")).exists());
+    }
+
+    #[test]
+    fn test_corrupt_is_reproducible_and_recoverable() {
+        let mut images = Vec::new();
+        for run in ["corrupt-a", "corrupt-b"] {
+            let tmp = TempDir::new(run);
+            let data = encode_sample(&tmp);
+            let status = corrupt(&args(&[
+                "--seed",
+                "1234",
+                "--delete",
+                "1",
+                "--flip",
+                "3",
+                "--burst",
+                "1",
+                &tmp.path("shares"),
+            ]))
+            .unwrap();
+            assert_eq!(status, Status::Ok);
+
+            let status = decode(&args(&["-o", &tmp.path("out.bin"), &tmp.path("shares")])).unwrap();
+            assert_eq!(status, Status::Recovered);
+            assert_eq!(fs::read(tmp.path("out.bin")).unwrap(), data);
+
+            let mut image = Vec::new();
+            for number in 0..7 {
+                let path = tmp.path(&format!("shares/input.bin.00{}.share", number));
+                image.push(fs::read(path).ok());
+            }
+            images.push(image);
+        }
+        assert_eq!(images[0], images[1]);
+        assert_eq!(images[0].iter().filter(|f| f.is_none()).count(), 1);
+
+        let tmp = TempDir::new("corrupt-swap");
+        encode_sample(&tmp);
+        corrupt(&args(&["--seed", "9", "--swap", "1", &tmp.path("shares")])).unwrap();
+        assert_eq!(
+            verify(&args(&[&tmp.path("shares")])).unwrap(),
+            Status::Recovered
+        );
+
+        let err = corrupt(&args(&[&tmp.path("shares")])).unwrap_err();
+        assert_eq!(err.status, Status::InvalidInput);
+        let err = corrupt(&args(&["--delete", "8", &tmp.path("shares")])).unwrap_err();
+        assert_eq!(err.status, Status::InvalidInput);
+    }
+
+    #[test]
+    fn test_archive_restore() {
+        let tmp = TempDir::new("archive");
+        fs::create_dir_all(tmp.path("tree/docs")).unwrap();
+        fs::write(tmp.path("tree/readme"), b"hello").unwrap();
+        fs::write(tmp.path("tree/docs/big"), vec![3u8; 10_000]).unwrap();
+
+        let (tree, out, inside) = (tmp.path("tree"), tmp.path("out"), tmp.path("tree/out"));
+        let argv = ["-k", "2", "-n", "4", "--stripe-size", "512", &tree, &out];
+        assert_eq!(archive(&args(&argv)).unwrap(), Status::Ok);
+        let err = archive(&args(&argv)).unwrap_err();
+        assert_eq!(err.status, Status::InvalidInput);
+
+        fs::remove_dir_all(tmp.path("out/002")).unwrap();
+        let status = restore(&args(&["-o", &tmp.path("restored"), &tmp.path("out")])).unwrap();
+        assert_eq!(status, Status::Recovered);
+        assert_eq!(fs::read(tmp.path("restored/readme")).unwrap(), b"hello");
+        assert_eq!(
+            fs::read(tmp.path("restored/docs/big")).unwrap(),
+            [3u8; 10_000]
+        );
+
+        let err = restore(&args(&["-o", &tmp.path("restored"), &tmp.path("out")])).unwrap_err();
+        assert_eq!(err.status, Status::InvalidInput);
+        fs::remove_dir_all(tmp.path("out/000")).unwrap();
+        fs::remove_dir_all(tmp.path("out/003")).unwrap();
+        let err = restore(&args(&["-o", &tmp.path("again"), &tmp.path("out")])).unwrap_err();
+        assert_eq!(err.status, Status::Unrecoverable);
+
+        let argv = ["-k", "2", "-n", "4", &tree, &inside];
+        let err = archive(&args(&argv)).unwrap_err();
+        assert_eq!(err.status, Status::InvalidInput);
+    }
+
+    #[test]
+    fn test_json_report() {
+        let tmp = TempDir::new("json");
+        encode_sample(&tmp);
+        fs::remove_file(tmp.path("shares/input.bin.002.share")).unwrap();
+        // share 5 damaged before its checksum was computed, so it has to be corrected
+        let corrupt = tmp.path("shares/input.bin.005.share");
+        let file = ShareFile::from_bytes(&fs::read(&corrupt).unwrap()).unwrap();
+        let header = file.header;
+        let mut share = file.into_share();
+        share.data[10] ^= 0x01;
+        share.data[20] ^= 0x02;
+        let fec = header.fec().unwrap();
+        let file = ShareFile::new(&fec, header.stripe_size, header.original_len, share);
+        fs::write(&corrupt, file.to_bytes()).unwrap();
+
+        let mut out = Report::new("verify", true);
+        let result = super::verify(&args(&[&tmp.path("shares")]), &mut out);
+        out.finish(&result, std::time::Duration::from_millis(5));
+        let json: serde_json::Value = serde_json::from_str(&out.to_json()).unwrap();
+
+        assert_eq!(json["command"], "verify");
+        assert_eq!(json["status"], "recovered");
+        assert_eq!(json["exit_code"], 1);
+        assert_eq!((json["k"].as_u64(), json["n"].as_u64()), (Some(3), Some(7)));
+        assert_eq!(json["bytes"], 5000);
+        let shares = json["shares"].as_array().unwrap();
+        let statuses: Vec<&str> = shares
+            .iter()
+            .map(|s| s["status"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            statuses,
+            ["ok", "ok", "missing", "ok", "ok", "corrected", "ok"]
+        );
+        assert_eq!(shares[5]["corrected_bytes"], 2);
+        assert!(json.get("error").is_none());
+
+        let mut out = Report::new("decode", true);
+        let result = super::decode(&args(&["-o", &tmp.path("out.bin")]), &mut out);
+        out.finish(&result, std::time::Duration::ZERO);
+        let json: serde_json::Value = serde_json::from_str(&out.to_json()).unwrap();
+        assert_eq!(json["status"], "invalid_input");
+        assert_eq!(json["error"]["kind"], "invalid_input");
+    }
+
+    #[test]
+    fn test_stream_round_trip() {
+        let tmp = TempDir::new("stream");
+        let data = encode_sample(&tmp);
+        let stream = tmp.path("input.rss");
+        let status = encode(&args(&[
+            "-k",
+            "3",
+            "-n",
+            "7",
+            "--stripe-size",
+            "999",
+            "--stream",
+            "-o",
+            &stream,
+            &tmp.path("input.bin"),
+        ]))
+        .unwrap();
+        assert_eq!(status, Status::Ok);
+
+        let status = decode(&args(&["--stream", "-o", &tmp.path("out.bin"), &stream])).unwrap();
+        assert_eq!(status, Status::Ok);
+        assert_eq!(fs::read(tmp.path("out.bin")).unwrap(), data);
+
+        // damage a chunk of the first stripe, then cut the stream short
+        let mut bytes = fs::read(&stream).unwrap();
+        bytes[100] ^= 0xFF;
+        fs::write(&stream, &bytes).unwrap();
+        let out = tmp.path("out.bin");
+        let err = decode(&args(&["--stream", "-o", &out, &stream])).unwrap_err();
+        assert_eq!(err.status, Status::InvalidInput);
+        let status = decode(&args(&["--stream", "--force", "-o", &out, &stream])).unwrap();
+        assert_eq!(status, Status::Recovered);
+        assert_eq!(fs::read(tmp.path("out.bin")).unwrap(), data);
+
+        fs::write(&stream, &bytes[..bytes.len() - 20]).unwrap();
+        let out = tmp.path("cut.bin");
+        let err = decode(&args(&["--stream", "-o", &out, &stream])).unwrap_err();
+        assert_eq!(err.status, Status::Unrecoverable);
+        assert!(!Path::new(&out).exists());
+        assert!(!Path::new(&format!("{}.partial", out)).exists());
+    }
+
+    #[test]
+    fn test_scrub() {
+        use reed_solomon_rs::store::share_store::{put_object, ShareStore};
+
+        let tmp = TempDir::new("scrub");
+        let (disk0, disk1, cursor) = (tmp.path("disk0"), tmp.path("disk1"), tmp.path("cursor"));
+        let store = FsStore::new(vec![PathBuf::from(&disk0), PathBuf::from(&disk1)]).unwrap();
+        let fec = FEC::new(2, 4).unwrap();
+        for id in ["a", "b", "c"] {
+            put_object(&store, &fec, id, b"scrubbed in the background").unwrap();
+        }
+        assert_eq!(scrub(&args(&[&disk0, &disk1])).unwrap(), Status::Ok);
+
+        fs::remove_dir_all(&disk1).unwrap();
+        let dry = ["--dry-run", &disk0, &disk1];
+        assert_eq!(scrub(&args(&dry)).unwrap(), Status::Recovered);
+        assert_eq!(store.list("a").unwrap(), vec![0, 2]);
+
+        let argv = ["--max-objects", "2", "--cursor", &cursor, &disk0, &disk1];
+        let mut out = Report::new("scrub", true);
+        assert_eq!(
+            super::scrub(&args(&argv), &mut out).unwrap(),
+            Status::Recovered
+        );
+        let report = out.scrub.unwrap();
+        assert_eq!(
+            (report.repaired, report.next_cursor.as_deref()),
+            (2, Some("b"))
+        );
+        assert_eq!(store.list("c").unwrap(), vec![0, 2]);
+        assert_eq!(scrub(&args(&argv)).unwrap(), Status::Recovered);
+        assert_eq!(store.list("c").unwrap(), vec![0, 1, 2, 3]);
+        assert!(!Path::new(&cursor).exists());
+
+        store.delete("c", 0).unwrap();
+        store.delete("c", 1).unwrap();
+        store.delete("c", 2).unwrap();
+        assert_eq!(
+            scrub(&args(&[&disk0, &disk1])).unwrap(),
+            Status::Unrecoverable
+        );
+        assert!(scrub(&args(&["--max-objects", "0", &disk0])).is_err());
+    }
+}
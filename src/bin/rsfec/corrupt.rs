@@ -0,0 +1,307 @@
+use crate::status::{CliError, CliResult};
+use reed_solomon_rs::format::share_file::{ShareHeader, HEADER_LEN};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// `Rng` is a SplitMix64 generator. Damage must be reproducible from a seed on every
+/// platform and release, so the tool carries its own generator.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// `below()` returns a number in `0..bound`. `bound` must not be 0.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        ((self.next_u64() as u128 * bound as u128) >> 64) as u64
+    }
+
+    /// `take()` removes and returns a random element of `items`.
+    fn take<T>(&mut self, items: &mut Vec<T>) -> T {
+        let i = self.below(items.len() as u64) as usize;
+        items.swap_remove(i)
+    }
+}
+
+/// `Plan` says how much damage `plan_damage()` should do.
+#[derive(Debug, Clone, Default)]
+pub struct Plan {
+    /// Share files to delete.
+    pub delete: usize,
+    /// Share files to cut short at a random length.
+    pub truncate: usize,
+    /// Pairs of share files whose share numbers are exchanged.
+    pub swap: usize,
+    /// Single payload bytes to flip.
+    pub flip: usize,
+    /// Payload ranges to overwrite with random bytes.
+    pub burst: usize,
+    /// Length of every burst, shortened to the payload if that is smaller.
+    pub burst_len: u64,
+}
+
+/// `Damage` is one change made to a share file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Damage {
+    Deleted {
+        path: PathBuf,
+    },
+    Truncated {
+        path: PathBuf,
+        len: u64,
+    },
+    /// The headers of both files now carry each other's share number.
+    Swapped {
+        first: PathBuf,
+        second: PathBuf,
+        numbers: (usize, usize),
+    },
+    Flipped {
+        path: PathBuf,
+        offset: u64,
+        mask: u8,
+    },
+    Burst {
+        path: PathBuf,
+        offset: u64,
+        data: Vec<u8>,
+    },
+}
+
+impl fmt::Display for Damage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Damage::Deleted { path } => write!(f, "deleted {}", path.display()),
+            Damage::Truncated { path, len } => {
+                write!(f, "truncated {} to {} bytes", path.display(), len)
+            }
+            Damage::Swapped {
+                first,
+                second,
+                numbers,
+            } => write!(
+                f,
+                "swapped share numbers of {} ({}) and {} ({})",
+                first.display(),
+                numbers.0,
+                second.display(),
+                numbers.1
+            ),
+            Damage::Flipped { path, offset, mask } => write!(
+                f,
+                "flipped byte {} of {} (xor {:#04x})",
+                offset,
+                path.display(),
+                mask
+            ),
+            Damage::Burst { path, offset, data } => write!(
+                f,
+                "overwrote bytes {}..{} of {}",
+                offset,
+                offset + data.len() as u64,
+                path.display()
+            ),
+        }
+    }
+}
+
+/// `Target` is a file `plan_damage()` may pick.
+struct Target {
+    path: PathBuf,
+    len: u64,
+    header: Option<ShareHeader>,
+}
+
+fn read_target(path: &PathBuf) -> CliResult<Target> {
+    let mut file = File::open(path).map_err(CliError::io(path.display()))?;
+    let len = file.metadata().map_err(CliError::io(path.display()))?.len();
+    let mut bytes = [0u8; HEADER_LEN];
+    let header = match file.read_exact(&mut bytes) {
+        Ok(()) => ShareHeader::from_bytes(&bytes).ok(),
+        Err(_) => None,
+    };
+    Ok(Target {
+        path: path.clone(),
+        len,
+        header,
+    })
+}
+
+/// `plan_damage()` picks the damage to do to the files at `paths` without touching
+/// them. The same paths, plan and seed always give the same damage.
+///
+/// Every file is deleted, truncated or swapped at most once, and flips and bursts only
+/// hit the payload of the files that are left, so that every change shows up in the
+/// report of a later `verify`.
+pub fn plan_damage(paths: &[PathBuf], plan: &Plan, rng: &mut Rng) -> CliResult<Vec<Damage>> {
+    let targets = paths
+        .iter()
+        .map(read_target)
+        .collect::<CliResult<Vec<_>>>()?;
+    let (mut swappable, mut others): (Vec<Target>, Vec<Target>) =
+        targets.into_iter().partition(|t| t.header.is_some());
+    if swappable.len() < 2 * plan.swap {
+        return Err(CliError::invalid(format!(
+            "swapping {} pairs needs {} share files, found {}",
+            plan.swap,
+            2 * plan.swap,
+            swappable.len()
+        )));
+    }
+
+    let mut damage = Vec::new();
+    let mut swapped = Vec::new();
+    for _ in 0..plan.swap {
+        let first = rng.take(&mut swappable);
+        let second = rng.take(&mut swappable);
+        damage.push(Damage::Swapped {
+            first: first.path.clone(),
+            second: second.path.clone(),
+            numbers: (first.header.unwrap().number, second.header.unwrap().number),
+        });
+        swapped.push(first);
+        swapped.push(second);
+    }
+
+    others.extend(swappable);
+    others.sort_by(|a, b| a.path.cmp(&b.path));
+    if others.len() < plan.delete + plan.truncate {
+        return Err(CliError::invalid(format!(
+            "cannot delete {} and truncate {} of the {} files left",
+            plan.delete,
+            plan.truncate,
+            others.len()
+        )));
+    }
+    for _ in 0..plan.delete {
+        let path = rng.take(&mut others).path;
+        damage.push(Damage::Deleted { path });
+    }
+    for _ in 0..plan.truncate {
+        let target = rng.take(&mut others);
+        let len = rng.below(target.len.max(1));
+        damage.push(Damage::Truncated {
+            path: target.path,
+            len,
+        });
+    }
+
+    let mut payloads: Vec<Target> = others
+        .into_iter()
+        .chain(swapped)
+        .filter(|t| t.len > HEADER_LEN as u64)
+        .collect();
+    payloads.sort_by(|a, b| a.path.cmp(&b.path));
+    if payloads.is_empty() && plan.flip + plan.burst > 0 {
+        return Err(CliError::invalid("no share payloads left to corrupt"));
+    }
+    for _ in 0..plan.flip {
+        let target = &payloads[rng.below(payloads.len() as u64) as usize];
+        let offset = HEADER_LEN as u64 + rng.below(target.len - HEADER_LEN as u64);
+        let mask = 1 + rng.below(255) as u8;
+        damage.push(Damage::Flipped {
+            path: target.path.clone(),
+            offset,
+            mask,
+        });
+    }
+    for _ in 0..plan.burst {
+        let target = &payloads[rng.below(payloads.len() as u64) as usize];
+        let payload = target.len - HEADER_LEN as u64;
+        let len = plan.burst_len.clamp(1, payload);
+        let offset = HEADER_LEN as u64 + rng.below(payload - len + 1);
+        let data = (0..len).map(|_| rng.next_u64() as u8).collect();
+        damage.push(Damage::Burst {
+            path: target.path.clone(),
+            offset,
+            data,
+        });
+    }
+    Ok(damage)
+}
+
+/// `write_at()` overwrites the bytes of the file at `path` starting at `offset`.
+fn write_at(path: &Path, offset: u64, data: &[u8]) -> CliResult<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(path)
+        .map_err(CliError::io(path.display()))?;
+    file.seek(SeekFrom::Start(offset))
+        .and_then(|_| file.write_all(data))
+        .map_err(CliError::io(path.display()))
+}
+
+/// `swap_number()` rewrites the header of the share file at `path` to carry `number`.
+fn swap_number(path: &Path, number: usize) -> CliResult<()> {
+    let mut bytes = [0u8; HEADER_LEN];
+    File::open(path)
+        .and_then(|mut f| f.read_exact(&mut bytes))
+        .map_err(CliError::io(path.display()))?;
+    let mut header = ShareHeader::from_bytes(&bytes)
+        .map_err(|e| CliError::invalid(format!("{}: {}", path.display(), e)))?;
+    header.number = number;
+    write_at(path, 0, &header.to_bytes())
+}
+
+/// `apply_damage()` makes the change described by `damage`.
+pub fn apply_damage(damage: &Damage) -> CliResult<()> {
+    match damage {
+        Damage::Deleted { path } => fs::remove_file(path).map_err(CliError::io(path.display())),
+        Damage::Truncated { path, len } => OpenOptions::new()
+            .write(true)
+            .open(path)
+            .and_then(|f| f.set_len(*len))
+            .map_err(CliError::io(path.display())),
+        Damage::Swapped {
+            first,
+            second,
+            numbers,
+        } => {
+            swap_number(first, numbers.1)?;
+            swap_number(second, numbers.0)
+        }
+        Damage::Flipped { path, offset, mask } => {
+            let mut byte = [0u8; 1];
+            File::open(path)
+                .and_then(|mut f| {
+                    f.seek(SeekFrom::Start(*offset))?;
+                    f.read_exact(&mut byte)
+                })
+                .map_err(CliError::io(path.display()))?;
+            write_at(path, *offset, &[byte[0] ^ mask])
+        }
+        Damage::Burst { path, offset, data } => write_at(path, *offset, data),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rng_is_reproducible() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        let first: Vec<u64> = (0..16).map(|_| a.next_u64()).collect();
+        let second: Vec<u64> = (0..16).map(|_| b.next_u64()).collect();
+        assert_eq!(first, second);
+        // reference value of SplitMix64 seeded with 0
+        assert_eq!(Rng::new(0).next_u64(), 0xE220_A839_7B1D_CDAF);
+        assert_ne!(Rng::new(43).next_u64(), first[0]);
+
+        let mut rng = Rng::new(7);
+        assert!((0..1000).all(|_| rng.below(3) < 3));
+    }
+}
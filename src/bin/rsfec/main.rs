@@ -0,0 +1,140 @@
+mod args;
+mod commands;
+mod corrupt;
+mod report;
+mod shares;
+mod status;
+
+use report::Report;
+use status::{CliError, CliResult, Status};
+use std::env;
+use std::process;
+use std::time::Instant;
+
+const USAGE: &str = "\
+usage: rsfec [--json] <command> [options] <arguments>
+
+commands:
+  encode  -k/--required K -n/--total N [--stripe-size BYTES]
+          [--matrix vandermonde|cauchy] [-o/--output DIR] [--name STEM]
+          [--force] FILE
+          Erasure-code FILE into N share files, any K of which recover it.
+          FILE may be - to read stdin; the shares are then named after --name
+          (default stdin). Existing share files are only overwritten with --force.
+  encode  --stream -k/--required K -n/--total N [--stripe-size BYTES]
+          [--matrix vandermonde|cauchy] [-o/--output FILE] [--force] FILE
+          Write all N shares interleaved stripe by stripe into a single stream,
+          to stdout unless -o is given.
+  decode  -o/--output FILE [--force] SHARES...
+          Recover the original data from share files or directories.
+  decode  --stream -o/--output FILE [--force] STREAM
+          Recover the original data from a multiplexed share stream, or stdin if
+          STREAM is -.
+          Both forms decode stripe by stripe. With -o - the data is written to
+          stdout as it is recovered; a file only appears once decoding succeeded,
+          and an existing one is only overwritten with --force.
+  repair  SHARES...
+          Rewrite missing and damaged share files from the intact ones.
+  verify  SHARES...
+          Check that all share files are present and intact.
+  inspect SHARES...
+          Print the header of each share file.
+  archive -k/--required K -n/--total N [--stripe-size BYTES]
+          [--matrix vandermonde|cauchy] [--force] DIR OUTPUT...
+          Erasure-code the tree at DIR into N output directories, given one by
+          one or as a single directory that receives 000, 001, ... Each gets a
+          share of the packed file contents and of the manifest of paths, sizes,
+          permissions and stripe locations.
+  restore -o/--output DIR [--force] OUTPUT...
+          Restore a tree from any K of its output directories, or directories
+          holding them, into DIR, which must be empty unless --force is given.
+  corrupt [--seed N] [--delete N] [--truncate N] [--swap N] [--flip N]
+          [--burst N [--burst-len BYTES]] SHARES...
+          Damage share files for recovery drills: delete or truncate N files,
+          swap the share numbers of N pairs, flip N payload bytes or overwrite
+          N payload ranges. The same seed and files give the same damage; the
+          seed is printed first so a run can be replayed.
+  scrub   [--dry-run] [--rate BYTES_PER_SEC] [--cursor FILE]
+          [--max-objects N] DIR...
+          Check every object of a share store kept under DIR... (one directory
+          per disk) and rewrite its missing, unreadable and corrupted shares.
+          --dry-run only reports the damage. --rate caps the bytes read and
+          written per second. With --cursor, a run stopped by --max-objects
+          saves its position to FILE and the next run resumes from there.
+
+Diagnostics go to stderr, so stdout only carries data and reports.
+
+--json, accepted anywhere before --, replaces the text output with a single JSON
+object: the command, status and exit_code, k and n, per-share status (ok,
+corrected, missing or invalid) with the bytes corrected in each, elapsed_ms and,
+on failure, an error with a stable kind (the status names: unrecoverable,
+invalid_input, io). It goes to stderr when stdout carries data.
+
+exit codes:
+  0  everything was intact
+  1  recovered, but shares were missing or had to be corrected
+  2  unrecoverable
+  3  invalid arguments or input files
+  4  i/o error
+";
+
+fn run(command: &str, args: &[String], out: &mut Report) -> CliResult<Status> {
+    match command {
+        "encode" => commands::encode(args, out),
+        "decode" => commands::decode(args, out),
+        "repair" => commands::repair(args, out),
+        "verify" => commands::verify(args, out),
+        "inspect" => commands::inspect(args, out),
+        "corrupt" => commands::corrupt(args, out),
+        "archive" => commands::archive(args, out),
+        "restore" => commands::restore(args, out),
+        "scrub" => commands::scrub(args, out),
+        "help" | "-h" | "--help" => {
+            out.line(USAGE.trim_end());
+            Ok(Status::Ok)
+        }
+        "" => Err(CliError::usage("missing command")),
+        _ => Err(CliError::usage(format!("unknown command {:?}", command))),
+    }
+}
+
+/// `take_json_flag()` removes `--json` from the arguments before `--`, returning whether
+/// it was given.
+fn take_json_flag(args: &mut Vec<String>) -> bool {
+    let end = args.iter().position(|a| a == "--").unwrap_or(args.len());
+    let before = args.len();
+    let mut i = 0;
+    args.retain(|a| {
+        i += 1;
+        i > end || a != "--json"
+    });
+    args.len() != before
+}
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let json = take_json_flag(&mut args);
+    let (command, rest) = match args.split_first() {
+        Some((command, rest)) => (command.as_str(), rest),
+        None => ("", &[][..]),
+    };
+
+    let start = Instant::now();
+    let mut out = Report::new(command, json);
+    let result = run(command, rest, &mut out);
+    out.finish(&result, start.elapsed());
+
+    if json {
+        if out.stdout_is_data {
+            eprintln!("{}", out.to_json());
+        } else {
+            println!("{}", out.to_json());
+        }
+    } else if let Err(e) = &result {
+        eprintln!("rsfec: {}", e);
+        if e.show_usage {
+            eprint!("\n{}", USAGE);
+        }
+    }
+    process::exit(out.exit_code);
+}
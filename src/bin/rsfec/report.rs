@@ -0,0 +1,247 @@
+use crate::status::{CliError, Status};
+use reed_solomon_rs::format::share_file::ShareHeader;
+use reed_solomon_rs::store::scrub::{ObjectStatus, ScrubSummary};
+use serde::Serialize;
+use std::fmt;
+use std::time::Duration;
+
+/// `ShareStatus` is what became of one share number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShareStatus {
+    /// The share was intact.
+    Ok,
+    /// The share was readable, but bytes of it had to be corrected.
+    Corrected,
+    /// No file held the share.
+    Missing,
+    /// The share failed its checksum or could not be parsed.
+    Invalid,
+}
+
+/// `ShareReport` is the JSON entry of one share.
+#[derive(Debug, Clone, Serialize)]
+pub struct ShareReport {
+    /// Share number, absent for files that could not be parsed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub number: Option<usize>,
+    pub status: ShareStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Number of bytes `FEC::correct` changed in the share.
+    pub corrected_bytes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub header: Option<HeaderReport>,
+}
+
+impl ShareReport {
+    pub fn new(number: usize, status: ShareStatus) -> ShareReport {
+        ShareReport {
+            number: Some(number),
+            status,
+            path: None,
+            corrected_bytes: 0,
+            reason: None,
+            header: None,
+        }
+    }
+}
+
+/// `HeaderReport` is the header of a share file, as printed by `inspect`.
+#[derive(Debug, Clone, Serialize)]
+pub struct HeaderReport {
+    pub version: u16,
+    pub matrix: &'static str,
+    pub stripe_size: usize,
+    pub original_len: u64,
+    pub payload_len: u64,
+    pub payload_checksum: u32,
+}
+
+impl HeaderReport {
+    pub fn new(header: &ShareHeader, matrix: &'static str) -> HeaderReport {
+        HeaderReport {
+            version: header.version,
+            matrix,
+            stripe_size: header.stripe_size,
+            original_len: header.original_len,
+            payload_len: header.payload_len,
+            payload_checksum: header.payload_checksum,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StripeFailure {
+    pub stripe: usize,
+    pub reason: String,
+}
+
+/// `ScrubReport` is the outcome of `scrub`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScrubReport {
+    pub dry_run: bool,
+    pub objects: usize,
+    pub healthy: usize,
+    pub repaired: usize,
+    pub damaged: usize,
+    pub unrecoverable: usize,
+    pub failed: usize,
+    pub shares_rewritten: usize,
+    /// Objects that were not healthy.
+    pub reports: Vec<ObjectReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ObjectReport {
+    pub id: String,
+    pub status: &'static str,
+    pub missing: Vec<usize>,
+    pub invalid: Vec<usize>,
+    pub corrected: Vec<usize>,
+    pub rewritten: Vec<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// `object_status_name()` returns the JSON name of `status`.
+pub fn object_status_name(status: ObjectStatus) -> &'static str {
+    match status {
+        ObjectStatus::Healthy => "healthy",
+        ObjectStatus::Repaired => "repaired",
+        ObjectStatus::Damaged => "damaged",
+        ObjectStatus::Unrecoverable => "unrecoverable",
+        ObjectStatus::Failed => "failed",
+    }
+}
+
+impl ScrubReport {
+    pub fn new(summary: &ScrubSummary) -> ScrubReport {
+        ScrubReport {
+            dry_run: summary.dry_run,
+            objects: summary.objects,
+            healthy: summary.healthy,
+            repaired: summary.repaired,
+            damaged: summary.damaged,
+            unrecoverable: summary.unrecoverable,
+            failed: summary.failed,
+            shares_rewritten: summary.shares_rewritten,
+            reports: summary
+                .reports
+                .iter()
+                .map(|r| ObjectReport {
+                    id: r.id.clone(),
+                    status: object_status_name(r.status),
+                    missing: r.missing.clone(),
+                    invalid: r.invalid.clone(),
+                    corrected: r.corrected.clone(),
+                    rewritten: r.rewritten.clone(),
+                    error: r.error.clone(),
+                })
+                .collect(),
+            next_cursor: summary.next_cursor.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorReport {
+    /// One of the `Status::name()` strings.
+    pub kind: &'static str,
+    pub message: String,
+}
+
+/// `Report` is the outcome of a command. Commands print text through it as they go,
+/// and fill in its fields for `--json`, which prints the report as a single JSON object
+/// on stdout when the command ends and silences the text.
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    #[serde(skip)]
+    pub json: bool,
+    /// Whether stdout carries encoded or recovered data, in which case the JSON report
+    /// goes to stderr instead.
+    #[serde(skip)]
+    pub stdout_is_data: bool,
+    pub command: String,
+    pub status: &'static str,
+    pub exit_code: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub k: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<usize>,
+    /// Number of bytes encoded or recovered.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+    /// Number of files archived or restored.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub files: Option<usize>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub shares: Vec<ShareReport>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub failed_stripes: Vec<StripeFailure>,
+    /// Chunks of a multiplexed stream that failed their checksum and were rebuilt.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub damaged_chunks: Option<u64>,
+    /// Numbers of the shares `repair` rewrote.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub repaired: Vec<usize>,
+    /// Seed and changes of `corrupt`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub damage: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scrub: Option<ScrubReport>,
+    pub elapsed_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorReport>,
+}
+
+impl Report {
+    pub fn new(command: &str, json: bool) -> Report {
+        Report {
+            json,
+            command: command.to_string(),
+            ..Report::default()
+        }
+    }
+
+    /// `line()` prints a line of the command's result to stdout.
+    pub fn line(&self, line: impl fmt::Display) {
+        if !self.json {
+            println!("{}", line);
+        }
+    }
+
+    /// `note()` prints a diagnostic to stderr.
+    pub fn note(&self, note: impl fmt::Display) {
+        if !self.json {
+            eprintln!("{}", note);
+        }
+    }
+
+    /// `finish()` records how the command ended.
+    pub fn finish(&mut self, result: &Result<Status, CliError>, elapsed: Duration) {
+        let status = match result {
+            Ok(status) => *status,
+            Err(e) => {
+                self.error = Some(ErrorReport {
+                    kind: e.status.name(),
+                    message: e.message.clone(),
+                });
+                e.status
+            }
+        };
+        self.status = status.name();
+        self.exit_code = status.code();
+        self.elapsed_ms = elapsed.as_secs_f64() * 1000.0;
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("a report always serializes")
+    }
+}
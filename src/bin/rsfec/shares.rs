@@ -0,0 +1,359 @@
+use crate::status::{CliError, CliResult, Status};
+use reed_solomon_rs::fec::fec::{Share, FEC};
+use reed_solomon_rs::fec::striped::{Stripe, StripedCodec};
+use reed_solomon_rs::format::share_file::{ShareHeader, HEADER_LEN};
+use reed_solomon_rs::hash::crc32c::Crc32c;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// Extension of the share files written by `rsfec encode`.
+pub const SHARE_EXT: &str = "share";
+
+/// `share_path()` returns the path of share `number` of the input named `stem`:
+/// `<dir>/<stem>.<number>.share`, the number padded to three digits.
+pub fn share_path(dir: &Path, stem: &str, number: usize) -> PathBuf {
+    dir.join(format!("{}.{:03}.{}", stem, number, SHARE_EXT))
+}
+
+/// `split_share_name()` is the inverse of `share_path()`: it returns the stem and share
+/// number encoded in a file name, if it follows that pattern.
+pub fn split_share_name(path: &Path) -> Option<(String, usize)> {
+    let name = path.file_name()?.to_str()?;
+    let rest = name.strip_suffix(SHARE_EXT)?.strip_suffix('.')?;
+    let (stem, number) = rest.rsplit_once('.')?;
+    Some((stem.to_string(), number.parse().ok()?))
+}
+
+/// `expand_paths()` replaces every directory in `args` with the share files it contains.
+pub fn expand_paths(args: &[String]) -> CliResult<Vec<PathBuf>> {
+    if args.is_empty() {
+        return Err(CliError::usage("no share files given"));
+    }
+    let mut paths = Vec::new();
+    for arg in args {
+        let path = PathBuf::from(arg);
+        if path.is_dir() {
+            let mut entries: Vec<PathBuf> = fs::read_dir(&path)
+                .map_err(CliError::io(path.display()))?
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.is_file() && p.extension().is_some_and(|e| e == SHARE_EXT))
+                .collect();
+            entries.sort();
+            paths.extend(entries);
+        } else {
+            paths.push(path);
+        }
+    }
+    Ok(paths)
+}
+
+/// `LoadedShare` is a share file whose header was parsed and whose payload was
+/// checked against its checksum. The payload itself is read again, stripe by stripe,
+/// by `recover()`.
+#[derive(Debug)]
+pub struct LoadedShare {
+    pub path: PathBuf,
+    pub header: ShareHeader,
+    /// Whether the payload matches the checksum in the header.
+    pub checksum_ok: bool,
+}
+
+/// `Rejected` is a file that could not be used as a share, with the reason.
+#[derive(Debug)]
+pub struct Rejected {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// `load_share()` parses the header of a share file and checksums its payload. A
+/// checksum mismatch is recorded but not an error, since the damage may be correctable.
+pub fn load_share(path: &Path) -> Result<LoadedShare, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let file_len = file.metadata().map_err(|e| e.to_string())?.len();
+    let mut reader = BufReader::new(file);
+
+    let mut bytes = [0u8; HEADER_LEN];
+    reader
+        .read_exact(&mut bytes)
+        .map_err(|_| "not a share file (too short)".to_string())?;
+    let header = ShareHeader::from_bytes(&bytes).map_err(|e| e.to_string())?;
+    let expected = HEADER_LEN as u64 + header.payload_len;
+    if file_len != expected {
+        return Err(format!(
+            "file is {} bytes, the header describes {}",
+            file_len, expected
+        ));
+    }
+
+    let mut crc = Crc32c::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let read = reader.read(&mut buf).map_err(|e| e.to_string())?;
+        if read == 0 {
+            break;
+        }
+        crc.update(&buf[..read]);
+    }
+    Ok(LoadedShare {
+        path: path.to_path_buf(),
+        checksum_ok: crc.finish() == header.payload_checksum,
+        header,
+    })
+}
+
+/// `ShareSet` is the set of shares of one encoded input.
+#[derive(Debug)]
+pub struct ShareSet {
+    /// Header of the encoding, as found in the shares.
+    pub header: ShareHeader,
+    /// At most one share per number, ordered by number.
+    pub shares: Vec<LoadedShare>,
+    pub rejected: Vec<Rejected>,
+}
+
+/// `load_set()` loads the share files at `paths`. Files that cannot be read or parsed
+/// are rejected. If the shares belong to several encodings, the one with the most
+/// shares is kept and the others are rejected; of several shares with the same number,
+/// one whose checksum matches is preferred.
+pub fn load_set(paths: &[PathBuf]) -> CliResult<ShareSet> {
+    let mut loaded = Vec::new();
+    let mut rejected = Vec::new();
+    for path in paths {
+        match load_share(path) {
+            Ok(share) => loaded.push(share),
+            Err(reason) => rejected.push(Rejected {
+                path: path.clone(),
+                reason,
+            }),
+        }
+    }
+
+    let header = loaded
+        .iter()
+        .max_by_key(|s| {
+            let count = loaded
+                .iter()
+                .filter(|o| o.header.is_compatible(&s.header))
+                .count();
+            // prefer the first share among equally common encodings
+            (count, std::cmp::Reverse(s.path.clone()))
+        })
+        .map(|s| s.header)
+        .ok_or_else(|| match rejected.first() {
+            Some(r) => CliError::invalid(format!(
+                "no valid share files ({}: {})",
+                r.path.display(),
+                r.reason
+            )),
+            None => CliError::invalid("no share files found"),
+        })?;
+
+    let mut by_number: Vec<Option<LoadedShare>> = (0..header.n).map(|_| None).collect();
+    for share in loaded {
+        if !share.header.is_compatible(&header) {
+            rejected.push(Rejected {
+                path: share.path,
+                reason: "belongs to a different encoding".to_string(),
+            });
+            continue;
+        }
+        let slot = &mut by_number[share.header.number];
+        let replace = match slot {
+            None => true,
+            Some(existing) => !existing.checksum_ok && share.checksum_ok,
+        };
+        let duplicate = if replace {
+            slot.replace(share)
+        } else {
+            Some(share)
+        };
+        if let Some(duplicate) = duplicate {
+            rejected.push(Rejected {
+                path: duplicate.path,
+                reason: format!("duplicate of share {}", duplicate.header.number),
+            });
+        }
+    }
+
+    Ok(ShareSet {
+        header,
+        shares: by_number.into_iter().flatten().collect(),
+        rejected,
+    })
+}
+
+/// `stripes()` returns the stripes of the input described by `header`. An input that
+/// was not striped is a single stripe.
+pub fn stripes(header: &ShareHeader) -> CliResult<Vec<Stripe>> {
+    let len = header.original_len as usize;
+    if header.stripe_size == 0 {
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+        return Ok(vec![Stripe {
+            index: 0,
+            offset: 0,
+            len,
+            share_len: len.div_ceil(header.k),
+        }]);
+    }
+    let codec = StripedCodec::new(fec(header)?, header.stripe_size)
+        .map_err(|e| CliError::invalid(e.to_string()))?;
+    Ok(codec.layout(len).stripes)
+}
+
+pub fn fec(header: &ShareHeader) -> CliResult<FEC> {
+    header.fec().map_err(|e| CliError::invalid(e.to_string()))
+}
+
+impl ShareSet {
+    /// Numbers of the shares that were not found.
+    pub fn missing(&self) -> Vec<usize> {
+        let present: BTreeSet<usize> = self.shares.iter().map(|s| s.header.number).collect();
+        (0..self.header.n)
+            .filter(|i| !present.contains(i))
+            .collect()
+    }
+
+    /// Numbers of the shares whose payload does not match its checksum.
+    pub fn checksum_failures(&self) -> Vec<usize> {
+        self.shares
+            .iter()
+            .filter(|s| !s.checksum_ok)
+            .map(|s| s.header.number)
+            .collect()
+    }
+}
+
+/// `Recovery` is the result of decoding a `ShareSet`.
+#[derive(Debug)]
+pub struct Recovery {
+    /// Number of bytes recovered.
+    pub len: u64,
+    /// Numbers of the shares that had to be corrected or were found damaged.
+    pub damaged: BTreeSet<usize>,
+    /// Number of bytes corrected in every share that had to be corrected.
+    pub corrected: BTreeMap<usize, u64>,
+    /// Numbers of the shares that were not found.
+    pub missing: Vec<usize>,
+    /// Stripes that could not be recovered, with the reason.
+    pub failed_stripes: Vec<(usize, String)>,
+}
+
+impl Recovery {
+    pub fn status(&self) -> Status {
+        if !self.failed_stripes.is_empty() {
+            Status::Unrecoverable
+        } else if !self.damaged.is_empty() || !self.missing.is_empty() {
+            Status::Recovered
+        } else {
+            Status::Ok
+        }
+    }
+}
+
+/// `recover()` decodes `set` stripe by stripe and passes every recovered stripe to
+/// `output`, so only one stripe of every share is held in memory at a time.
+///
+/// The shares whose checksum failed are treated as erasures: each stripe is corrected
+/// with the verified shares alone, and the failed ones are only brought in when fewer
+/// than k shares were verified. With `stop_on_failure`
+/// decoding ends at the first stripe that cannot be recovered; otherwise every stripe
+/// is attempted, which is what `verify` wants.
+pub fn recover<F>(set: &ShareSet, stop_on_failure: bool, mut output: F) -> CliResult<Recovery>
+where
+    F: FnMut(&Stripe, &[u8]) -> CliResult<()>,
+{
+    let fec = fec(&set.header)?;
+    let mut recovery = Recovery {
+        len: 0,
+        damaged: set.checksum_failures().into_iter().collect(),
+        corrected: BTreeMap::new(),
+        missing: set.missing(),
+        failed_stripes: Vec::new(),
+    };
+
+    let mut readers = Vec::with_capacity(set.shares.len());
+    for share in &set.shares {
+        let mut file = File::open(&share.path).map_err(CliError::io(share.path.display()))?;
+        file.seek(SeekFrom::Start(HEADER_LEN as u64))
+            .map_err(CliError::io(share.path.display()))?;
+        readers.push(BufReader::new(file));
+    }
+
+    for stripe in stripes(&set.header)? {
+        let mut chunks: Vec<(bool, Share)> = Vec::with_capacity(readers.len());
+        for (share, reader) in set.shares.iter().zip(readers.iter_mut()) {
+            let mut data = vec![0u8; stripe.share_len];
+            // the file length was checked by `load_share()`, so running short means the
+            // file changed underneath us
+            reader
+                .read_exact(&mut data)
+                .map_err(CliError::io(share.path.display()))?;
+            let number = share.header.number;
+            chunks.push((share.checksum_ok, Share { number, data }));
+        }
+
+        let verified = chunks.iter().filter(|(ok, _)| *ok).count();
+        let usable = chunks
+            .into_iter()
+            .filter(|(ok, _)| *ok || verified < fec.k)
+            .map(|(_, s)| s)
+            .collect();
+        let result = decode_stripe(&fec, usable);
+
+        match result {
+            Ok(DecodedStripe {
+                mut data,
+                corrected,
+            }) => {
+                data.truncate(stripe.len);
+                output(&stripe, &data)?;
+                recovery.len += data.len() as u64;
+                for (number, bytes) in corrected {
+                    recovery.damaged.insert(number);
+                    *recovery.corrected.entry(number).or_default() += bytes;
+                }
+            }
+            Err(e) => {
+                recovery.failed_stripes.push((stripe.index, e));
+                if stop_on_failure {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(recovery)
+}
+
+/// `DecodedStripe` is the data of one stripe and, for every share that was corrected,
+/// its number and how many of its bytes changed.
+struct DecodedStripe {
+    data: Vec<u8>,
+    corrected: Vec<(usize, u64)>,
+}
+
+/// `decode_stripe()` corrects and rebuilds one stripe.
+fn decode_stripe(fec: &FEC, shares: Vec<Share>) -> Result<DecodedStripe, String> {
+    let original = shares.clone();
+    let mut shares = shares;
+    fec.correct(&mut shares).map_err(|e| e.to_string())?;
+
+    let corrected = shares
+        .iter()
+        .filter_map(|s| {
+            let o = original.iter().find(|o| o.number == s.number)?;
+            let changed = o.data.iter().zip(&s.data).filter(|(a, b)| a != b).count();
+            (changed > 0).then_some((s.number, changed as u64))
+        })
+        .collect();
+    let piece_len = shares[0].data.len();
+    let mut data = vec![0u8; piece_len * fec.k];
+    fec.rebuild(shares, |s: Share| {
+        data[s.number * piece_len..(s.number + 1) * piece_len].copy_from_slice(&s.data);
+    })
+    .map_err(|e| e.to_string())?;
+    Ok(DecodedStripe { data, corrected })
+}
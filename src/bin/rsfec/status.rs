@@ -0,0 +1,87 @@
+use std::fmt;
+use std::io;
+
+/// `Status` is the outcome of a command, reported as the process exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    /// Everything was intact.
+    Ok,
+    /// The data was recovered, but shares were missing or had to be corrected.
+    Recovered,
+    /// The data could not be recovered.
+    Unrecoverable,
+    /// The arguments or input files were invalid.
+    InvalidInput,
+    /// Reading or writing failed.
+    Io,
+}
+
+impl Status {
+    pub fn code(self) -> i32 {
+        match self {
+            Status::Ok => 0,
+            Status::Recovered => 1,
+            Status::Unrecoverable => 2,
+            Status::InvalidInput => 3,
+            Status::Io => 4,
+        }
+    }
+
+    /// `name()` is the stable name of the status in JSON output.
+    pub fn name(self) -> &'static str {
+        match self {
+            Status::Ok => "ok",
+            Status::Recovered => "recovered",
+            Status::Unrecoverable => "unrecoverable",
+            Status::InvalidInput => "invalid_input",
+            Status::Io => "io",
+        }
+    }
+}
+
+/// `CliError` is an error message together with the exit status it maps to.
+#[derive(Debug)]
+pub struct CliError {
+    pub status: Status,
+    pub message: String,
+    /// Whether the usage text should be printed after the message.
+    pub show_usage: bool,
+}
+
+pub type CliResult<T> = Result<T, CliError>;
+
+impl CliError {
+    pub fn new(status: Status, message: impl Into<String>) -> CliError {
+        CliError {
+            status,
+            message: message.into(),
+            show_usage: false,
+        }
+    }
+
+    pub fn usage(message: impl Into<String>) -> CliError {
+        CliError {
+            show_usage: true,
+            ..CliError::new(Status::InvalidInput, message)
+        }
+    }
+
+    pub fn invalid(message: impl Into<String>) -> CliError {
+        CliError::new(Status::InvalidInput, message)
+    }
+
+    pub fn unrecoverable(message: impl Into<String>) -> CliError {
+        CliError::new(Status::Unrecoverable, message)
+    }
+
+    /// `io()` returns a closure that wraps an I/O error with the path it concerns.
+    pub fn io(path: impl fmt::Display) -> impl FnOnce(io::Error) -> CliError {
+        move |e| CliError::new(Status::Io, format!("{}: {}", path, e))
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
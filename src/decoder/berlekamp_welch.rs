@@ -1,7 +1,7 @@
 use crate::{
     fec::fec::{Share, FEC},
-    galois_field::gf_alg::{GfMat, GfPoly, GfVal, GfVals},
-    math::addmul::addmul,
+    fec::workspace::Decoder,
+    galois_field::gf_alg::{GfMat, GfVal},
 };
 
 // Berlekamp Welch functions for FEC
@@ -44,6 +44,67 @@ impl FEC {
         return Ok(dst);
     }
 
+    /// decode_range() returns `len` bytes of the data passed to the corresponding
+    /// Encode call, starting at `offset`, without decoding the rest of the shares.
+    ///
+    /// Byte `offset` of the data lives in piece `offset / piece_len` at column
+    /// `offset % piece_len`, and every column of the shares can be corrected and rebuilt
+    /// on its own. Only the columns covering the requested range are cut out of the
+    /// shares, corrected and rebuilt, so the cost depends on `len` rather than on the
+    /// share length. Corruption outside of those columns is not detected.
+    pub fn decode_range(
+        &self,
+        shares: Vec<Share>,
+        offset: usize,
+        len: usize,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        if shares.is_empty() {
+            return Err(("Must specify at least one share").into());
+        }
+
+        let piece_len = shares[0].data.len();
+        if shares.iter().any(|s| s.data.len() != piece_len) {
+            return Err(("All shares must have the same length").into());
+        }
+        let end = offset
+            .checked_add(len)
+            .filter(|&end| end <= piece_len * self.k)
+            .ok_or("Range is out of bounds")?;
+        if len == 0 {
+            return Ok(vec![]);
+        }
+
+        let columns = range_columns(offset, end, piece_len);
+        let width: usize = columns.iter().map(|(start, end)| end - start).sum();
+        let sub_shares: Vec<Share> = shares
+            .iter()
+            .map(|s| Share {
+                number: s.number,
+                data: columns
+                    .iter()
+                    .flat_map(|&(start, end)| s.data[start..end].iter().copied())
+                    .collect(),
+            })
+            .collect();
+
+        let data = self.decode(vec![], sub_shares)?;
+
+        let mut out = Vec::with_capacity(len);
+        for pos in offset..end {
+            let (piece, col) = (pos / piece_len, pos % piece_len);
+            let mut index = 0;
+            for &(start, end) in &columns {
+                if col >= start && col < end {
+                    index += col - start;
+                    break;
+                }
+                index += end - start;
+            }
+            out.push(data[piece * width + index]);
+        }
+        Ok(out)
+    }
+
     /// If you don't want the data concatenated for you, you can use correct() and
     /// then rebuild() individually.
     pub fn decode_no_concat<F>(
@@ -62,128 +123,24 @@ impl FEC {
     /// correct() implements the Berlekamp-Welch algorithm for correcting
     /// errors in given FEC encoded data. It will correct the supplied shares,
     /// mutating the underlying byte slices and reordering the shares
+    ///
+    /// The shares must be distinct and of the same length. Only the columns with a
+    /// non-zero syndrome are decoded, see `workspace::Decoder::correct()`.
     pub fn correct(&self, shares: &mut Vec<Share>) -> Result<(), Box<dyn std::error::Error>> {
         if shares.len() < self.k {
             return Err(format!("Must specify at least the number of required shares").into());
         }
-        shares.sort();
-
-        // fast path: check to see if there are no errors by evaluating it with the syndrome matrix
-        let synd = self.syndrome_matrix(&shares)?;
-
-        let mut buf = vec![0u8; shares[0].data.len()];
-        for i in 0..synd.r {
-            for j in 0..buf.len() {
-                buf[j] = 0;
-            }
-            for j in 0..synd.c {
-                addmul(
-                    buf.as_mut_slice(),
-                    shares[j].data.as_slice(),
-                    synd.get(i, j).0,
-                );
-            }
-
-            for j in 0..buf.len() {
-                if buf[j] == 0 {
-                    continue;
-                }
-                let data = self.berlekamp_welch(&shares, j)?;
-                for i in 0..shares.len() {
-                    shares[i].data[j] = data[shares[i].number];
-                }
-            }
-        }
-        Ok(())
+        Decoder::new(self, shares[0].data.len()).correct(self, shares)
     }
 
+    /// berlekamp_welch() decodes column `index` of the shares and returns the corrected
+    /// value of every share number.
     pub fn berlekamp_welch(
         &self,
         shares: &Vec<Share>,
         index: usize,
     ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        let k = self.k;
-        let r = shares.len();
-        let e = (r - k) / 2; // deg of E polynomial
-        let q = e + k; // deg of Q polynomial
-
-        if e <= 0 {
-            return Err(("Not enough shares!").into());
-        }
-
-        let interp_base = GfVal(2);
-        let eval_point = |num: usize| -> GfVal {
-            if num == 0 {
-                GfVal(0)
-            } else {
-                interp_base.pow(num - 1)
-            }
-        };
-        let dim = q + e;
-        let mut s = GfMat::matrix_zero(dim, dim); // constraint matrix
-        let mut a = GfMat::matrix_zero(dim, dim); // augmented matrix
-        let mut f = GfVals::gfvals_zero(dim); // constant column
-        let mut u = GfVals::gfvals_zero(dim); // solution column
-
-        for i in 0..dim {
-            let x_i = eval_point(shares[i].number);
-            let r_i = GfVal(shares[i].data[index]);
-
-            f.0[i] = x_i.pow(e).mul(r_i);
-
-            for j in 0..q {
-                s.set(i, j, x_i.pow(j));
-                if i == j {
-                    a.set(i, j, GfVal(1));
-                }
-            }
-
-            for k in 0..e {
-                let j = k + q;
-                s.set(i, j, x_i.pow(k).mul(r_i));
-                if i == j {
-                    a.set(i, j, GfVal(1));
-                }
-            }
-        }
-
-        // invert and put the result in a
-        if s.invert_with(&mut a).is_err() {
-            return Err(("Error inverting matrix").into());
-        }
-
-        // multiply the inverted matrix by the column vector
-        for i in 0..dim {
-            let ri = a.index_row(i);
-            u.0[i] = ri.dot(&f);
-        }
-
-        // reverse u for easier construction of the polynomials
-        let len_u = u.0.len();
-        for i in 0..len_u / 2 {
-            u.0.swap(i, len_u - i - 1);
-        }
-
-        let mut q_poly = GfPoly(u.0[e..].to_vec());
-        let mut e_poly = GfPoly(vec![GfVal(1)]);
-        e_poly.0.extend_from_slice(&u.0[..e]);
-
-        let (p_poly, rem) = q_poly.div(e_poly)?;
-
-        if !rem.is_zero() {
-            return Err(("too many errors to reconstruct").into());
-        }
-
-        let mut out = vec![0u8; self.n];
-        for i in 0..out.len() {
-            let mut pt = GfVal(0);
-            if i != 0 {
-                pt = interp_base.pow(i - 1);
-            }
-            out[i] = p_poly.eval(pt).0;
-        }
-
-        return Ok(out);
+        Decoder::new(self, 0).decode_column(shares, index)
     }
 
     pub fn syndrome_matrix(
@@ -220,3 +177,67 @@ impl FEC {
         return Ok(out.parity());
     }
 }
+
+/// Returns the sorted, disjoint column ranges of pieces of `piece_len` bytes that hold
+/// the bytes `offset..end` of the data.
+fn range_columns(offset: usize, end: usize, piece_len: usize) -> Vec<(usize, usize)> {
+    let (first, last) = (offset / piece_len, (end - 1) / piece_len);
+    let (start_col, end_col) = (offset % piece_len, (end - 1) % piece_len + 1);
+
+    if first == last {
+        vec![(start_col, end_col)]
+    } else if last == first + 1 && end_col <= start_col {
+        // the tail of one piece and the head of the next one
+        vec![(0, end_col), (start_col, piece_len)]
+    } else {
+        vec![(0, piece_len)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_range_columns() {
+        assert_eq!(range_columns(3, 7, 10), vec![(3, 7)]);
+        assert_eq!(range_columns(8, 12, 10), vec![(0, 2), (8, 10)]);
+        assert_eq!(range_columns(5, 16, 10), vec![(0, 10)]);
+        assert_eq!(range_columns(5, 35, 10), vec![(0, 10)]);
+        assert_eq!(range_columns(10, 20, 10), vec![(0, 10)]);
+    }
+
+    #[test]
+    fn test_decode_range_matches_decode() {
+        let f = FEC::new(4, 8).unwrap();
+        let data: Vec<u8> = (0..4000u32).map(|i| (i * 7 + i / 251) as u8).collect();
+        let mut shares: Vec<Share> = vec![];
+        f.encode(&data, |s: Share| shares.push(s)).unwrap();
+
+        // corruption inside the requested columns gets corrected
+        shares[1].data[900] ^= 0xFF;
+        shares[6].data[10] ^= 0x01;
+        shares.remove(3);
+
+        for (offset, len) in [
+            (0, 1),
+            (1900, 300),
+            (995, 10),
+            (0, 4000),
+            (3999, 1),
+            (100, 2000),
+        ] {
+            let range = f.decode_range(shares.clone(), offset, len).unwrap();
+            assert_eq!(
+                &range[..],
+                &data[offset..offset + len],
+                "{}+{}",
+                offset,
+                len
+            );
+        }
+
+        assert!(f.decode_range(shares.clone(), 3990, 20).is_err());
+        assert!(f.decode_range(shares, 4000, 0).unwrap().is_empty());
+    }
+}
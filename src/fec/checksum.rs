@@ -0,0 +1,195 @@
+use crate::fec::fec::{Share, FEC};
+use crate::fec::striped::{Stripe, StripedCodec};
+use crate::hash::crc32c::Crc32c;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+/// `ChecksummedShare` is a `Share` carrying a CRC-32C of its number and data.
+///
+/// Berlekamp-Welch can correct at most (n - k) / 2 corrupted shares because it first has
+/// to find them. A share whose checksum does not match is known to be bad, so it can be
+/// dropped and rebuilt as an erasure instead, which works for up to n - k bad shares.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChecksummedShare {
+    pub share: Share,
+    /// CRC-32C of the share number (as a little-endian u32) followed by the data.
+    pub checksum: u32,
+}
+
+impl ChecksummedShare {
+    /// `new()` computes the checksum of `share`.
+    pub fn new(share: Share) -> ChecksummedShare {
+        let checksum = share_checksum(&share);
+        ChecksummedShare { share, checksum }
+    }
+
+    /// `verify()` returns whether the share still matches its checksum.
+    pub fn verify(&self) -> bool {
+        share_checksum(&self.share) == self.checksum
+    }
+}
+
+/// `share_checksum()` returns the CRC-32C of the share number and data.
+pub fn share_checksum(share: &Share) -> u32 {
+    chunk_checksum(share.number, &share.data)
+}
+
+/// `chunk_checksum()` is `share_checksum()` for data that is not held in a `Share`.
+pub fn chunk_checksum(number: usize, data: &[u8]) -> u32 {
+    let mut crc = Crc32c::new();
+    crc.update(&(number as u32).to_le_bytes());
+    crc.update(data);
+    crc.finish()
+}
+
+/// `verify_shares()` splits `shares` into the shares matching their checksum and the
+/// numbers of those that do not, in ascending order.
+pub fn verify_shares(shares: Vec<ChecksummedShare>) -> (Vec<Share>, Vec<usize>) {
+    let mut good = Vec::with_capacity(shares.len());
+    let mut bad = Vec::new();
+    for s in shares {
+        if s.verify() {
+            good.push(s.share);
+        } else {
+            bad.push(s.share.number);
+        }
+    }
+    bad.sort_unstable();
+    (good, bad)
+}
+
+impl FEC {
+    /// `encode_checksummed()` works like `encode()` but hands out every share with its
+    /// checksum.
+    pub fn encode_checksummed<F>(&self, input: &[u8], mut output: F) -> Result<(), Box<dyn Error>>
+    where
+        F: FnMut(ChecksummedShare),
+    {
+        self.encode(input, |s| output(ChecksummedShare::new(s)))
+    }
+
+    /// `decode_checksummed()` verifies the checksum of every share, demotes the shares
+    /// that fail to erasures and decodes the rest with `decode()`. It returns the data
+    /// together with the numbers of the demoted shares.
+    pub fn decode_checksummed(
+        &self,
+        dst: Vec<u8>,
+        shares: Vec<ChecksummedShare>,
+    ) -> Result<(Vec<u8>, Vec<usize>), Box<dyn Error>> {
+        let (shares, corrupted) = verify_shares(shares);
+        if shares.len() < self.k {
+            return Err(format!(
+                "only {} of the {} required shares passed their checksum (failed: {:?})",
+                shares.len(),
+                self.k,
+                corrupted
+            )
+            .into());
+        }
+        let data = self.decode(dst, shares)?;
+        Ok((data, corrupted))
+    }
+}
+
+impl StripedCodec {
+    /// `encode_stripe_checksummed()` works like `encode_stripe()` but checksums every
+    /// chunk of the stripe.
+    pub fn encode_stripe_checksummed<F>(
+        &self,
+        stripe: &Stripe,
+        data: &[u8],
+        mut output: F,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        F: FnMut(ChecksummedShare),
+    {
+        self.encode_stripe(stripe, data, |s| output(ChecksummedShare::new(s)))
+    }
+
+    /// `decode_stripe_checksummed()` verifies the chunks of a stripe, demotes the ones
+    /// that fail to erasures and decodes the stripe from the rest. It returns the
+    /// original bytes of the stripe and the numbers of the demoted chunks.
+    pub fn decode_stripe_checksummed(
+        &self,
+        stripe: &Stripe,
+        shares: Vec<ChecksummedShare>,
+    ) -> Result<(Vec<u8>, Vec<usize>), Box<dyn Error>> {
+        let (shares, corrupted) = verify_shares(shares);
+        if shares.len() < self.fec().k {
+            return Err(format!(
+                "stripe {}: only {} of the {} required shares passed their checksum (failed: {:?})",
+                stripe.index,
+                shares.len(),
+                self.fec().k,
+                corrupted
+            )
+            .into());
+        }
+        let data = self.decode_stripe(stripe, shares)?;
+        Ok((data, corrupted))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksums_recover_from_n_minus_k_corruptions() {
+        let fec = FEC::new(4, 8).unwrap();
+        let input = b"checksums turn errors into erasures!";
+        let mut shares = Vec::new();
+        fec.encode_checksummed(input, |s| shares.push(s)).unwrap();
+
+        // four corrupted shares are twice what Berlekamp-Welch alone could correct
+        for i in [0, 2, 5, 7] {
+            shares[i].share.data[1] ^= 0x40;
+        }
+        let (data, corrupted) = fec.decode_checksummed(vec![], shares).unwrap();
+        assert_eq!(&data[..input.len()], input);
+        assert_eq!(corrupted, vec![0, 2, 5, 7]);
+    }
+
+    #[test]
+    fn test_checksums_cover_share_number() {
+        let share = Share {
+            number: 3,
+            data: vec![1, 2, 3],
+        };
+        let mut checked = ChecksummedShare::new(share);
+        assert!(checked.verify());
+        checked.share.number = 4;
+        assert!(!checked.verify());
+    }
+
+    #[test]
+    fn test_stripe_checksums() {
+        let codec = StripedCodec::new(FEC::new(3, 5).unwrap(), 30).unwrap();
+        let input: Vec<u8> = (0..75u8).collect();
+        let layout = codec.layout(input.len());
+
+        for stripe in &layout.stripes {
+            let data = &input[stripe.offset..stripe.offset + stripe.len];
+            let mut chunks = Vec::new();
+            codec
+                .encode_stripe_checksummed(stripe, data, |s| chunks.push(s))
+                .unwrap();
+            chunks[1].share.data[0] ^= 1;
+            chunks[4].share.data[0] ^= 1;
+
+            let (decoded, corrupted) = codec.decode_stripe_checksummed(stripe, chunks).unwrap();
+            assert_eq!(decoded, data);
+            assert_eq!(corrupted, vec![1, 4]);
+        }
+
+        let stripe = layout.stripes[0];
+        let mut chunks = Vec::new();
+        codec
+            .encode_stripe_checksummed(&stripe, &input[..30], |s| chunks.push(s))
+            .unwrap();
+        for chunk in chunks.iter_mut().take(3) {
+            chunk.share.data[0] ^= 1;
+        }
+        assert!(codec.decode_stripe_checksummed(&stripe, chunks).is_err());
+    }
+}
@@ -1,14 +1,22 @@
+use crate::fec::workspace::Decoder;
+use crate::galois_field::gf_alg::GfVal;
 use crate::galois_field::tables::{GF_EXP, GF_MUL_TABLE};
 use crate::math::addmul::addmul;
-use crate::math::pivot_searcher::{create_inverted_vdm, invert_matrix};
+use crate::math::bitmatrix::XorSchedule;
+use crate::math::cauchy::{cauchy_multiplier, cauchy_point, create_cauchy_matrix};
+use crate::math::matmul::{matmul_tiled, tile_size};
+#[cfg(feature = "parallel")]
+use crate::math::parallel::{for_each_column_chunk, DEFAULT_MIN_PARALLEL_LEN};
+use crate::math::pivot_searcher::create_inverted_vdm;
+use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
 use std::error::Error;
 
 /// `FEC` (Forward Error Correction) struct represents a Reed-Solomon encoder/decoder.
 ///
 /// This struct is used to encode data into multiple pieces and decode it back, even if
-/// some pieces are missing or corrupted. It uses Vandermonde matrices to generate the
-/// encoding matrix.
+/// some pieces are missing or corrupted. By default it uses Vandermonde matrices to
+/// generate the encoding matrix; see `MatrixKind` for the alternatives.
 
 #[derive(Debug)]
 pub struct FEC {
@@ -19,7 +27,45 @@ pub struct FEC {
     ///The encoding matrix used for generating the encoded pieces.
     pub enc_matrix: Vec<u8>,
     ///`vand_matrix`: The Vandermonde matrix used for constructing the encoding matrix.
+    /// For a Cauchy code this holds the equivalent generalized Vandermonde matrix
+    /// (columns scaled by the share multipliers), which is what the syndrome check uses.
     pub vand_matrix: Vec<u8>,
+    /// The family of matrix `enc_matrix` was built from.
+    pub matrix: MatrixKind,
+    /// The kernel used to multiply blocks by `enc_matrix` and the decoding matrix.
+    pub backend: Backend,
+    /// XOR schedule for the parity rows of `enc_matrix`, built on first use.
+    parity_schedule: OnceCell<XorSchedule>,
+    /// Blocks shorter than twice this many bytes are processed on the calling thread;
+    /// longer ones are split into ranges of at least this size across rayon's pool.
+    #[cfg(feature = "parallel")]
+    pub min_parallel_len: usize,
+}
+
+/// `MatrixKind` selects how `FEC` builds its encoding matrix.
+///
+/// Both kinds produce a systematic code (the first k shares are the input data) that
+/// can be rebuilt from any k shares and corrected with Berlekamp-Welch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MatrixKind {
+    /// Extended Vandermonde rows multiplied by the inverse of the top k x k block.
+    #[default]
+    Vandermonde,
+    /// Identity on top of a Cauchy matrix `1 / (x_i + y_j)`. Every square submatrix
+    /// of a Cauchy matrix is invertible, so no inversion is needed at construction.
+    Cauchy,
+}
+
+/// `Backend` selects the kernel `encode()`, `encode_single()` and `rebuild()` use
+/// for the GF(256) matrix products. All backends produce byte-identical shares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// One `GF_MUL_TABLE` lookup per byte and coefficient.
+    #[default]
+    Table,
+    /// Binary expansion of the coding matrix (an 8x8 bit-matrix per coefficient),
+    /// evaluated with XORs of bit-sliced regions only. See `XorSchedule`.
+    BitMatrix,
 }
 
 /// `Share` represents a single encoded piece of data in the Forward Error Correction (FEC) process.
@@ -66,10 +112,23 @@ impl Ord for Share {
 
 impl FEC {
     pub fn new(k: usize, n: usize) -> Result<FEC, Box<dyn Error>> {
+        FEC::new_with_matrix(k, n, MatrixKind::Vandermonde)
+    }
+
+    /// `new_with_matrix()` creates a `FEC` whose encoding matrix is built from the
+    /// given `MatrixKind`. Shares produced by different kinds are not interchangeable.
+    pub fn new_with_matrix(k: usize, n: usize, matrix: MatrixKind) -> Result<FEC, Box<dyn Error>> {
         if k <= 0 || n <= 0 || k > 256 || n > 256 || k > n {
             return Err("requires 1 <= k <= n <= 256".into());
         }
 
+        match matrix {
+            MatrixKind::Vandermonde => Ok(FEC::vandermonde(k, n)),
+            MatrixKind::Cauchy => Ok(FEC::cauchy(k, n)),
+        }
+    }
+
+    fn vandermonde(k: usize, n: usize) -> FEC {
         let mut enc_matrix = vec![0u8; n * k];
         let mut temp_matrix = vec![0u8; n * k];
         create_inverted_vdm(&mut temp_matrix, k);
@@ -108,14 +167,121 @@ impl FEC {
             g = GF_MUL_TABLE[2][g as usize];
         }
 
-        Ok(FEC {
+        FEC::from_matrices(k, n, enc_matrix, vand_matrix, MatrixKind::Vandermonde)
+    }
+
+    fn cauchy(k: usize, n: usize) -> FEC {
+        let mut enc_matrix = vec![0u8; n * k];
+        create_cauchy_matrix(&mut enc_matrix, k, n);
+
+        // k rows, n columns: column j is v_j * (1, a_j, a_j^2, ...)
+        let mut vand_matrix = vec![0u8; k * n];
+        for col in 0..n {
+            let point = cauchy_point(col) as usize;
+            let mut a = cauchy_multiplier(col, k);
+            for row in 0..k {
+                vand_matrix[row * n + col] = a;
+                a = GF_MUL_TABLE[point][a as usize];
+            }
+        }
+
+        FEC::from_matrices(k, n, enc_matrix, vand_matrix, MatrixKind::Cauchy)
+    }
+
+    fn from_matrices(
+        k: usize,
+        n: usize,
+        enc_matrix: Vec<u8>,
+        vand_matrix: Vec<u8>,
+        matrix: MatrixKind,
+    ) -> FEC {
+        FEC {
             k,
             n,
             enc_matrix,
             vand_matrix,
+            matrix,
+            backend: Backend::Table,
+            parity_schedule: OnceCell::new(),
+            #[cfg(feature = "parallel")]
+            min_parallel_len: DEFAULT_MIN_PARALLEL_LEN,
+        }
+    }
+
+    /// `eval_point()` returns the X co-ordinate share `num` is evaluated at.
+    pub fn eval_point(&self, num: usize) -> GfVal {
+        match self.matrix {
+            MatrixKind::Vandermonde if num == 0 => GfVal(0),
+            MatrixKind::Vandermonde => GfVal(2).pow(num - 1),
+            MatrixKind::Cauchy => GfVal(cauchy_point(num)),
+        }
+    }
+
+    /// `multiplier()` returns the factor share `num` is scaled by, i.e. share `num`
+    /// holds `multiplier(num) * P(eval_point(num))` for the encoding polynomial `P`.
+    pub fn multiplier(&self, num: usize) -> GfVal {
+        match self.matrix {
+            MatrixKind::Vandermonde => GfVal(1),
+            MatrixKind::Cauchy => GfVal(cauchy_multiplier(num, self.k)),
+        }
+    }
+
+    /// `with_backend()` returns the same `FEC` using `backend` for its matrix products.
+    pub fn with_backend(mut self, backend: Backend) -> FEC {
+        self.backend = backend;
+        self
+    }
+
+    /// `with_min_parallel_len()` sets the smallest column range a thread works on.
+    /// Payloads under twice this size stay single-threaded.
+    #[cfg(feature = "parallel")]
+    pub fn with_min_parallel_len(mut self, min_parallel_len: usize) -> FEC {
+        self.min_parallel_len = min_parallel_len;
+        self
+    }
+
+    fn parity_schedule(&self) -> &XorSchedule {
+        self.parity_schedule.get_or_init(|| {
+            XorSchedule::new(&self.enc_matrix[self.k * self.k..], self.n - self.k, self.k)
         })
     }
 
+    /// Computes `outputs[i] = sum_j rows[i][j] * inputs[j]` with the configured backend.
+    /// `schedule` must be the XOR schedule of `rows` when the backend is `BitMatrix`.
+    fn mul_blocks(
+        &self,
+        rows: &[u8],
+        schedule: Option<&XorSchedule>,
+        inputs: &[&[u8]],
+        outputs: &mut [&mut [u8]],
+    ) {
+        let kernel = |inputs: &[&[u8]], outputs: &mut [&mut [u8]]| match schedule {
+            Some(schedule) => schedule.apply(inputs, outputs),
+            None => {
+                let tile = tile_size(inputs.len() + outputs.len());
+                matmul_tiled(rows, inputs, outputs, tile)
+            }
+        };
+
+        #[cfg(feature = "parallel")]
+        {
+            let len = outputs.first().map_or(0, |out| out.len());
+            if len >= 2 * self.min_parallel_len {
+                for_each_column_chunk(inputs, outputs, self.min_parallel_len, kernel);
+                return;
+            }
+        }
+
+        kernel(inputs, outputs);
+    }
+
+    fn schedule_for(&self, rows: &[u8]) -> Option<XorSchedule> {
+        match self.backend {
+            Backend::Table => None,
+            Backend::BitMatrix => Some(XorSchedule::new(rows, rows.len() / self.k, self.k)),
+        }
+    }
+
     pub fn required(&self) -> usize {
         self.k
     }
@@ -165,21 +331,23 @@ impl FEC {
             });
         }
 
-        let mut fec_buf = vec![0u8; block_size];
-        for i in k..n {
-            fec_buf.iter_mut().for_each(|byte| *byte = 0);
+        let inputs: Vec<&[u8]> = (0..k)
+            .map(|j| &input[j * block_size..(j + 1) * block_size])
+            .collect();
 
-            for j in 0..k {
-                addmul(
-                    &mut fec_buf,
-                    &input[j * block_size..(j + 1) * block_size],
-                    enc_matrix[i * k + j],
-                );
-            }
+        // all parity pieces are computed together so the input is only read once
+        let mut parity = vec![vec![0u8; block_size]; n - k];
+        let schedule = match self.backend {
+            Backend::Table => None,
+            Backend::BitMatrix => Some(self.parity_schedule()),
+        };
+        let mut outputs: Vec<&mut [u8]> = parity.iter_mut().map(|p| p.as_mut_slice()).collect();
+        self.mul_blocks(&enc_matrix[k * k..], schedule, &inputs, &mut outputs);
 
+        for (i, data) in parity.into_iter().enumerate() {
             output(Share {
-                number: i,
-                data: fec_buf.clone(),
+                number: k + i,
+                data,
             });
         }
 
@@ -225,16 +393,70 @@ impl FEC {
             return Ok(());
         }
 
-        output.fill(0);
+        let inputs: Vec<&[u8]> = (0..k)
+            .map(|i| &input[i * block_size..(i + 1) * block_size])
+            .collect();
 
-        for i in 0..k {
+        let row = &enc_matrix[num * k..(num + 1) * k];
+        let schedule = self.schedule_for(row);
+        self.mul_blocks(row, schedule.as_ref(), &inputs, &mut [output]);
+
+        Ok(())
+    }
+
+    /// `update_parity()` patches `parity_shares` after data piece `piece_index` changed
+    /// from `old` to `new`, without re-encoding the other data pieces.
+    ///
+    /// The code is linear, so every parity share `i` only changes by
+    /// `enc_matrix[i][piece_index] * (old ^ new)`. `old` and `new` must be the full piece
+    /// (as long as the shares); updating the data share itself is up to the caller.
+    pub fn update_parity(
+        &self,
+        piece_index: usize,
+        old: &[u8],
+        new: &[u8],
+        parity_shares: &mut [Share],
+    ) -> Result<(), Box<dyn Error>> {
+        if let Some(share) = parity_shares.iter().find(|s| s.data.len() != old.len()) {
+            return Err(format!("share {} must be {} bytes", share.number, old.len()).into());
+        }
+        self.update_parity_range(piece_index, 0, old, new, parity_shares)
+    }
+
+    /// `update_parity_range()` is `update_parity()` for a partial write: `old` and `new`
+    /// are the bytes of data piece `piece_index` starting at `offset`.
+    pub fn update_parity_range(
+        &self,
+        piece_index: usize,
+        offset: usize,
+        old: &[u8],
+        new: &[u8],
+        parity_shares: &mut [Share],
+    ) -> Result<(), Box<dyn Error>> {
+        let k = self.k;
+        if piece_index >= k {
+            return Err(format!("piece index must be less than {}", k).into());
+        }
+        if old.len() != new.len() {
+            return Err("old and new data must have the same length".into());
+        }
+        for share in parity_shares.iter() {
+            if share.number < k || share.number >= self.n {
+                return Err(format!("share {} is not a parity share", share.number).into());
+            }
+            if share.data.len() < offset + old.len() {
+                return Err(format!("share {} is too short for the update", share.number).into());
+            }
+        }
+
+        let delta: Vec<u8> = old.iter().zip(new.iter()).map(|(a, b)| a ^ b).collect();
+        for share in parity_shares.iter_mut() {
             addmul(
-                output,
-                &input[i * block_size..(i + 1) * block_size],
-                enc_matrix[num * k + i],
+                &mut share.data[offset..offset + delta.len()],
+                &delta,
+                self.enc_matrix[share.number * k + piece_index],
             );
         }
-
         Ok(())
     }
 
@@ -256,76 +478,49 @@ impl FEC {
     where
         F: FnMut(Share),
     {
-        let size = shares.len();
         let k = self.k;
-        let n = self.n;
-        let enc_matrix = &self.enc_matrix;
-
-        if size < k {
+        if shares.len() < k {
             return Err(("Not enough Shares!").into());
         }
         let share_size = shares[0].data.len();
         shares.sort();
-
-        let mut m_dec = vec![0u8; k * k];
-        let mut indexes = vec![0; k];
-        let mut sharesv: Vec<Vec<u8>> = vec![vec![]; k];
-
-        let mut shares_b_iter = 0;
-        let mut shares_e_iter = size - 1;
-
-        for i in 0..k {
-            let mut share_id: usize = 0;
-            let mut share_data: Vec<u8> = Vec::new();
-            if let Some(share) = shares.get(shares_b_iter) {
-                if share.number == i {
-                    share_id = share.number;
-                    share_data = share.data.clone();
-                    shares_b_iter += 1;
-                } else if let Some(share) = shares.get(shares_e_iter) {
-                    share_id = share.number;
-                    share_data = share.data.clone();
-                    shares_e_iter -= 1;
-                }
+        for (i, share) in shares.iter().enumerate() {
+            if share.number >= self.n || (i > 0 && shares[i - 1].number == share.number) {
+                return Err(format!("invalid share id {}", share.number).into());
             }
-            if share_id >= n {
-                return Err(format!("invalid share id {}", share_id).into());
+            if share.data.len() != share_size {
+                return Err(format!("shares must be {} bytes", share_size).into());
             }
-            if share_id < k {
-                m_dec[i * (k + 1)] = 1;
-                output(Share {
-                    number: share_id,
-                    data: share_data.clone(),
-                });
-            } else {
-                m_dec[i * k..i * k + k]
-                    .copy_from_slice(&enc_matrix[share_id * k..share_id * k + k]);
-            }
-            sharesv[i] = share_data;
-            indexes[i] = share_id;
         }
 
-        // 0.2 µs
-        if invert_matrix(&mut m_dec, k).is_err() {
-            return Err(("Matrix inversion failed").into());
-        }
-
-        let mut buf = vec![0u8; share_size];
+        let mut decoder = Decoder::new(self, 0);
+        let (slots, m_dec) = decoder.decoding_matrix(self, &shares)?;
 
-        for i in 0..indexes.len() {
-            if indexes[i] >= k {
-                buf.fill(0);
+        // rebuild every missing data piece in one pass over the kept shares
+        let missing: Vec<usize> = (0..k).filter(|&i| shares[slots[i]].number != i).collect();
+        let mut rows = Vec::with_capacity(missing.len() * k);
+        for &i in &missing {
+            rows.extend_from_slice(&m_dec[i * k..(i + 1) * k]);
+        }
 
-                for col in 0..k {
-                    addmul(&mut buf, &sharesv[col], m_dec[i * k + col]);
+        let inputs: Vec<&[u8]> = slots.iter().map(|&s| shares[s].data.as_slice()).collect();
+        let mut rebuilt = vec![vec![0u8; share_size]; missing.len()];
+        let mut outputs: Vec<&mut [u8]> = rebuilt.iter_mut().map(|r| r.as_mut_slice()).collect();
+        let schedule = self.schedule_for(&rows);
+        self.mul_blocks(&rows, schedule.as_ref(), &inputs, &mut outputs);
 
-                    output(Share {
-                        number: i,
-                        data: buf.clone(),
-                    });
-                }
+        for i in 0..k {
+            let share = &shares[slots[i]];
+            if share.number == i {
+                output(Share {
+                    number: i,
+                    data: share.data.clone(),
+                });
             }
         }
+        for (i, data) in missing.into_iter().zip(rebuilt) {
+            output(Share { number: i, data });
+        }
 
         Ok(())
     }
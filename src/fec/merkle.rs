@@ -0,0 +1,259 @@
+use crate::fec::fec::{Share, FEC};
+use crate::hash::sha256::{Sha256, DIGEST_LEN};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+/// A SHA-256 digest: a Merkle root or node.
+pub type Hash = [u8; DIGEST_LEN];
+
+/// Domain separation prefixes, so a leaf can never be passed off as an inner node.
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+/// `leaf_hash()` returns the Merkle leaf of a share: the SHA-256 of a zero byte, the
+/// share number as a little-endian u32 and the data.
+pub fn leaf_hash(share: &Share) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update(&[LEAF_PREFIX]);
+    hasher.update(&(share.number as u32).to_le_bytes());
+    hasher.update(&share.data);
+    hasher.finish()
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update(&[NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finish()
+}
+
+/// `MerkleTree` commits to the n shares of an encoded input.
+///
+/// Leaf `i` is the `leaf_hash()` of share `i`. Each level pairs up the nodes of the
+/// level below; when a level has an odd number of nodes the last one is carried up
+/// unchanged.
+#[derive(Debug, Clone)]
+pub struct MerkleTree {
+    /// `levels[0]` holds the leaves, the last level holds the root.
+    levels: Vec<Vec<Hash>>,
+}
+
+/// `MerkleProof` lists the sibling hashes on the path from a leaf to the root, from the
+/// bottom up. Levels where the node is carried up have no sibling.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    pub siblings: Vec<Hash>,
+}
+
+/// `ProvenShare` is a `Share` with the proof that it belongs to a committed encoding.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProvenShare {
+    pub share: Share,
+    pub proof: MerkleProof,
+}
+
+impl MerkleTree {
+    /// `new()` builds the tree over `shares`, which must be ordered by share number
+    /// starting at 0.
+    pub fn new(shares: &[Share]) -> Result<MerkleTree, Box<dyn Error>> {
+        if shares.is_empty() {
+            return Err("cannot commit to an empty set of shares".into());
+        }
+        if shares.iter().enumerate().any(|(i, s)| s.number != i) {
+            return Err("shares must be numbered 0..n in order".into());
+        }
+
+        let mut levels = vec![shares.iter().map(leaf_hash).collect::<Vec<_>>()];
+        while levels.last().unwrap().len() > 1 {
+            let level = levels.last().unwrap();
+            let next = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => node_hash(left, right),
+                    [single] => *single,
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(next);
+        }
+        Ok(MerkleTree { levels })
+    }
+
+    pub fn root(&self) -> Hash {
+        self.levels.last().unwrap()[0]
+    }
+
+    /// `proof()` returns the inclusion proof of share `number`.
+    pub fn proof(&self, number: usize) -> MerkleProof {
+        let mut siblings = Vec::new();
+        let mut index = number;
+        for level in &self.levels[..self.levels.len() - 1] {
+            let sibling = index ^ 1;
+            if sibling < level.len() {
+                siblings.push(level[sibling]);
+            }
+            index /= 2;
+        }
+        MerkleProof { siblings }
+    }
+}
+
+impl MerkleProof {
+    /// `verify()` returns whether `share` is share `share.number` of the n shares
+    /// committed to by `root`.
+    pub fn verify(&self, root: &Hash, n: usize, share: &Share) -> bool {
+        if share.number >= n {
+            return false;
+        }
+        let mut hash = leaf_hash(share);
+        let mut siblings = self.siblings.iter();
+        let (mut index, mut width) = (share.number, n);
+        while width > 1 {
+            if index ^ 1 < width {
+                let Some(sibling) = siblings.next() else {
+                    return false;
+                };
+                hash = if index % 2 == 0 {
+                    node_hash(&hash, sibling)
+                } else {
+                    node_hash(sibling, &hash)
+                };
+            }
+            index /= 2;
+            width = width.div_ceil(2);
+        }
+        siblings.next().is_none() && &hash == root
+    }
+}
+
+impl ProvenShare {
+    pub fn verify(&self, root: &Hash, n: usize) -> bool {
+        self.proof.verify(root, n, &self.share)
+    }
+}
+
+impl FEC {
+    /// `encode_committed()` encodes `input`, builds a `MerkleTree` over all n shares and
+    /// hands out every share with its inclusion proof. It returns the Merkle root,
+    /// which should be kept by whoever will later decode, apart from the shares.
+    pub fn encode_committed<F>(&self, input: &[u8], mut output: F) -> Result<Hash, Box<dyn Error>>
+    where
+        F: FnMut(ProvenShare),
+    {
+        let mut shares = Vec::with_capacity(self.n);
+        self.encode(input, |s| shares.push(s))?;
+        let tree = MerkleTree::new(&shares)?;
+        for share in shares {
+            let proof = tree.proof(share.number);
+            output(ProvenShare { share, proof });
+        }
+        Ok(tree.root())
+    }
+
+    /// `decode_committed()` checks every share against `root` before decoding. Shares
+    /// whose proof fails, or that repeat the number of a share already accepted, are
+    /// treated as erasures. It returns the data together with the numbers of the
+    /// rejected shares.
+    pub fn decode_committed(
+        &self,
+        dst: Vec<u8>,
+        root: &Hash,
+        shares: Vec<ProvenShare>,
+    ) -> Result<(Vec<u8>, Vec<usize>), Box<dyn Error>> {
+        let mut seen = vec![false; self.n];
+        let mut accepted = Vec::with_capacity(shares.len());
+        let mut rejected = Vec::new();
+        for s in shares {
+            if s.verify(root, self.n) && !seen[s.share.number] {
+                seen[s.share.number] = true;
+                accepted.push(s.share);
+            } else {
+                rejected.push(s.share.number);
+            }
+        }
+        rejected.sort_unstable();
+
+        if accepted.len() < self.k {
+            return Err(format!(
+                "only {} of the {} required shares match the commitment (rejected: {:?})",
+                accepted.len(),
+                self.k,
+                rejected
+            )
+            .into());
+        }
+        let data = self.decode(dst, accepted)?;
+        Ok((data, rejected))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merkle_proofs_for_all_sizes() {
+        for n in 1..=9 {
+            let shares: Vec<Share> = (0..n)
+                .map(|number| Share {
+                    number,
+                    data: vec![number as u8; 5],
+                })
+                .collect();
+            let tree = MerkleTree::new(&shares).unwrap();
+            let root = tree.root();
+            for share in &shares {
+                let proof = tree.proof(share.number);
+                assert!(proof.verify(&root, n, share));
+                assert!(!proof.verify(
+                    &root,
+                    n,
+                    &Share {
+                        number: n,
+                        data: share.data.clone()
+                    }
+                ));
+
+                let forged = Share {
+                    number: share.number,
+                    data: vec![0xEE; 5],
+                };
+                assert!(!proof.verify(&root, n, &forged));
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_committed_rejects_forged_shares() {
+        let fec = FEC::new(3, 7).unwrap();
+        let input = b"shares from untrusted holders";
+        let mut shares = Vec::new();
+        let root = fec.encode_committed(input, |s| shares.push(s)).unwrap();
+
+        // colluding holders replace four shares with a consistent forgery
+        let mut forged = Vec::new();
+        FEC::new(3, 7)
+            .unwrap()
+            .encode(b"a completely different input", |s| forged.push(s))
+            .unwrap();
+        for i in [0, 2, 4, 6] {
+            shares[i].share.data = forged[i].data.clone();
+        }
+
+        let (data, rejected) = fec.decode_committed(vec![], &root, shares).unwrap();
+        assert_eq!(&data[..input.len()], input);
+        assert_eq!(rejected, vec![0, 2, 4, 6]);
+    }
+
+    #[test]
+    fn test_decode_committed_not_enough_shares() {
+        let fec = FEC::new(3, 5).unwrap();
+        let mut shares = Vec::new();
+        let root = fec.encode_committed(b"abcdef", |s| shares.push(s)).unwrap();
+        for s in shares.iter_mut().take(3) {
+            s.share.data[0] ^= 1;
+        }
+        assert!(fec.decode_committed(vec![], &root, shares).is_err());
+    }
+}
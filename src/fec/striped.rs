@@ -0,0 +1,280 @@
+use crate::fec::fec::{Share, FEC};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+
+/// `StripedCodec` encodes large inputs as a sequence of independently encoded stripes.
+///
+/// `FEC::encode` turns the whole input into k pieces, so every share is `len / k` bytes
+/// long and a corrupted byte anywhere forces decoding across the full length. The
+/// striped codec cuts the input into stripes of `stripe_size` bytes (the last one may be
+/// shorter) and encodes each of them with the wrapped `FEC`. Each stripe produces its
+/// own n shares of `stripe_size / k` bytes and is decoded on its own, so a stripe that
+/// cannot be recovered does not affect the others.
+#[derive(Debug)]
+pub struct StripedCodec {
+    fec: FEC,
+    stripe_size: usize,
+}
+
+/// `Stripe` records where one stripe lives in the original input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stripe {
+    /// Position of the stripe in the sequence.
+    pub index: usize,
+    /// Offset of the first byte of the stripe in the original input.
+    pub offset: usize,
+    /// Number of original bytes in the stripe.
+    pub len: usize,
+    /// Length of every share of the stripe.
+    pub share_len: usize,
+}
+
+/// `StripeLayout` describes how an input was cut into stripes. It is needed to
+/// decode the stripes back into the original bytes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StripeLayout {
+    /// Maximum number of original bytes per stripe.
+    pub stripe_size: usize,
+    /// Length of the original input.
+    pub original_len: usize,
+    /// Stripe boundaries, in order.
+    pub stripes: Vec<Stripe>,
+}
+
+/// `StripeDecodeError` lists the stripes `StripedCodec::decode()` could not recover.
+#[derive(Debug)]
+pub struct StripeDecodeError {
+    /// Index of each failed stripe with the reason it failed.
+    pub failures: Vec<(usize, String)>,
+}
+
+impl fmt::Display for StripeDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} stripe(s) could not be decoded:", self.failures.len())?;
+        for (index, reason) in &self.failures {
+            write!(f, " [stripe {}: {}]", index, reason)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for StripeDecodeError {}
+
+impl StripedCodec {
+    /// `new()` wraps `fec`. `stripe_size` must be a positive multiple of the required
+    /// number of pieces k.
+    pub fn new(fec: FEC, stripe_size: usize) -> Result<StripedCodec, Box<dyn Error>> {
+        if stripe_size == 0 || stripe_size % fec.k != 0 {
+            return Err(format!("stripe size must be a positive multiple of {}", fec.k).into());
+        }
+        Ok(StripedCodec { fec, stripe_size })
+    }
+
+    pub fn fec(&self) -> &FEC {
+        &self.fec
+    }
+
+    pub fn stripe_size(&self) -> usize {
+        self.stripe_size
+    }
+
+    /// `layout()` returns the stripes an input of `len` bytes is cut into.
+    pub fn layout(&self, len: usize) -> StripeLayout {
+        let k = self.fec.k;
+        let stripes = (0..len.div_ceil(self.stripe_size))
+            .map(|index| {
+                let offset = index * self.stripe_size;
+                let len = usize::min(self.stripe_size, len - offset);
+                Stripe {
+                    index,
+                    offset,
+                    len,
+                    share_len: len.div_ceil(k),
+                }
+            })
+            .collect();
+
+        StripeLayout {
+            stripe_size: self.stripe_size,
+            original_len: len,
+            stripes,
+        }
+    }
+
+    /// `encode()` encodes every stripe of `input` in order. `output` is called n times
+    /// per stripe with the stripe index and one of its shares.
+    ///
+    /// Unlike `FEC::encode`, the input does not need to be a multiple of k; the last
+    /// stripe is padded as `FEC::encode` does and the layout records the true length.
+    pub fn encode<F>(&self, input: &[u8], mut output: F) -> Result<StripeLayout, Box<dyn Error>>
+    where
+        F: FnMut(usize, Share),
+    {
+        let layout = self.layout(input.len());
+        for stripe in &layout.stripes {
+            self.encode_stripe(
+                stripe,
+                &input[stripe.offset..stripe.offset + stripe.len],
+                |s| output(stripe.index, s),
+            )?;
+        }
+        Ok(layout)
+    }
+
+    /// `encode_stripe()` encodes the bytes of a single stripe of a layout.
+    pub fn encode_stripe<F>(
+        &self,
+        stripe: &Stripe,
+        data: &[u8],
+        output: F,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        F: FnMut(Share),
+    {
+        if data.len() != stripe.len {
+            return Err(format!("stripe {} must be {} bytes", stripe.index, stripe.len).into());
+        }
+        self.fec.encode(data, output)
+    }
+
+    /// `decode_stripe()` corrects and rebuilds a single stripe from its shares and
+    /// returns its original bytes.
+    pub fn decode_stripe(
+        &self,
+        stripe: &Stripe,
+        shares: Vec<Share>,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        if shares.iter().any(|s| s.data.len() != stripe.share_len) {
+            return Err(format!(
+                "shares of stripe {} must be {} bytes",
+                stripe.index, stripe.share_len
+            )
+            .into());
+        }
+        let mut data = self.fec.decode(vec![], shares)?;
+        data.truncate(stripe.len);
+        Ok(data)
+    }
+
+    /// `decode_stripes()` decodes every stripe independently. `shares[i]` holds the
+    /// shares of stripe `i`; the result has one entry per stripe of the layout.
+    pub fn decode_stripes(
+        &self,
+        layout: &StripeLayout,
+        shares: Vec<Vec<Share>>,
+    ) -> Vec<Result<Vec<u8>, Box<dyn Error>>> {
+        let mut shares = shares.into_iter();
+        layout
+            .stripes
+            .iter()
+            .map(|stripe| match shares.next() {
+                Some(stripe_shares) => self.decode_stripe(stripe, stripe_shares),
+                None => Err("no shares for stripe".into()),
+            })
+            .collect()
+    }
+
+    /// `decode()` decodes all stripes and concatenates them into the original input.
+    /// Every stripe is attempted; if any fail, the error lists all failed stripes.
+    /// `shares` must hold the shares of exactly the stripes of `layout`.
+    pub fn decode(
+        &self,
+        layout: &StripeLayout,
+        shares: Vec<Vec<Share>>,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        if shares.len() != layout.stripes.len() {
+            return Err(format!(
+                "got shares for {} stripes, the layout has {}",
+                shares.len(),
+                layout.stripes.len()
+            )
+            .into());
+        }
+        let mut out = Vec::with_capacity(layout.original_len);
+        let mut failures = Vec::new();
+
+        for (stripe, result) in layout
+            .stripes
+            .iter()
+            .zip(self.decode_stripes(layout, shares))
+        {
+            match result {
+                Ok(data) => out.extend_from_slice(&data),
+                Err(e) => failures.push((stripe.index, e.to_string())),
+            }
+        }
+
+        if !failures.is_empty() {
+            return Err(Box::new(StripeDecodeError { failures }));
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(codec: &StripedCodec, data: &[u8]) -> (StripeLayout, Vec<Vec<Share>>) {
+        let mut stripes: Vec<Vec<Share>> = vec![];
+        let layout = codec
+            .encode(data, |index, share| {
+                if stripes.len() <= index {
+                    stripes.push(vec![]);
+                }
+                stripes[index].push(share);
+            })
+            .unwrap();
+        (layout, stripes)
+    }
+
+    #[test]
+    fn test_striped_roundtrip() {
+        let codec = StripedCodec::new(FEC::new(4, 8).unwrap(), 64).unwrap();
+        let data: Vec<u8> = (0..250u32).map(|i| (i * 7) as u8).collect();
+
+        let (layout, mut stripes) = encode(&codec, &data);
+        assert_eq!(layout.stripes.len(), 4);
+        assert_eq!(layout.stripes[3].offset, 192);
+        assert_eq!(layout.stripes[3].len, 58);
+        assert_eq!(stripes[3][0].data.len(), 15);
+
+        stripes[2][5].data[3] ^= 0xFF;
+        assert_eq!(codec.decode(&layout, stripes.clone()).unwrap(), data);
+
+        // shares of a longer input do not fit the layout
+        let (_, longer) = encode(&codec, &[data.clone(), data].concat());
+        assert!(codec.decode(&layout, longer).is_err());
+        stripes.pop();
+        assert!(codec.decode(&layout, stripes).is_err());
+    }
+
+    #[test]
+    fn test_striped_failure_is_isolated() {
+        let codec = StripedCodec::new(FEC::new(4, 8).unwrap(), 32).unwrap();
+        let data: Vec<u8> = (0..96u32).map(|i| i as u8).collect();
+
+        let (layout, mut stripes) = encode(&codec, &data);
+        // three corrupted shares are beyond what stripe 1 can correct
+        for share in stripes[1].iter_mut().take(3) {
+            share.data[0] ^= 0x11;
+        }
+
+        let results = codec.decode_stripes(&layout, stripes.clone());
+        assert_eq!(results[0].as_ref().unwrap(), &data[..32]);
+        assert!(results[1].is_err());
+        assert_eq!(results[2].as_ref().unwrap(), &data[64..]);
+
+        let err = codec.decode(&layout, stripes).unwrap_err();
+        let err = err.downcast_ref::<StripeDecodeError>().unwrap();
+        assert_eq!(err.failures.len(), 1);
+        assert_eq!(err.failures[0].0, 1);
+    }
+
+    #[test]
+    fn test_stripe_size_must_be_multiple_of_k() {
+        assert!(StripedCodec::new(FEC::new(3, 5).unwrap(), 10).is_err());
+        assert!(StripedCodec::new(FEC::new(3, 5).unwrap(), 0).is_err());
+    }
+}
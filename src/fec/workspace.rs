@@ -0,0 +1,711 @@
+use crate::fec::fec::{MatrixKind, Share, FEC};
+use crate::galois_field::tables::{GF_INVERSE, GF_MUL_TABLE};
+use crate::math::addmul::addmul;
+use crate::math::matmul::tile_size;
+use std::cell::RefCell;
+use std::error::Error;
+
+/// Maximum number of idle workspaces of each kind kept per thread.
+const POOL_SIZE: usize = 8;
+
+thread_local! {
+    static ENCODERS: RefCell<Vec<Encoder>> = const { RefCell::new(Vec::new()) };
+    static DECODERS: RefCell<Vec<Decoder>> = const { RefCell::new(Vec::new()) };
+}
+
+fn mul(a: u8, b: u8) -> u8 {
+    GF_MUL_TABLE[a as usize][b as usize]
+}
+
+fn check_fec(fec: &FEC, k: usize, n: usize, matrix: MatrixKind) -> Result<(), Box<dyn Error>> {
+    if fec.k != k || fec.n != n || fec.matrix != matrix {
+        return Err(format!("workspace was created for a different FEC ({} of {})", k, n).into());
+    }
+    Ok(())
+}
+
+/// `Encoder` owns the scratch space to encode inputs of a fixed share length.
+///
+/// Once created, `encode()` performs no heap allocations: the parity pieces are
+/// computed into a buffer owned by the encoder and handed to the output callback as
+/// slices. Shares are byte-identical to those of `FEC::encode`, whatever `Backend` the
+/// `FEC` is configured with.
+#[derive(Debug)]
+pub struct Encoder {
+    k: usize,
+    n: usize,
+    matrix: MatrixKind,
+    share_len: usize,
+    /// Input padded to `k * share_len`, only used for short inputs.
+    padded: Vec<u8>,
+    /// The n - k parity pieces, back to back.
+    parity: Vec<u8>,
+}
+
+impl Encoder {
+    /// `new()` creates an encoder for `fec` producing shares of `share_len` bytes, i.e.
+    /// for inputs of up to `k * share_len` bytes.
+    pub fn new(fec: &FEC, share_len: usize) -> Encoder {
+        Encoder {
+            k: fec.k,
+            n: fec.n,
+            matrix: fec.matrix,
+            share_len,
+            padded: vec![0u8; fec.k * share_len],
+            parity: vec![0u8; (fec.n - fec.k) * share_len],
+        }
+    }
+
+    pub fn share_len(&self) -> usize {
+        self.share_len
+    }
+
+    /// `encode()` encodes `input` and calls `output` n times with a share number and
+    /// its data. Inputs shorter than `k * share_len` are padded like `FEC::encode` does.
+    pub fn encode<F>(
+        &mut self,
+        fec: &FEC,
+        input: &[u8],
+        mut output: F,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        F: FnMut(usize, &[u8]),
+    {
+        check_fec(fec, self.k, self.n, self.matrix)?;
+        let (k, len) = (self.k, self.share_len);
+        if input.len() > k * len {
+            return Err(format!("input must be at most {} bytes", k * len).into());
+        }
+
+        let input = if input.len() == k * len {
+            input
+        } else {
+            self.padded[..input.len()].copy_from_slice(input);
+            self.padded[input.len()..].fill(b'_');
+            &self.padded
+        };
+
+        for i in 0..k {
+            output(i, &input[i * len..(i + 1) * len]);
+        }
+
+        // same tiling as `matmul_tiled`, without building slice lists
+        let tile = tile_size(self.n);
+        for start in (0..len).step_by(tile) {
+            let end = usize::min(start + tile, len);
+            for (i, out) in self.parity.chunks_mut(len).enumerate() {
+                let out = &mut out[start..end];
+                out.fill(0);
+                for j in 0..k {
+                    let x = &input[j * len + start..j * len + end];
+                    addmul(out, x, fec.enc_matrix[(k + i) * k + j]);
+                }
+            }
+        }
+
+        for (i, data) in self.parity.chunks(len).enumerate() {
+            output(k + i, data);
+        }
+        Ok(())
+    }
+}
+
+/// `Decoder` owns the scratch space to correct and rebuild shares of a fixed length.
+///
+/// `decode()` runs the syndrome check, Berlekamp-Welch correction and rebuild behind
+/// `FEC::decode`, but on buffers owned by the decoder, so once created it performs no
+/// heap allocations on success.
+#[derive(Debug)]
+pub struct Decoder {
+    k: usize,
+    n: usize,
+    matrix: MatrixKind,
+    share_len: usize,
+    /// Evaluation point and multiplier of every share number.
+    points: Vec<u8>,
+    multipliers: Vec<u8>,
+    /// Generator columns of the present shares, standardized in place (k x m).
+    gen: Vec<u8>,
+    /// Syndrome of one parity check row.
+    syndrome: Vec<u8>,
+    /// Columns with a non-zero syndrome.
+    bad: Vec<bool>,
+    solver: ColumnSolver,
+    /// Decoding matrix for the rebuild and its inverse (k x k).
+    m_dec: Vec<u8>,
+    m_inv: Vec<u8>,
+    /// Index into the shares used for each data piece.
+    slots: Vec<usize>,
+    /// The rebuilt data, `k * share_len` bytes once `decode()` has run.
+    data: Vec<u8>,
+}
+
+/// Index of the share used for each data piece, and the k x k decoding matrix.
+pub type DecodingMatrix<'a> = (&'a [usize], &'a [u8]);
+
+/// `ColumnSolver` holds the Berlekamp-Welch system of one column: the system, its
+/// inverse, and the constant and solution columns.
+#[derive(Debug)]
+struct ColumnSolver {
+    system: Vec<u8>,
+    inverse: Vec<u8>,
+    constants: Vec<u8>,
+    solution: Vec<u8>,
+}
+
+impl Decoder {
+    /// `new()` creates a decoder for shares of `share_len` bytes produced by `fec`.
+    pub fn new(fec: &FEC, share_len: usize) -> Decoder {
+        let (k, n) = (fec.k, fec.n);
+        Decoder {
+            k,
+            n,
+            matrix: fec.matrix,
+            share_len,
+            points: (0..n).map(|i| fec.eval_point(i).0).collect(),
+            multipliers: (0..n).map(|i| fec.multiplier(i).0).collect(),
+            gen: vec![0u8; k * n],
+            syndrome: vec![0u8; share_len],
+            bad: vec![false; share_len],
+            solver: ColumnSolver::new(n),
+            m_dec: vec![0u8; k * k],
+            m_inv: vec![0u8; k * k],
+            slots: vec![0; k],
+            data: Vec::new(),
+        }
+    }
+
+    pub fn share_len(&self) -> usize {
+        self.share_len
+    }
+
+    /// `decode()` corrects `shares` in place (sorting them by number) and rebuilds the
+    /// data passed to the corresponding encode call, padding included.
+    pub fn decode(&mut self, fec: &FEC, shares: &mut [Share]) -> Result<&[u8], Box<dyn Error>> {
+        self.correct(fec, shares)?;
+        self.rebuild(fec, shares)?;
+        Ok(&self.data)
+    }
+
+    /// `correct()` corrects `shares` in place, sorting them by number. It backs
+    /// `FEC::correct`.
+    pub fn correct(&mut self, fec: &FEC, shares: &mut [Share]) -> Result<(), Box<dyn Error>> {
+        self.check_shares(fec, shares)?;
+        let (k, m) = (self.k, shares.len());
+
+        self.standardize_generator(fec, shares)?;
+
+        // parity check row i is (P^T row i | e_i), see `GfMat::parity`
+        self.bad.fill(false);
+        for i in 0..m - k {
+            self.syndrome.copy_from_slice(&shares[k + i].data);
+            for (j, share) in shares[..k].iter().enumerate() {
+                addmul(&mut self.syndrome, &share.data, self.gen[j * m + k + i]);
+            }
+            for (bad, &s) in self.bad.iter_mut().zip(self.syndrome.iter()) {
+                *bad |= s != 0;
+            }
+        }
+
+        #[cfg(feature = "parallel")]
+        {
+            let bad = self.bad.iter().filter(|&&b| b).count();
+            if bad >= 2 * fec.min_parallel_len {
+                return self.correct_parallel(shares);
+            }
+        }
+
+        for col in 0..self.share_len {
+            if !self.bad[col] {
+                continue;
+            }
+            let p_poly = self
+                .solver
+                .solve(k, &self.points, &self.multipliers, shares, col)?;
+            for share in shares.iter_mut() {
+                let value = evaluate(p_poly, self.points[share.number]);
+                share.data[col] = mul(self.multipliers[share.number], value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes the bad columns on rayon's pool, each worker with its own solver.
+    #[cfg(feature = "parallel")]
+    fn correct_parallel(&self, shares: &mut [Share]) -> Result<(), Box<dyn Error>> {
+        use rayon::prelude::*;
+
+        let columns: Vec<usize> = (0..self.share_len).filter(|&c| self.bad[c]).collect();
+        let present: &[Share] = shares;
+        let decoded = columns
+            .par_iter()
+            .map_init(
+                || ColumnSolver::new(self.n),
+                |solver, &col| {
+                    let p_poly = solver
+                        .solve(self.k, &self.points, &self.multipliers, present, col)
+                        .map_err(|e| e.to_string())?;
+                    Ok(present
+                        .iter()
+                        .map(|s| {
+                            let value = evaluate(p_poly, self.points[s.number]);
+                            mul(self.multipliers[s.number], value)
+                        })
+                        .collect())
+                },
+            )
+            .collect::<Result<Vec<Vec<u8>>, String>>()?;
+
+        for (&col, values) in columns.iter().zip(decoded) {
+            for (share, value) in shares.iter_mut().zip(values) {
+                share.data[col] = value;
+            }
+        }
+        Ok(())
+    }
+
+    /// `decode_column()` decodes column `col` of `shares` and returns the corrected value
+    /// of every share number, present or not. It backs `FEC::berlekamp_welch`.
+    pub fn decode_column(
+        &mut self,
+        shares: &[Share],
+        col: usize,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        if shares.len() < self.k {
+            return Err("Must specify at least the number of required shares".into());
+        }
+        if shares
+            .iter()
+            .any(|s| s.number >= self.n || s.data.len() <= col)
+        {
+            return Err(format!(
+                "every share must have a number below {} and column {}",
+                self.n, col
+            )
+            .into());
+        }
+        let p_poly = self
+            .solver
+            .solve(self.k, &self.points, &self.multipliers, shares, col)?;
+        Ok((0..self.n)
+            .map(|i| mul(self.multipliers[i], evaluate(p_poly, self.points[i])))
+            .collect())
+    }
+
+    /// Sorts `shares` and checks they are at least k distinct shares of the decoder's
+    /// length from `fec`.
+    fn check_shares(&self, fec: &FEC, shares: &mut [Share]) -> Result<(), Box<dyn Error>> {
+        check_fec(fec, self.k, self.n, self.matrix)?;
+        if shares.len() < self.k {
+            return Err("Must specify at least the number of required shares".into());
+        }
+        shares.sort_unstable();
+        for (i, share) in shares.iter().enumerate() {
+            if share.number >= self.n || (i > 0 && shares[i - 1].number == share.number) {
+                return Err(format!("invalid or duplicate share number {}", share.number).into());
+            }
+            if share.data.len() != self.share_len {
+                return Err(format!("shares must be {} bytes", self.share_len).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds the k x m generator restricted to the present shares and brings it into
+    /// the form [I | P] the same way `GfMat::standardize` does.
+    fn standardize_generator(&mut self, fec: &FEC, shares: &[Share]) -> Result<(), Box<dyn Error>> {
+        let (k, n, m) = (self.k, self.n, shares.len());
+        let gen = &mut self.gen[..k * m];
+        for i in 0..k {
+            for (j, share) in shares.iter().enumerate() {
+                gen[i * m + j] = fec.vand_matrix[i * n + share.number];
+            }
+        }
+
+        for i in 0..k {
+            let mut p_row = i;
+            while p_row < k && gen[p_row * m + i] == 0 {
+                p_row += 1;
+            }
+            if p_row == k {
+                continue;
+            }
+            if p_row != i {
+                for c in 0..m {
+                    gen.swap(i * m + c, p_row * m + c);
+                }
+            }
+            let inv = GF_INVERSE[gen[i * m + i] as usize];
+            for c in 0..m {
+                gen[i * m + c] = mul(gen[i * m + c], inv);
+            }
+            for j in 0..k {
+                let factor = gen[j * m + i];
+                if j == i || factor == 0 {
+                    continue;
+                }
+                for c in 0..m {
+                    gen[j * m + c] ^= mul(factor, gen[i * m + c]);
+                }
+            }
+        }
+
+        for i in 0..k {
+            if gen[i * m + i] != 1 {
+                return Err("Matrix standardizing failed".into());
+            }
+        }
+        Ok(())
+    }
+
+    /// `rebuild()` writes the data into the decoder. `shares` must be sorted, as
+    /// `correct()` leaves them.
+    fn rebuild(&mut self, fec: &FEC, shares: &[Share]) -> Result<(), Box<dyn Error>> {
+        let (k, len) = (self.k, self.share_len);
+        self.decoding_matrix(fec, shares)?;
+        if self.data.len() != k * len {
+            self.data.resize(k * len, 0);
+        }
+
+        for i in 0..k {
+            let out = &mut self.data[i * len..(i + 1) * len];
+            let share = &shares[self.slots[i]];
+            if share.number == i {
+                out.copy_from_slice(&share.data);
+                continue;
+            }
+            out.fill(0);
+            for j in 0..k {
+                addmul(out, &shares[self.slots[j]].data, self.m_inv[i * k + j]);
+            }
+        }
+        Ok(())
+    }
+
+    /// `decoding_matrix()` picks the k shares the data is rebuilt from and returns, for
+    /// each data piece, the index of its share and the row of the decoding matrix that
+    /// rebuilds it from those shares. Data pieces that are present are used as-is, the
+    /// rest come from parity shares. `shares` must be sorted and distinct.
+    ///
+    /// It backs `FEC::rebuild`, which multiplies with the configured `Backend`.
+    pub fn decoding_matrix(
+        &mut self,
+        fec: &FEC,
+        shares: &[Share],
+    ) -> Result<DecodingMatrix<'_>, Box<dyn Error>> {
+        check_fec(fec, self.k, self.n, self.matrix)?;
+        let k = self.k;
+        if shares.len() < k {
+            return Err("Not enough Shares!".into());
+        }
+
+        let mut next_parity = shares.partition_point(|s| s.number < k);
+        let mut present = 0;
+        for i in 0..k {
+            if present < shares.len() && shares[present].number == i {
+                self.slots[i] = present;
+                present += 1;
+            } else {
+                self.slots[i] = next_parity;
+                next_parity += 1;
+            }
+        }
+
+        for i in 0..k {
+            let number = shares[self.slots[i]].number;
+            self.m_dec[i * k..(i + 1) * k]
+                .copy_from_slice(&fec.enc_matrix[number * k..(number + 1) * k]);
+        }
+        invert(&mut self.m_dec, &mut self.m_inv, k)?;
+        Ok((&self.slots, &self.m_inv))
+    }
+}
+
+impl ColumnSolver {
+    fn new(n: usize) -> ColumnSolver {
+        ColumnSolver {
+            system: vec![0u8; n * n],
+            inverse: vec![0u8; n * n],
+            constants: vec![0u8; n],
+            solution: vec![0u8; n],
+        }
+    }
+
+    /// `solve()` finds the data polynomial through column `col` of `shares` with
+    /// Berlekamp-Welch and returns its coefficients, highest first.
+    fn solve(
+        &mut self,
+        k: usize,
+        points: &[u8],
+        multipliers: &[u8],
+        shares: &[Share],
+        col: usize,
+    ) -> Result<&[u8], Box<dyn Error>> {
+        let e = (shares.len() - k) / 2; // deg of E polynomial
+        let q = e + k; // deg of Q polynomial
+        if e == 0 {
+            return Err("Not enough shares!".into());
+        }
+
+        let dim = q + e;
+        let s = &mut self.system[..dim * dim];
+        let a = &mut self.inverse[..dim * dim];
+        let f = &mut self.constants[..dim];
+        s.fill(0);
+        a.fill(0);
+
+        for i in 0..dim {
+            let number = shares[i].number;
+            let x_i = points[number];
+            // undo the column multiplier so r_i is a plain evaluation of the polynomial
+            let r_i = mul(
+                shares[i].data[col],
+                GF_INVERSE[multipliers[number] as usize],
+            );
+
+            let mut x_pow = 1u8;
+            for j in 0..q {
+                if j == e {
+                    f[i] = mul(x_pow, r_i);
+                }
+                s[i * dim + j] = x_pow;
+                if j < e {
+                    s[i * dim + q + j] = mul(x_pow, r_i);
+                }
+                x_pow = mul(x_pow, x_i);
+            }
+            a[i * dim + i] = 1;
+        }
+
+        invert_with(s, a, dim);
+
+        let u = &mut self.solution[..dim];
+        for i in 0..dim {
+            u[i] = (0..dim).fold(0u8, |acc, j| acc ^ mul(a[i * dim + j], f[j]));
+        }
+
+        // Q is stored lowest coefficient first in u[..q] and E (monic, degree e) in u[q..];
+        // divide Q by E in place, highest coefficient first
+        u[..q].reverse();
+        u[q..].reverse();
+        let (q_poly, e_poly) = u.split_at_mut(q);
+        for i in 0..q - e {
+            let coef = q_poly[i];
+            if coef == 0 {
+                continue;
+            }
+            // the leading coefficient of E is 1
+            for j in 0..e {
+                q_poly[i + 1 + j] ^= mul(coef, e_poly[j]);
+            }
+        }
+        if q_poly[q - e..].iter().any(|&c| c != 0) {
+            return Err("too many errors to reconstruct".into());
+        }
+        Ok(&q_poly[..q - e])
+    }
+}
+
+/// Evaluates the polynomial `p` (highest coefficient first) at `x`.
+fn evaluate(p: &[u8], x: u8) -> u8 {
+    p.iter().fold(0u8, |acc, &c| mul(acc, x) ^ c)
+}
+
+/// Gauss-Jordan elimination of the Berlekamp-Welch system `s` into `a`, on row-major
+/// `dim x dim` byte matrices. Zero pivots are skipped.
+fn invert_with(s: &mut [u8], a: &mut [u8], dim: usize) {
+    let addmul_row = |m: &mut [u8], from: usize, to: usize, val: u8| {
+        for c in 0..dim {
+            m[to * dim + c] ^= mul(val, m[from * dim + c]);
+        }
+    };
+
+    for i in 0..dim {
+        let mut p_row = i;
+        let mut p_val = s[i * dim + i];
+        for j in (i + 1)..dim {
+            if p_val == 0 {
+                p_row = j;
+                p_val = s[j * dim + i];
+            }
+        }
+        if p_val == 0 {
+            continue;
+        }
+
+        if p_row != i {
+            for c in 0..dim {
+                s.swap(i * dim + c, p_row * dim + c);
+                a.swap(i * dim + c, p_row * dim + c);
+            }
+        }
+
+        let inv = GF_INVERSE[p_val as usize];
+        for c in 0..dim {
+            s[i * dim + c] = mul(s[i * dim + c], inv);
+            a[i * dim + c] = mul(a[i * dim + c], inv);
+        }
+
+        for j in (i + 1)..dim {
+            let leading = s[j * dim + i];
+            addmul_row(s, i, j, leading);
+            addmul_row(a, i, j, leading);
+        }
+    }
+
+    for i in (1..dim).rev() {
+        for j in (0..i).rev() {
+            let trailing = s[j * dim + i];
+            addmul_row(s, i, j, trailing);
+            addmul_row(a, i, j, trailing);
+        }
+    }
+}
+
+/// Inverts the `k x k` matrix `m` into `inv` by Gauss-Jordan elimination (`m` is
+/// destroyed).
+fn invert(m: &mut [u8], inv: &mut [u8], k: usize) -> Result<(), Box<dyn Error>> {
+    inv.fill(0);
+    for i in 0..k {
+        inv[i * (k + 1)] = 1;
+    }
+
+    for i in 0..k {
+        let p_row = (i..k)
+            .find(|&r| m[r * k + i] != 0)
+            .ok_or("Matrix inversion failed")?;
+        if p_row != i {
+            for c in 0..k {
+                m.swap(i * k + c, p_row * k + c);
+                inv.swap(i * k + c, p_row * k + c);
+            }
+        }
+
+        let scale = GF_INVERSE[m[i * k + i] as usize];
+        for c in 0..k {
+            m[i * k + c] = mul(m[i * k + c], scale);
+            inv[i * k + c] = mul(inv[i * k + c], scale);
+        }
+
+        for r in 0..k {
+            let factor = m[r * k + i];
+            if r == i || factor == 0 {
+                continue;
+            }
+            for c in 0..k {
+                m[r * k + c] ^= mul(factor, m[i * k + c]);
+                inv[r * k + c] ^= mul(factor, inv[i * k + c]);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `with_encoder()` runs `f` with an `Encoder` for `fec` and `share_len` taken from a
+/// per-thread pool, creating one if none is idle, and returns it to the pool after.
+pub fn with_encoder<R>(fec: &FEC, share_len: usize, f: impl FnOnce(&mut Encoder) -> R) -> R {
+    let pooled = ENCODERS.with(|pool| {
+        let mut pool = pool.borrow_mut();
+        let found = pool.iter().position(|e| {
+            (e.k, e.n, e.matrix, e.share_len) == (fec.k, fec.n, fec.matrix, share_len)
+        });
+        found.map(|i| pool.swap_remove(i))
+    });
+    let mut encoder = pooled.unwrap_or_else(|| Encoder::new(fec, share_len));
+
+    let result = f(&mut encoder);
+
+    ENCODERS.with(|pool| {
+        let mut pool = pool.borrow_mut();
+        if pool.len() < POOL_SIZE {
+            pool.push(encoder);
+        }
+    });
+    result
+}
+
+/// `with_decoder()` is the `Decoder` counterpart of `with_encoder()`.
+pub fn with_decoder<R>(fec: &FEC, share_len: usize, f: impl FnOnce(&mut Decoder) -> R) -> R {
+    let pooled = DECODERS.with(|pool| {
+        let mut pool = pool.borrow_mut();
+        let found = pool.iter().position(|d| {
+            (d.k, d.n, d.matrix, d.share_len) == (fec.k, fec.n, fec.matrix, share_len)
+        });
+        found.map(|i| pool.swap_remove(i))
+    });
+    let mut decoder = pooled.unwrap_or_else(|| Decoder::new(fec, share_len));
+
+    let result = f(&mut decoder);
+
+    DECODERS.with(|pool| {
+        let mut pool = pool.borrow_mut();
+        if pool.len() < POOL_SIZE {
+            pool.push(decoder);
+        }
+    });
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_shares(fec: &FEC, data: &[u8]) -> Vec<Share> {
+        let mut shares = vec![];
+        fec.encode(data, |s: Share| shares.push(s)).unwrap();
+        shares
+    }
+
+    #[test]
+    fn test_encoder_matches_fec_encode() {
+        for matrix in [MatrixKind::Vandermonde, MatrixKind::Cauchy] {
+            let fec = FEC::new_with_matrix(5, 9, matrix).unwrap();
+            let data: Vec<u8> = (0..498u32).map(|i| (i * 17) as u8).collect();
+            let expected = encode_shares(&fec, &data);
+
+            let mut encoder = Encoder::new(&fec, 100);
+            let mut got = vec![];
+            encoder
+                .encode(&fec, &data, |number, d| got.push((number, d.to_vec())))
+                .unwrap();
+
+            assert_eq!(got.len(), 9);
+            for ((number, d), share) in got.iter().zip(expected.iter()) {
+                assert_eq!(*number, share.number);
+                assert_eq!(d, &share.data);
+            }
+        }
+    }
+
+    #[test]
+    fn test_decoder_matches_fec_decode() {
+        for matrix in [MatrixKind::Vandermonde, MatrixKind::Cauchy] {
+            let fec = FEC::new_with_matrix(4, 10, matrix).unwrap();
+            let data: Vec<u8> = (0..400u32).map(|i| (i * 29 + 3) as u8).collect();
+            let mut shares = encode_shares(&fec, &data);
+
+            // one corrupted data share, two corrupted parity shares, one missing share
+            shares[0].data[7] ^= 0x42;
+            shares[6].data[7] ^= 0x01;
+            shares[8].data[99] = 0;
+            shares.remove(2);
+            shares.reverse();
+
+            let expected = fec.decode(vec![], shares.clone()).unwrap();
+            let mut decoder = Decoder::new(&fec, 100);
+            assert_eq!(decoder.decode(&fec, &mut shares).unwrap(), &expected[..]);
+            assert_eq!(expected, data);
+        }
+    }
+
+    #[test]
+    fn test_decoder_too_many_errors() {
+        let fec = FEC::new(4, 8).unwrap();
+        let mut shares = encode_shares(&fec, b"hello, world! __");
+        for share in shares.iter_mut().take(3) {
+            share.data[0] = b'?';
+        }
+        let mut decoder = Decoder::new(&fec, 4);
+        assert!(decoder.decode(&fec, &mut shares).is_err());
+    }
+}
@@ -0,0 +1,634 @@
+use crate::fec::fec::Share;
+use crate::fec::striped::StripedCodec;
+use crate::format::share_file::{ShareFile, ShareHeader, ShareWriter, HEADER_LEN};
+use crate::hash::crc32c::Crc32c;
+use crate::stream::decoder::StreamDecoder;
+use crate::stream::encoder::StreamEncoder;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+/// Version of the manifest layout.
+pub const ARCHIVE_VERSION: u32 = 1;
+/// Name of the manifest share in every output directory.
+pub const MANIFEST_FILE: &str = "manifest.share";
+/// Name of the pack share in every output directory.
+pub const PACK_FILE: &str = "pack.share";
+
+/// `EntryKind` is what an archived path is, with what is needed to restore it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EntryKind {
+    Dir,
+    /// The contents are bytes `offset..offset + len` of the pack, which lie in the
+    /// `stripes` stripes starting at `first_stripe`.
+    File {
+        offset: u64,
+        len: u64,
+        /// CRC-32C of the contents.
+        checksum: u32,
+        first_stripe: u64,
+        stripes: u64,
+    },
+    Symlink {
+        target: String,
+    },
+}
+
+/// `Entry` is one archived path.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Path relative to the archived directory, with `/` separators.
+    pub path: String,
+    /// Permission bits (`0o7777` on Unix).
+    pub mode: u32,
+    #[serde(flatten)]
+    pub kind: EntryKind,
+}
+
+/// `Manifest` describes an archived directory tree.
+///
+/// The contents of all files are concatenated, in the order of `entries`, into one
+/// pack that is erasure-coded in stripes, so that small files share stripes and large
+/// files span many. The manifest itself is erasure-coded with the same `FEC`, so that
+/// the tree can be restored from any k of the n output directories.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub stripe_size: usize,
+    /// Length of the pack.
+    pub pack_len: u64,
+    /// Directories come before their contents.
+    pub entries: Vec<Entry>,
+}
+
+impl Manifest {
+    /// Number of regular files.
+    pub fn files(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|e| matches!(e.kind, EntryKind::File { .. }))
+            .count()
+    }
+}
+
+/// `Restored` is the result of `restore_tree()`.
+#[derive(Debug)]
+pub struct Restored {
+    pub manifest: Manifest,
+    /// Header of a manifest share, which carries the code parameters.
+    pub header: ShareHeader,
+    /// Share numbers for which no usable output directory was found.
+    pub missing: Vec<usize>,
+    /// Share numbers whose pack failed while decoding and was treated as an erasure.
+    pub dropped: Vec<usize>,
+}
+
+fn invalid(message: impl Into<String>) -> Box<dyn Error> {
+    io::Error::new(io::ErrorKind::InvalidData, message.into()).into()
+}
+
+#[cfg(unix)]
+fn mode_of(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn mode_of(metadata: &fs::Metadata) -> u32 {
+    if metadata.permissions().readonly() {
+        0o444
+    } else {
+        0o644
+    }
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_readonly(mode & 0o222 == 0);
+    fs::set_permissions(path, permissions)
+}
+
+#[cfg(unix)]
+fn make_symlink(target: &str, path: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, path)
+}
+
+#[cfg(not(unix))]
+fn make_symlink(_target: &str, path: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!(
+            "{}: symbolic links are only restored on Unix",
+            path.display()
+        ),
+    ))
+}
+
+/// `relative_path()` checks that an entry path stays inside the restored directory and
+/// returns it as a `PathBuf`.
+fn relative_path(path: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let relative = PathBuf::from(path);
+    let safe = !path.is_empty()
+        && relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)));
+    if !safe {
+        return Err(invalid(format!("unsafe path {:?} in manifest", path)));
+    }
+    Ok(relative)
+}
+
+/// `check_no_symlinks()` fails if `relative` below `dst`, or a directory on the way to
+/// it, is a symbolic link, so that a link restored earlier, or already in `dst`, cannot
+/// redirect a write outside of `dst`.
+fn check_no_symlinks(dst: &Path, relative: &Path) -> Result<(), Box<dyn Error>> {
+    let mut path = dst.to_path_buf();
+    for component in relative.components() {
+        path.push(component);
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                return Err(invalid(format!(
+                    "{}: refusing to restore through the symbolic link {}",
+                    relative.display(),
+                    path.display()
+                )));
+            }
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+/// `walk()` appends the entries below `dir` to `entries`, sorted by name. File contents
+/// are written to `pack` as they are found. Paths that are neither files, directories
+/// nor symbolic links are skipped.
+fn walk<W: Write>(
+    root: &Path,
+    dir: &Path,
+    stripe_size: u64,
+    pack: &mut StreamEncoder<W>,
+    entries: &mut Vec<Entry>,
+) -> Result<(), Box<dyn Error>> {
+    let mut children: Vec<PathBuf> = fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<io::Result<_>>()?;
+    children.sort();
+
+    for child in children {
+        let metadata = fs::symlink_metadata(&child)?;
+        let path = child
+            .strip_prefix(root)?
+            .to_str()
+            .ok_or_else(|| format!("{}: path is not valid UTF-8", child.display()))?
+            .replace(std::path::MAIN_SEPARATOR, "/");
+        let mode = mode_of(&metadata);
+
+        if metadata.is_dir() {
+            entries.push(Entry {
+                path,
+                mode,
+                kind: EntryKind::Dir,
+            });
+            walk(root, &child, stripe_size, pack, entries)?;
+        } else if metadata.is_file() {
+            let offset = pack.len();
+            let mut crc = Crc32c::new();
+            let mut file = File::open(&child)?;
+            let mut buf = vec![0u8; 64 * 1024];
+            loop {
+                let read = file.read(&mut buf)?;
+                if read == 0 {
+                    break;
+                }
+                crc.update(&buf[..read]);
+                pack.write_all(&buf[..read])?;
+            }
+            let len = pack.len() - offset;
+            let first_stripe = offset / stripe_size;
+            let stripes = if len == 0 {
+                0
+            } else {
+                (offset + len - 1) / stripe_size - first_stripe + 1
+            };
+            entries.push(Entry {
+                path,
+                mode,
+                kind: EntryKind::File {
+                    offset,
+                    len,
+                    checksum: crc.finish(),
+                    first_stripe,
+                    stripes,
+                },
+            });
+        } else if metadata.file_type().is_symlink() {
+            let target = fs::read_link(&child)?;
+            let target = target
+                .to_str()
+                .ok_or_else(|| format!("{}: link target is not valid UTF-8", child.display()))?
+                .to_string();
+            entries.push(Entry {
+                path,
+                mode,
+                kind: EntryKind::Symlink { target },
+            });
+        }
+    }
+    Ok(())
+}
+
+/// `archive_tree()` archives the directory tree at `src` into `outputs`, one directory
+/// per share number, which must be `codec.fec().n` directories. Each receives its share
+/// of the pack in `PACK_FILE` and of the manifest in `MANIFEST_FILE`; the manifest is
+/// written last, so a directory with a manifest holds a complete archive.
+pub fn archive_tree(
+    src: &Path,
+    outputs: &[PathBuf],
+    codec: StripedCodec,
+) -> Result<Manifest, Box<dyn Error>> {
+    let fec = codec.fec();
+    if outputs.len() != fec.n {
+        return Err(format!(
+            "expected {} output directories, got {}",
+            fec.n,
+            outputs.len()
+        )
+        .into());
+    }
+    if !fs::metadata(src)?.is_dir() {
+        return Err(format!("{} is not a directory", src.display()).into());
+    }
+
+    let stripe_size = codec.stripe_size();
+    let mut writers = Vec::with_capacity(outputs.len());
+    for (number, dir) in outputs.iter().enumerate() {
+        fs::create_dir_all(dir)?;
+        // a stale manifest must not describe the new pack if archiving fails
+        match fs::remove_file(dir.join(MANIFEST_FILE)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        let file = File::create(dir.join(PACK_FILE))?;
+        writers.push(ShareWriter::new(
+            BufWriter::new(file),
+            fec,
+            number,
+            stripe_size,
+        )?);
+    }
+
+    let mut entries = Vec::new();
+    let mut pack = StreamEncoder::new(codec, writers)?;
+    walk(src, src, stripe_size as u64, &mut pack, &mut entries)?;
+
+    let manifest = Manifest {
+        version: ARCHIVE_VERSION,
+        stripe_size,
+        pack_len: pack.len(),
+        entries,
+    };
+    let bytes = serde_json::to_vec(&manifest)?;
+    let fec = pack.codec().fec();
+    let mut manifest_files = Vec::with_capacity(fec.n);
+    fec.encode(&bytes, |s| {
+        manifest_files.push(ShareFile::new(fec, 0, bytes.len() as u64, s))
+    })?;
+
+    let (writers, pack_len) = pack.finish()?;
+    for writer in writers {
+        writer.finish(pack_len)?;
+    }
+    for file in manifest_files {
+        fs::write(
+            outputs[file.header.number].join(MANIFEST_FILE),
+            file.to_bytes(),
+        )?;
+    }
+    Ok(manifest)
+}
+
+/// `read_manifest()` decodes the manifest from the output directories in `dirs`, which
+/// may be any subset of them in any order. Manifest shares that cannot be read or fail
+/// their checksum are ignored; of the rest, those of the most common encoding are used.
+/// It returns the manifest and the header of one of its shares.
+pub fn read_manifest(dirs: &[PathBuf]) -> Result<(Manifest, ShareHeader), Box<dyn Error>> {
+    let files: Vec<ShareFile> = dirs
+        .iter()
+        .filter_map(|dir| fs::read(dir.join(MANIFEST_FILE)).ok())
+        .filter_map(|bytes| ShareFile::from_bytes(&bytes).ok())
+        .collect();
+    let header = files
+        .iter()
+        .max_by_key(|f| {
+            files
+                .iter()
+                .filter(|o| o.header.is_compatible(&f.header))
+                .count()
+        })
+        .map(|f| f.header)
+        .ok_or_else(|| invalid("no readable manifest share found"))?;
+
+    let mut shares: Vec<Share> = Vec::new();
+    for file in files {
+        let number = file.header.number;
+        if file.header.is_compatible(&header) && !shares.iter().any(|s| s.number == number) {
+            shares.push(file.into_share());
+        }
+    }
+    let fec = header.fec()?;
+    if shares.len() < fec.k {
+        return Err(invalid(format!(
+            "found {} manifest shares, at least {} are needed",
+            shares.len(),
+            fec.k
+        )));
+    }
+    let mut bytes = fec.decode(vec![], shares)?;
+    bytes.truncate(header.original_len as usize);
+    let manifest: Manifest = serde_json::from_slice(&bytes)?;
+    if manifest.version != ARCHIVE_VERSION {
+        return Err(invalid(format!(
+            "unsupported archive version {}",
+            manifest.version
+        )));
+    }
+    Ok((manifest, header))
+}
+
+/// `open_packs()` opens the pack share of every directory whose header matches the
+/// manifest, positioned after the header, indexed by share number.
+fn open_packs(
+    dirs: &[PathBuf],
+    manifest: &Manifest,
+    header: &ShareHeader,
+) -> Vec<Option<BufReader<File>>> {
+    let mut packs: Vec<Option<BufReader<File>>> = (0..header.n).map(|_| None).collect();
+    for dir in dirs {
+        let Ok(file) = File::open(dir.join(PACK_FILE)) else {
+            continue;
+        };
+        let mut reader = BufReader::new(file);
+        let mut bytes = [0u8; HEADER_LEN];
+        if reader.read_exact(&mut bytes).is_err() {
+            continue;
+        }
+        let Ok(pack) = ShareHeader::from_bytes(&bytes) else {
+            continue;
+        };
+        let matches = pack.k == header.k
+            && pack.n == header.n
+            && pack.matrix == header.matrix
+            && pack.stripe_size == manifest.stripe_size
+            && pack.original_len == manifest.pack_len;
+        if matches && packs[pack.number].is_none() {
+            packs[pack.number] = Some(reader);
+        }
+    }
+    packs
+}
+
+/// `restore_tree()` restores the tree archived in the output directories `dirs`, any
+/// k of which suffice, into `dst`. The pack is decoded stripe by stripe and every file
+/// is checked against the checksum in the manifest.
+///
+/// No entry is restored through a symbolic link: an entry whose path, or a parent of
+/// it, already is a link in `dst` is an error.
+pub fn restore_tree(dirs: &[PathBuf], dst: &Path) -> Result<Restored, Box<dyn Error>> {
+    let (manifest, header) = read_manifest(dirs)?;
+    let paths = manifest
+        .entries
+        .iter()
+        .map(|e| relative_path(&e.path))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let packs = open_packs(dirs, &manifest, &header);
+    let missing = (0..header.n).filter(|&i| packs[i].is_none()).collect();
+    let codec = StripedCodec::new(header.fec()?, manifest.stripe_size)?;
+    let mut pack = StreamDecoder::new(codec, packs, manifest.pack_len)?;
+
+    fs::create_dir_all(dst)?;
+    let mut position = 0;
+    for (entry, path) in manifest.entries.iter().zip(&paths) {
+        check_no_symlinks(dst, path)?;
+        let path = dst.join(path);
+        match &entry.kind {
+            EntryKind::Dir => fs::create_dir_all(&path)?,
+            EntryKind::Symlink { target } => make_symlink(target, &path)?,
+            EntryKind::File {
+                offset,
+                len,
+                checksum,
+                ..
+            } => {
+                if *offset != position {
+                    return Err(invalid(format!(
+                        "{}: expected at pack offset {}, found {}",
+                        entry.path, position, offset
+                    )));
+                }
+                let mut out = BufWriter::new(File::create(&path)?);
+                let mut contents = (&mut pack).take(*len);
+                let mut crc = Crc32c::new();
+                let mut buf = vec![0u8; 64 * 1024];
+                loop {
+                    let read = contents.read(&mut buf)?;
+                    if read == 0 {
+                        break;
+                    }
+                    crc.update(&buf[..read]);
+                    out.write_all(&buf[..read])?;
+                }
+                out.flush()?;
+                position += len;
+                if contents.limit() != 0 {
+                    return Err(invalid(format!("{}: pack ended early", entry.path)));
+                }
+                if crc.finish() != *checksum {
+                    return Err(invalid(format!(
+                        "{}: contents do not match the manifest checksum",
+                        entry.path
+                    )));
+                }
+                set_mode(&path, entry.mode)?;
+            }
+        }
+    }
+
+    // directories last and deepest first, so read-only ones do not block their contents
+    for (entry, path) in manifest.entries.iter().zip(&paths).rev() {
+        if entry.kind == EntryKind::Dir {
+            set_mode(&dst.join(path), entry.mode)?;
+        }
+    }
+
+    let dropped = pack.dropped().iter().map(|(number, _)| *number).collect();
+    Ok(Restored {
+        manifest,
+        header,
+        missing,
+        dropped,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fec::fec::FEC;
+
+    /// A scratch directory removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let dir =
+                std::env::temp_dir().join(format!("rs-archive-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn sample_tree(root: &Path) -> Vec<(&'static str, Vec<u8>)> {
+        let files = vec![
+            ("a.txt", b"small file".to_vec()),
+            ("empty", Vec::new()),
+            ("sub/b.bin", (0..5000u32).map(|i| (i * 7) as u8).collect()),
+            ("sub/deeper/c.txt", b"another small one".to_vec()),
+        ];
+        fs::create_dir_all(root.join("sub/deeper")).unwrap();
+        fs::create_dir_all(root.join("empty-dir")).unwrap();
+        for (path, data) in &files {
+            fs::write(root.join(path), data).unwrap();
+        }
+        #[cfg(unix)]
+        {
+            set_mode(&root.join("a.txt"), 0o640).unwrap();
+            make_symlink("../a.txt", &root.join("sub/link")).unwrap();
+        }
+        files
+    }
+
+    #[test]
+    fn test_archive_and_restore_from_k_outputs() {
+        let tmp = TempDir::new("round-trip");
+        let src = tmp.0.join("src");
+        let files = sample_tree(&src);
+        let outputs: Vec<PathBuf> = (0..5).map(|i| tmp.0.join(format!("out{}", i))).collect();
+
+        let codec = StripedCodec::new(FEC::new(3, 5).unwrap(), 999).unwrap();
+        let manifest = archive_tree(&src, &outputs, codec).unwrap();
+        assert_eq!(manifest.files(), 4);
+        assert_eq!(manifest.entries[0].path, "a.txt");
+        let big = manifest
+            .entries
+            .iter()
+            .find(|e| e.path == "sub/b.bin")
+            .unwrap();
+        assert!(matches!(big.kind, EntryKind::File { stripes: 6, .. }));
+
+        // lose two output directories entirely
+        fs::remove_dir_all(&outputs[1]).unwrap();
+        fs::remove_dir_all(&outputs[3]).unwrap();
+
+        let dst = tmp.0.join("restored");
+        let restored = restore_tree(&outputs, &dst).unwrap();
+        assert_eq!(restored.missing, vec![1, 3]);
+        assert_eq!(restored.manifest, manifest);
+        for (path, data) in &files {
+            assert_eq!(&fs::read(dst.join(path)).unwrap(), data, "{}", path);
+        }
+        assert!(dst.join("empty-dir").is_dir());
+        #[cfg(unix)]
+        {
+            assert_eq!(mode_of(&fs::metadata(dst.join("a.txt")).unwrap()), 0o640);
+            let link = fs::read_link(dst.join("sub/link")).unwrap();
+            assert_eq!(link, Path::new("../a.txt"));
+        }
+
+        fs::remove_dir_all(&outputs[4]).unwrap();
+        assert!(restore_tree(&outputs, &tmp.0.join("again")).is_err());
+    }
+
+    /// Replaces the manifest in `outputs` with `manifest`, encoded like `archive_tree()`
+    /// does.
+    fn rewrite_manifest(outputs: &[PathBuf], manifest: &Manifest, fec: &FEC) {
+        let bytes = serde_json::to_vec(manifest).unwrap();
+        fec.encode(&bytes, |s| {
+            let file = ShareFile::new(fec, 0, bytes.len() as u64, s);
+            fs::write(
+                outputs[file.header.number].join(MANIFEST_FILE),
+                file.to_bytes(),
+            )
+            .unwrap();
+        })
+        .unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_restore_refuses_to_write_through_symlinks() {
+        let tmp = TempDir::new("symlinks");
+        let src = tmp.0.join("src");
+        fs::create_dir_all(src.join("x")).unwrap();
+        fs::write(src.join("x/passwd"), b"owned").unwrap();
+        fs::write(src.join("y"), b"plain file").unwrap();
+        let outside = tmp.0.join("outside");
+        fs::create_dir_all(&outside).unwrap();
+
+        let outputs: Vec<PathBuf> = (0..4).map(|i| tmp.0.join(format!("out{}", i))).collect();
+        let fec = FEC::new(2, 4).unwrap();
+        let codec = StripedCodec::new(FEC::new(2, 4).unwrap(), 64).unwrap();
+        let mut manifest = archive_tree(&src, &outputs, codec).unwrap();
+
+        // a link to a directory outside of dst takes the place of the directory "x"
+        let dir = manifest.entries.iter().position(|e| e.path == "x").unwrap();
+        manifest.entries[dir] = Entry {
+            path: "x".to_string(),
+            mode: 0o777,
+            kind: EntryKind::Symlink {
+                target: outside.to_str().unwrap().to_string(),
+            },
+        };
+        rewrite_manifest(&outputs, &manifest, &fec);
+
+        let dst = tmp.0.join("restored");
+        let e = restore_tree(&outputs, &dst).unwrap_err();
+        assert!(e.to_string().contains("symbolic link"), "{}", e);
+        assert!(!outside.join("passwd").exists());
+
+        // a link already in dst is not written through either
+        manifest.entries.remove(dir);
+        rewrite_manifest(&outputs, &manifest, &fec);
+        let dst = tmp.0.join("existing");
+        fs::create_dir_all(&dst).unwrap();
+        make_symlink(outside.join("victim").to_str().unwrap(), &dst.join("y")).unwrap();
+        assert!(restore_tree(&outputs, &dst).is_err());
+        assert!(!outside.join("victim").exists());
+    }
+
+    #[test]
+    fn test_restore_rejects_unsafe_paths() {
+        assert!(relative_path("sub/file").is_ok());
+        for path in ["", "../escape", "/etc/passwd", "sub/../../escape"] {
+            assert!(relative_path(path).is_err(), "{}", path);
+        }
+    }
+}
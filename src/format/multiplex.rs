@@ -0,0 +1,425 @@
+use crate::fec::checksum::{chunk_checksum, share_checksum, ChecksummedShare};
+use crate::fec::fec::{MatrixKind, Share, FEC};
+use crate::fec::striped::{Stripe, StripedCodec};
+use crate::fec::workspace::Encoder;
+use crate::format::share_file::FIELD_GF256_11D;
+use crate::hash::crc32c::crc32c;
+use std::io::{self, Read, Write};
+
+/// First bytes of a multiplexed stream.
+pub const STREAM_MAGIC: [u8; 8] = *b"RSSTREAM";
+/// Format version written by this crate.
+pub const STREAM_VERSION: u16 = 1;
+/// Length of the stream header in bytes.
+pub const STREAM_HEADER_LEN: usize = 30;
+
+const TAG_END: u8 = 0;
+const TAG_STRIPE: u8 = 1;
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// `MultiplexWriter` erasure-codes a byte stream into a single stream that carries all
+/// n shares, for pipelines that have one output rather than one per share.
+///
+/// The stream starts with a header (all integers little-endian):
+///
+/// | offset | size | field |
+/// |-------:|-----:|-------|
+/// | 0  | 8 | magic `RSSTREAM` |
+/// | 8  | 2 | version |
+/// | 10 | 2 | k |
+/// | 12 | 2 | n |
+/// | 14 | 2 | field id (`0x11D`) |
+/// | 16 | 1 | matrix family (0 Vandermonde, 1 Cauchy) |
+/// | 17 | 1 | reserved, must be 0 |
+/// | 18 | 8 | stripe size |
+/// | 26 | 4 | CRC-32C of bytes 0..26 |
+///
+/// Every stripe is a frame: a tag byte 1, the stripe length as a u32 and a CRC-32C of
+/// those five bytes, followed by the n chunks of the stripe in share order, each
+/// followed by its `share_checksum()`. Only the last stripe may be shorter than the
+/// stripe size. The stream ends with a tag byte 0, the total length as a u64 and a
+/// CRC-32C of those nine bytes, so a truncated stream is detected.
+#[derive(Debug)]
+pub struct MultiplexWriter<W: Write> {
+    codec: StripedCodec,
+    encoder: Encoder,
+    inner: W,
+    buf: Vec<u8>,
+    len: u64,
+}
+
+impl<W: Write> MultiplexWriter<W> {
+    /// `new()` writes the stream header to `inner`. Frames carry the stripe length as a
+    /// u32, so the stripe size must fit in one.
+    pub fn new(codec: StripedCodec, mut inner: W) -> io::Result<MultiplexWriter<W>> {
+        if codec.stripe_size() > u32::MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("stripe size must be at most {} bytes", u32::MAX),
+            ));
+        }
+        let fec = codec.fec();
+        let mut header = [0u8; STREAM_HEADER_LEN];
+        header[0..8].copy_from_slice(&STREAM_MAGIC);
+        header[8..10].copy_from_slice(&STREAM_VERSION.to_le_bytes());
+        header[10..12].copy_from_slice(&(fec.k as u16).to_le_bytes());
+        header[12..14].copy_from_slice(&(fec.n as u16).to_le_bytes());
+        header[14..16].copy_from_slice(&FIELD_GF256_11D.to_le_bytes());
+        header[16] = match fec.matrix {
+            MatrixKind::Vandermonde => 0,
+            MatrixKind::Cauchy => 1,
+        };
+        header[18..26].copy_from_slice(&(codec.stripe_size() as u64).to_le_bytes());
+        let checksum = crc32c(&header[..26]);
+        header[26..30].copy_from_slice(&checksum.to_le_bytes());
+        inner.write_all(&header)?;
+
+        let encoder = Encoder::new(fec, codec.stripe_size() / fec.k);
+        let buf = Vec::with_capacity(codec.stripe_size());
+        Ok(MultiplexWriter {
+            codec,
+            encoder,
+            inner,
+            buf,
+            len: 0,
+        })
+    }
+
+    /// Number of bytes written to the stream so far.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// `finish()` encodes the buffered partial stripe, if any, writes the end of the
+    /// stream and returns the inner writer with the length of the stream.
+    pub fn finish(mut self) -> io::Result<(W, u64)> {
+        if !self.buf.is_empty() {
+            let stripe = Stripe {
+                index: (self.len / self.codec.stripe_size() as u64) as usize,
+                offset: 0,
+                len: self.buf.len(),
+                share_len: self.buf.len().div_ceil(self.codec.fec().k),
+            };
+            write_frame_header(&mut self.inner, stripe.len)?;
+            let mut result = Ok(());
+            let inner = &mut self.inner;
+            self.codec
+                .encode_stripe(&stripe, &self.buf, |s| {
+                    if result.is_ok() {
+                        result = write_chunk(inner, &s);
+                    }
+                })
+                .map_err(|e| io::Error::other(e.to_string()))?;
+            result?;
+        }
+
+        let mut end = [0u8; 13];
+        end[0] = TAG_END;
+        end[1..9].copy_from_slice(&self.len.to_le_bytes());
+        let checksum = crc32c(&end[..9]);
+        end[9..13].copy_from_slice(&checksum.to_le_bytes());
+        self.inner.write_all(&end)?;
+        self.inner.flush()?;
+        Ok((self.inner, self.len))
+    }
+
+    fn encode_stripe(&mut self) -> io::Result<()> {
+        write_frame_header(&mut self.inner, self.buf.len())?;
+        let mut result = Ok(());
+        let inner = &mut self.inner;
+        self.encoder
+            .encode(self.codec.fec(), &self.buf, |number, data| {
+                if result.is_ok() {
+                    result = inner
+                        .write_all(data)
+                        .and_then(|_| inner.write_all(&chunk_checksum(number, data).to_le_bytes()));
+                }
+            })
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        self.buf.clear();
+        result
+    }
+}
+
+fn write_frame_header<W: Write>(w: &mut W, stripe_len: usize) -> io::Result<()> {
+    let mut header = [0u8; 9];
+    header[0] = TAG_STRIPE;
+    header[1..5].copy_from_slice(&(stripe_len as u32).to_le_bytes());
+    let checksum = crc32c(&header[..5]);
+    header[5..9].copy_from_slice(&checksum.to_le_bytes());
+    w.write_all(&header)
+}
+
+fn write_chunk<W: Write>(w: &mut W, share: &Share) -> io::Result<()> {
+    w.write_all(&share.data)?;
+    w.write_all(&share_checksum(share).to_le_bytes())
+}
+
+impl<W: Write> Write for MultiplexWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let take = usize::min(self.codec.stripe_size() - self.buf.len(), data.len());
+        self.buf.extend_from_slice(&data[..take]);
+        self.len += take as u64;
+        if self.buf.len() == self.codec.stripe_size() {
+            self.encode_stripe()?;
+        }
+        Ok(take)
+    }
+
+    /// Flushes the inner writer. A partial stripe stays buffered until it is complete
+    /// or `finish()` is called.
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// `MultiplexReader` decodes a stream written by `MultiplexWriter` and exposes the
+/// original bytes through `std::io::Read`.
+///
+/// Chunks whose checksum fails are treated as erasures, so every stripe survives up
+/// to n - k damaged chunks. Damage to the framing itself cannot be recovered from and
+/// is reported as an `InvalidData` error, as is a stream that ends without its end
+/// frame.
+#[derive(Debug)]
+pub struct MultiplexReader<R: Read> {
+    codec: StripedCodec,
+    inner: R,
+    next_stripe: usize,
+    len: u64,
+    done: bool,
+    damaged_chunks: u64,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> MultiplexReader<R> {
+    /// `new()` reads and checks the stream header.
+    pub fn new(mut inner: R) -> io::Result<MultiplexReader<R>> {
+        let mut header = [0u8; STREAM_HEADER_LEN];
+        inner.read_exact(&mut header)?;
+        let u16_at = |i: usize| u16::from_le_bytes([header[i], header[i + 1]]);
+
+        if header[0..8] != STREAM_MAGIC {
+            return Err(invalid("not a multiplexed share stream"));
+        }
+        if u16_at(8) != STREAM_VERSION {
+            return Err(invalid(format!("unsupported stream version {}", u16_at(8))));
+        }
+        if crc32c(&header[..26]) != u32::from_le_bytes(header[26..30].try_into().unwrap()) {
+            return Err(invalid("stream header checksum mismatch"));
+        }
+        if u16_at(14) != FIELD_GF256_11D {
+            return Err(invalid(format!("unsupported field id {:#x}", u16_at(14))));
+        }
+        if header[17] != 0 {
+            return Err(invalid("reserved byte is not zero"));
+        }
+        let matrix = match header[16] {
+            0 => MatrixKind::Vandermonde,
+            1 => MatrixKind::Cauchy,
+            m => return Err(invalid(format!("unknown matrix family {}", m))),
+        };
+        let stripe_size = u64::from_le_bytes(header[18..26].try_into().unwrap());
+
+        let fec = FEC::new_with_matrix(u16_at(10) as usize, u16_at(12) as usize, matrix)
+            .map_err(|e| invalid(e.to_string()))?;
+        let codec =
+            StripedCodec::new(fec, stripe_size as usize).map_err(|e| invalid(e.to_string()))?;
+        Ok(MultiplexReader {
+            codec,
+            inner,
+            next_stripe: 0,
+            len: 0,
+            done: false,
+            damaged_chunks: 0,
+            buf: Vec::new(),
+            pos: 0,
+        })
+    }
+
+    /// The code the stream was encoded with.
+    pub fn codec(&self) -> &StripedCodec {
+        &self.codec
+    }
+
+    /// Number of chunks so far that failed their checksum and were rebuilt.
+    pub fn damaged_chunks(&self) -> u64 {
+        self.damaged_chunks
+    }
+
+    /// Number of bytes decoded so far.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// `next_frame()` decodes the next stripe into `buf`, or checks the end frame.
+    fn next_frame(&mut self) -> io::Result<()> {
+        let mut tag = [0u8; 1];
+        self.inner.read_exact(&mut tag)?;
+        match tag[0] {
+            TAG_END => {
+                let mut end = [0u8; 13];
+                end[0] = TAG_END;
+                self.inner.read_exact(&mut end[1..])?;
+                let total = u64::from_le_bytes(end[1..9].try_into().unwrap());
+                if crc32c(&end[..9]) != u32::from_le_bytes(end[9..13].try_into().unwrap()) {
+                    return Err(invalid("end frame checksum mismatch"));
+                }
+                if total != self.len {
+                    return Err(invalid(format!(
+                        "stream ended after {} bytes, expected {}",
+                        self.len, total
+                    )));
+                }
+                self.done = true;
+                Ok(())
+            }
+            TAG_STRIPE => {
+                let mut header = [0u8; 9];
+                header[0] = TAG_STRIPE;
+                self.inner.read_exact(&mut header[1..])?;
+                if crc32c(&header[..5]) != u32::from_le_bytes(header[5..9].try_into().unwrap()) {
+                    return Err(invalid(format!(
+                        "stripe {}: frame checksum mismatch",
+                        self.next_stripe
+                    )));
+                }
+                let len = u32::from_le_bytes(header[1..5].try_into().unwrap()) as usize;
+                let stripe_size = self.codec.stripe_size();
+                if len == 0 || len > stripe_size || self.len % stripe_size as u64 != 0 {
+                    return Err(invalid(format!(
+                        "stripe {}: invalid length {}",
+                        self.next_stripe, len
+                    )));
+                }
+
+                let fec = self.codec.fec();
+                let stripe = Stripe {
+                    index: self.next_stripe,
+                    offset: self.len as usize,
+                    len,
+                    share_len: len.div_ceil(fec.k),
+                };
+                let mut chunks = Vec::with_capacity(fec.n);
+                for number in 0..fec.n {
+                    let mut data = vec![0u8; stripe.share_len];
+                    self.inner.read_exact(&mut data)?;
+                    let mut checksum = [0u8; 4];
+                    self.inner.read_exact(&mut checksum)?;
+                    chunks.push(ChecksummedShare {
+                        share: Share { number, data },
+                        checksum: u32::from_le_bytes(checksum),
+                    });
+                }
+
+                let (data, damaged) = self
+                    .codec
+                    .decode_stripe_checksummed(&stripe, chunks)
+                    .map_err(|e| invalid(e.to_string()))?;
+                self.damaged_chunks += damaged.len() as u64;
+                self.buf = data;
+                self.pos = 0;
+                self.len += len as u64;
+                self.next_stripe += 1;
+                Ok(())
+            }
+            t => Err(invalid(format!(
+                "stripe {}: unknown frame tag {}",
+                self.next_stripe, t
+            ))),
+        }
+    }
+}
+
+impl<R: Read> Read for MultiplexReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.buf.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.next_frame().map_err(|e| {
+                if e.kind() == io::ErrorKind::UnexpectedEof {
+                    invalid("share stream is truncated")
+                } else {
+                    e
+                }
+            })?;
+        }
+
+        let take = usize::min(out.len(), self.buf.len() - self.pos);
+        out[..take].copy_from_slice(&self.buf[self.pos..self.pos + take]);
+        self.pos += take;
+        Ok(take)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn multiplex(data: &[u8]) -> Vec<u8> {
+        let codec = StripedCodec::new(FEC::new(3, 6).unwrap(), 48).unwrap();
+        let mut writer = MultiplexWriter::new(codec, Vec::new()).unwrap();
+        writer.write_all(data).unwrap();
+        writer.finish().unwrap().0
+    }
+
+    #[test]
+    fn test_multiplex_round_trip_with_damaged_chunks() {
+        let data: Vec<u8> = (0..500u32).map(|i| (i * 17 + 3) as u8).collect();
+        let mut stream = multiplex(&data);
+
+        // damage chunks of the first stripe: share_len 16, chunks start after the
+        // stream header and the frame header
+        let first = STREAM_HEADER_LEN + 9;
+        for chunk in [0, 2, 5] {
+            stream[first + chunk * 20 + 3] ^= 0xA5;
+        }
+
+        let mut reader = MultiplexReader::new(stream.as_slice()).unwrap();
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, data);
+        assert_eq!(reader.damaged_chunks(), 3);
+    }
+
+    #[test]
+    fn test_multiplex_detects_truncation_and_bad_framing() {
+        let data = vec![9u8; 100];
+        let stream = multiplex(&data);
+
+        let mut out = Vec::new();
+        let mut reader = MultiplexReader::new(&stream[..stream.len() - 13]).unwrap();
+        let err = reader.read_to_end(&mut out).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut bad = stream.clone();
+        bad[STREAM_HEADER_LEN + 2] ^= 1;
+        let mut reader = MultiplexReader::new(bad.as_slice()).unwrap();
+        assert!(reader.read_to_end(&mut Vec::new()).is_err());
+
+        assert!(MultiplexReader::new(&b"not a stream at all, certainly not"[..]).is_err());
+
+        let empty = multiplex(&[]);
+        let mut reader = MultiplexReader::new(empty.as_slice()).unwrap();
+        assert_eq!(reader.read_to_end(&mut out).unwrap(), 0);
+
+        // a stripe length that does not fit in a frame header
+        if let Some(stripe_size) = (u32::MAX as usize).checked_add(1) {
+            let codec = StripedCodec::new(FEC::new(4, 8).unwrap(), stripe_size).unwrap();
+            let err = MultiplexWriter::new(codec, Vec::new()).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+}
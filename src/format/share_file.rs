@@ -0,0 +1,609 @@
+use crate::fec::fec::{MatrixKind, Share, FEC};
+use crate::fec::striped::{Stripe, StripedCodec};
+use crate::hash::crc32c::{crc32c, Crc32c};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+/// First bytes of every share file.
+pub const MAGIC: [u8; 8] = *b"RSSHARE\0";
+/// Format version written by this crate.
+pub const VERSION: u16 = 1;
+/// Length of the encoded header in bytes.
+pub const HEADER_LEN: usize = 52;
+/// Id of GF(2^8) with the reduction polynomial x^8 + x^4 + x^3 + x^2 + 1, the only
+/// field `FEC` works in.
+pub const FIELD_GF256_11D: u16 = 0x11D;
+
+/// `ShareHeader` describes one share file: the code it belongs to, where the share sits
+/// in it and how long the original input was, so it can be decoded without any
+/// out-of-band metadata.
+///
+/// The header is `HEADER_LEN` bytes, all integers little-endian:
+///
+/// | offset | size | field |
+/// |-------:|-----:|-------|
+/// | 0  | 8 | magic `RSSHARE\0` |
+/// | 8  | 2 | version |
+/// | 10 | 2 | k |
+/// | 12 | 2 | n |
+/// | 14 | 2 | share number |
+/// | 16 | 2 | field id (`0x11D`) |
+/// | 18 | 1 | matrix family (0 Vandermonde, 1 Cauchy) |
+/// | 19 | 1 | reserved, must be 0 |
+/// | 20 | 8 | stripe size, 0 if the input was encoded in one piece with `FEC::encode` |
+/// | 28 | 8 | original length |
+/// | 36 | 8 | payload length |
+/// | 44 | 4 | CRC-32C of the payload |
+/// | 48 | 4 | CRC-32C of bytes 0..48 |
+///
+/// The payload follows the header. For striped shares it holds the share's chunk of
+/// every stripe back to back, as written by `StreamEncoder`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShareHeader {
+    pub version: u16,
+    pub k: usize,
+    pub n: usize,
+    pub number: usize,
+    pub matrix: MatrixKind,
+    pub stripe_size: usize,
+    pub original_len: u64,
+    pub payload_len: u64,
+    pub payload_checksum: u32,
+}
+
+/// `ShareFileError` lists the ways a share file can be rejected.
+#[derive(Debug)]
+pub enum ShareFileError {
+    /// Reading or writing failed.
+    Io(io::Error),
+    /// The input ended before the header or payload was complete.
+    Truncated,
+    /// The file does not start with `MAGIC`.
+    BadMagic,
+    /// The file was written by an unknown version of the format.
+    UnsupportedVersion(u16),
+    /// The header does not match its checksum.
+    HeaderChecksum { expected: u32, actual: u32 },
+    /// The field id is not `FIELD_GF256_11D`.
+    UnsupportedField(u16),
+    /// The matrix family byte is unknown.
+    UnknownMatrix(u8),
+    /// The header fields are inconsistent, e.g. the share number is not below n.
+    InvalidHeader(String),
+    /// The payload length does not match k, the stripe size and the original length.
+    PayloadLength { expected: u64, actual: u64 },
+    /// The payload does not match its checksum.
+    PayloadChecksum { expected: u32, actual: u32 },
+    /// There are bytes after the payload.
+    TrailingData,
+}
+
+impl fmt::Display for ShareFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShareFileError::Io(e) => write!(f, "i/o error: {}", e),
+            ShareFileError::Truncated => write!(f, "share file is truncated"),
+            ShareFileError::BadMagic => write!(f, "not a share file"),
+            ShareFileError::UnsupportedVersion(v) => {
+                write!(f, "unsupported share file version {}", v)
+            }
+            ShareFileError::HeaderChecksum { expected, actual } => write!(
+                f,
+                "header checksum mismatch: expected {:08x}, got {:08x}",
+                expected, actual
+            ),
+            ShareFileError::UnsupportedField(id) => write!(f, "unsupported field id {:#x}", id),
+            ShareFileError::UnknownMatrix(m) => write!(f, "unknown matrix family {}", m),
+            ShareFileError::InvalidHeader(reason) => write!(f, "invalid header: {}", reason),
+            ShareFileError::PayloadLength { expected, actual } => write!(
+                f,
+                "payload length mismatch: expected {} bytes, got {}",
+                expected, actual
+            ),
+            ShareFileError::PayloadChecksum { expected, actual } => write!(
+                f,
+                "payload checksum mismatch: expected {:08x}, got {:08x}",
+                expected, actual
+            ),
+            ShareFileError::TrailingData => write!(f, "unexpected data after the payload"),
+        }
+    }
+}
+
+impl Error for ShareFileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ShareFileError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ShareFileError {
+    fn from(e: io::Error) -> ShareFileError {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            ShareFileError::Truncated
+        } else {
+            ShareFileError::Io(e)
+        }
+    }
+}
+
+fn matrix_id(matrix: MatrixKind) -> u8 {
+    match matrix {
+        MatrixKind::Vandermonde => 0,
+        MatrixKind::Cauchy => 1,
+    }
+}
+
+/// `payload_len()` returns the length of every share of an input of `original_len`
+/// bytes encoded with k pieces, in stripes of `stripe_size` bytes if it is not 0.
+pub fn payload_len(k: usize, stripe_size: usize, original_len: u64) -> u64 {
+    let k = k as u64;
+    if stripe_size == 0 {
+        return original_len.div_ceil(k);
+    }
+    let stripe_size = stripe_size as u64;
+    let full = original_len / stripe_size;
+    full * (stripe_size / k) + (original_len % stripe_size).div_ceil(k)
+}
+
+impl ShareHeader {
+    /// `new()` creates the header of share `number` of `fec`, for a payload of
+    /// `payload` bytes.
+    pub fn new(
+        fec: &FEC,
+        number: usize,
+        stripe_size: usize,
+        original_len: u64,
+        payload: &[u8],
+    ) -> ShareHeader {
+        ShareHeader {
+            version: VERSION,
+            k: fec.k,
+            n: fec.n,
+            number,
+            matrix: fec.matrix,
+            stripe_size,
+            original_len,
+            payload_len: payload.len() as u64,
+            payload_checksum: crc32c(payload),
+        }
+    }
+
+    /// `to_bytes()` encodes the header, including its checksum.
+    pub fn to_bytes(&self) -> [u8; HEADER_LEN] {
+        let mut out = [0u8; HEADER_LEN];
+        out[0..8].copy_from_slice(&MAGIC);
+        out[8..10].copy_from_slice(&self.version.to_le_bytes());
+        out[10..12].copy_from_slice(&(self.k as u16).to_le_bytes());
+        out[12..14].copy_from_slice(&(self.n as u16).to_le_bytes());
+        out[14..16].copy_from_slice(&(self.number as u16).to_le_bytes());
+        out[16..18].copy_from_slice(&FIELD_GF256_11D.to_le_bytes());
+        out[18] = matrix_id(self.matrix);
+        out[20..28].copy_from_slice(&(self.stripe_size as u64).to_le_bytes());
+        out[28..36].copy_from_slice(&self.original_len.to_le_bytes());
+        out[36..44].copy_from_slice(&self.payload_len.to_le_bytes());
+        out[44..48].copy_from_slice(&self.payload_checksum.to_le_bytes());
+        let checksum = crc32c(&out[..48]);
+        out[48..52].copy_from_slice(&checksum.to_le_bytes());
+        out
+    }
+
+    /// `from_bytes()` parses and validates a header. Every field is checked; the
+    /// payload itself is checked by `ShareFile::read_from`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<ShareHeader, ShareFileError> {
+        if bytes.len() < HEADER_LEN {
+            return Err(ShareFileError::Truncated);
+        }
+        let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+        let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+        let u64_at = |i: usize| u64::from_le_bytes(bytes[i..i + 8].try_into().unwrap());
+
+        if bytes[0..8] != MAGIC {
+            return Err(ShareFileError::BadMagic);
+        }
+        let version = u16_at(8);
+        if version != VERSION {
+            return Err(ShareFileError::UnsupportedVersion(version));
+        }
+        let expected = u32_at(48);
+        let actual = crc32c(&bytes[..48]);
+        if expected != actual {
+            return Err(ShareFileError::HeaderChecksum { expected, actual });
+        }
+
+        let field = u16_at(16);
+        if field != FIELD_GF256_11D {
+            return Err(ShareFileError::UnsupportedField(field));
+        }
+        let matrix = match bytes[18] {
+            0 => MatrixKind::Vandermonde,
+            1 => MatrixKind::Cauchy,
+            m => return Err(ShareFileError::UnknownMatrix(m)),
+        };
+        if bytes[19] != 0 {
+            return Err(ShareFileError::InvalidHeader(
+                "reserved byte is not zero".to_string(),
+            ));
+        }
+
+        let (k, n, number) = (
+            u16_at(10) as usize,
+            u16_at(12) as usize,
+            u16_at(14) as usize,
+        );
+        if k == 0 || n == 0 || k > n || n > 256 {
+            return Err(ShareFileError::InvalidHeader(format!(
+                "invalid code parameters k = {}, n = {}",
+                k, n
+            )));
+        }
+        if number >= n {
+            return Err(ShareFileError::InvalidHeader(format!(
+                "share number {} is not below n = {}",
+                number, n
+            )));
+        }
+
+        let stripe_size = u64_at(20);
+        if stripe_size % k as u64 != 0 || stripe_size > usize::MAX as u64 {
+            return Err(ShareFileError::InvalidHeader(format!(
+                "stripe size {} is not a multiple of k = {}",
+                stripe_size, k
+            )));
+        }
+        let stripe_size = stripe_size as usize;
+
+        let original_len = u64_at(28);
+        let payload_len = u64_at(36);
+        let expected = self::payload_len(k, stripe_size, original_len);
+        if payload_len != expected {
+            return Err(ShareFileError::PayloadLength {
+                expected,
+                actual: payload_len,
+            });
+        }
+
+        Ok(ShareHeader {
+            version,
+            k,
+            n,
+            number,
+            matrix,
+            stripe_size,
+            original_len,
+            payload_len,
+            payload_checksum: u32_at(44),
+        })
+    }
+
+    /// `fec()` creates the `FEC` the share was encoded with.
+    pub fn fec(&self) -> Result<FEC, Box<dyn Error>> {
+        FEC::new_with_matrix(self.k, self.n, self.matrix)
+    }
+
+    /// `codec()` creates the `StripedCodec` the share was encoded with, or `None` if
+    /// the input was encoded in one piece.
+    pub fn codec(&self) -> Result<Option<StripedCodec>, Box<dyn Error>> {
+        if self.stripe_size == 0 {
+            return Ok(None);
+        }
+        StripedCodec::new(self.fec()?, self.stripe_size).map(Some)
+    }
+
+    /// `stripes()` returns the stripes of the encoded input. An input encoded in one
+    /// piece is a single stripe of `payload_len` bytes per share.
+    pub fn stripes(&self) -> Result<Vec<Stripe>, Box<dyn Error>> {
+        match self.codec()? {
+            Some(codec) => Ok(codec.layout(self.original_len as usize).stripes),
+            None => Ok(vec![Stripe {
+                index: 0,
+                offset: 0,
+                len: self.original_len as usize,
+                share_len: self.payload_len as usize,
+            }]),
+        }
+    }
+
+    /// `is_compatible()` returns whether `other` is a share of the same encoded input.
+    pub fn is_compatible(&self, other: &ShareHeader) -> bool {
+        self.k == other.k
+            && self.n == other.n
+            && self.matrix == other.matrix
+            && self.stripe_size == other.stripe_size
+            && self.original_len == other.original_len
+    }
+}
+
+/// `ShareFile` is a share together with the header describing it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShareFile {
+    pub header: ShareHeader,
+    pub data: Vec<u8>,
+}
+
+impl ShareFile {
+    /// `new()` wraps `share` of an input of `original_len` bytes encoded with `fec`,
+    /// in stripes of `stripe_size` bytes or in one piece if `stripe_size` is 0.
+    pub fn new(fec: &FEC, stripe_size: usize, original_len: u64, share: Share) -> ShareFile {
+        ShareFile {
+            header: ShareHeader::new(fec, share.number, stripe_size, original_len, &share.data),
+            data: share.data,
+        }
+    }
+
+    /// `write_to()` writes the header followed by the payload.
+    pub fn write_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        w.write_all(&self.header.to_bytes())?;
+        w.write_all(&self.data)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_LEN + self.data.len());
+        out.extend_from_slice(&self.header.to_bytes());
+        out.extend_from_slice(&self.data);
+        out
+    }
+
+    /// `read_from()` reads one share file from `r` and checks the header and payload.
+    /// It stops right after the payload.
+    pub fn read_from<R: Read>(mut r: R) -> Result<ShareFile, ShareFileError> {
+        let mut header = [0u8; HEADER_LEN];
+        r.read_exact(&mut header)?;
+        let header = ShareHeader::from_bytes(&header)?;
+
+        let mut data = Vec::new();
+        let read = r.by_ref().take(header.payload_len).read_to_end(&mut data)?;
+        if (read as u64) < header.payload_len {
+            return Err(ShareFileError::Truncated);
+        }
+        let actual = crc32c(&data);
+        if actual != header.payload_checksum {
+            return Err(ShareFileError::PayloadChecksum {
+                expected: header.payload_checksum,
+                actual,
+            });
+        }
+        Ok(ShareFile { header, data })
+    }
+
+    /// `from_bytes()` parses a complete share file, rejecting trailing bytes.
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<ShareFile, ShareFileError> {
+        let file = ShareFile::read_from(&mut bytes)?;
+        if !bytes.is_empty() {
+            return Err(ShareFileError::TrailingData);
+        }
+        Ok(file)
+    }
+
+    pub fn share(&self) -> Share {
+        Share {
+            number: self.header.number,
+            data: self.data.clone(),
+        }
+    }
+
+    pub fn into_share(self) -> Share {
+        Share {
+            number: self.header.number,
+            data: self.data,
+        }
+    }
+}
+
+/// `ShareWriter` writes a share file whose payload is produced incrementally, e.g. one
+/// of the sinks of a `StreamEncoder`.
+///
+/// A placeholder header is written first; `finish()` seeks back and fills it in once the
+/// payload length, its checksum and the original length are known.
+#[derive(Debug)]
+pub struct ShareWriter<W: Write + Seek> {
+    inner: W,
+    start: u64,
+    header: ShareHeader,
+    crc: Crc32c,
+}
+
+impl<W: Write + Seek> ShareWriter<W> {
+    /// `new()` starts share `number` of `fec`, encoded in stripes of `stripe_size`
+    /// bytes (0 if not striped), at the current position of `inner`.
+    pub fn new(
+        mut inner: W,
+        fec: &FEC,
+        number: usize,
+        stripe_size: usize,
+    ) -> io::Result<ShareWriter<W>> {
+        let start = inner.stream_position()?;
+        inner.write_all(&[0u8; HEADER_LEN])?;
+        Ok(ShareWriter {
+            inner,
+            start,
+            header: ShareHeader::new(fec, number, stripe_size, 0, &[]),
+            crc: Crc32c::new(),
+        })
+    }
+
+    /// `finish()` writes the final header for an input of `original_len` bytes and
+    /// returns the inner writer, positioned after the payload.
+    pub fn finish(mut self, original_len: u64) -> io::Result<W> {
+        self.header.original_len = original_len;
+        self.header.payload_checksum = self.crc.finish();
+        let expected = payload_len(self.header.k, self.header.stripe_size, original_len);
+        if self.header.payload_len != expected {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "payload is {} bytes, expected {} for an input of {} bytes",
+                    self.header.payload_len, expected, original_len
+                ),
+            ));
+        }
+        self.inner.seek(SeekFrom::Start(self.start))?;
+        self.inner.write_all(&self.header.to_bytes())?;
+        self.inner.seek(SeekFrom::Start(
+            self.start + HEADER_LEN as u64 + self.header.payload_len,
+        ))?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write + Seek> Write for ShareWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(data)?;
+        self.crc.update(&data[..written]);
+        self.header.payload_len += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn share_files() -> Vec<ShareFile> {
+        let fec = FEC::new_with_matrix(3, 6, MatrixKind::Cauchy).unwrap();
+        let input = b"self-describing shares!";
+        let mut files = Vec::new();
+        fec.encode(input, |s| {
+            files.push(ShareFile::new(&fec, 0, input.len() as u64, s))
+        })
+        .unwrap();
+        files
+    }
+
+    #[test]
+    fn test_share_file_round_trip() {
+        let files = share_files();
+        let parsed: Vec<ShareFile> = files
+            .iter()
+            .map(|f| ShareFile::from_bytes(&f.to_bytes()).unwrap())
+            .collect();
+        assert_eq!(parsed, files);
+
+        let header = parsed[0].header;
+        let fec = header.fec().unwrap();
+        assert_eq!(fec.matrix, MatrixKind::Cauchy);
+        let shares = parsed
+            .into_iter()
+            .skip(2)
+            .map(ShareFile::into_share)
+            .collect();
+        let decoded = fec.decode(Vec::new(), shares).unwrap();
+        assert_eq!(
+            &decoded[..header.original_len as usize],
+            b"self-describing shares!"
+        );
+    }
+
+    #[test]
+    fn test_share_file_rejects_damage() {
+        let bytes = share_files()[4].to_bytes();
+
+        let mut bad = bytes.clone();
+        bad[0] = b'X';
+        assert!(matches!(
+            ShareFile::from_bytes(&bad),
+            Err(ShareFileError::BadMagic)
+        ));
+
+        let mut bad = bytes.clone();
+        bad[8] = 2;
+        assert!(matches!(
+            ShareFile::from_bytes(&bad),
+            Err(ShareFileError::UnsupportedVersion(2))
+        ));
+
+        let mut bad = bytes.clone();
+        bad[14] = 1;
+        assert!(matches!(
+            ShareFile::from_bytes(&bad),
+            Err(ShareFileError::HeaderChecksum { .. })
+        ));
+
+        let mut bad = bytes.clone();
+        bad[HEADER_LEN + 1] ^= 1;
+        assert!(matches!(
+            ShareFile::from_bytes(&bad),
+            Err(ShareFileError::PayloadChecksum { .. })
+        ));
+
+        assert!(matches!(
+            ShareFile::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(ShareFileError::Truncated)
+        ));
+        assert!(matches!(
+            ShareFile::from_bytes(&bytes[..10]),
+            Err(ShareFileError::Truncated)
+        ));
+
+        let mut bad = bytes.clone();
+        bad.push(0);
+        assert!(matches!(
+            ShareFile::from_bytes(&bad),
+            Err(ShareFileError::TrailingData)
+        ));
+    }
+
+    #[test]
+    fn test_share_writer_matches_share_file() {
+        use crate::fec::striped::StripedCodec;
+        use crate::stream::encoder::StreamEncoder;
+        use std::io::Cursor;
+
+        let codec = StripedCodec::new(FEC::new(2, 4).unwrap(), 8).unwrap();
+        let sinks = (0..4)
+            .map(|i| ShareWriter::new(Cursor::new(Vec::new()), codec.fec(), i, 8).unwrap())
+            .collect();
+        let mut stream = StreamEncoder::new(codec, sinks).unwrap();
+        stream.write_all(b"a stream of 27 bytes to go.").unwrap();
+        let (writers, len) = stream.finish().unwrap();
+
+        for writer in writers {
+            let bytes = writer.finish(len).unwrap().into_inner();
+            let file = ShareFile::from_bytes(&bytes).unwrap();
+            assert_eq!(file.header.original_len, 27);
+            assert_eq!(file.data.len(), 14);
+        }
+    }
+
+    #[test]
+    fn test_share_header_validates_fields() {
+        let fec = FEC::new(4, 6).unwrap();
+        let header = ShareHeader::new(&fec, 5, 64, 1000, &vec![0u8; 250]);
+        assert_eq!(ShareHeader::from_bytes(&header.to_bytes()).unwrap(), header);
+        assert!(header.codec().unwrap().is_some());
+
+        let reencode = |h: ShareHeader| ShareHeader::from_bytes(&h.to_bytes());
+        assert!(matches!(
+            reencode(ShareHeader {
+                number: 6,
+                ..header
+            }),
+            Err(ShareFileError::InvalidHeader(_))
+        ));
+        assert!(matches!(
+            reencode(ShareHeader {
+                stripe_size: 66,
+                ..header
+            }),
+            Err(ShareFileError::InvalidHeader(_))
+        ));
+        assert!(matches!(
+            reencode(ShareHeader {
+                payload_len: 251,
+                ..header
+            }),
+            Err(ShareFileError::PayloadLength {
+                expected: 250,
+                actual: 251
+            })
+        ));
+    }
+}
@@ -7,12 +7,57 @@ pub mod galois_field {
 /// Contains auxiliary math functions.
 pub mod math {
     pub mod addmul;
+    pub mod bitmatrix;
+    pub mod cauchy;
+    pub mod matmul;
+    #[cfg(feature = "parallel")]
+    pub mod parallel;
     pub mod pivot_searcher;
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    mod simd;
 }
 
 /// Contains FEC(Forward Error Correction) implementations.
 pub mod fec {
+    pub mod checksum;
     pub mod fec;
+    pub mod merkle;
+    pub mod striped;
+    pub mod workspace;
+}
+
+/// Contains streaming encoders and decoders over `std::io`.
+pub mod stream {
+    #[cfg(feature = "async")]
+    pub mod async_io;
+    pub mod decoder;
+    pub mod encoder;
+}
+
+/// Contains checksums and hash functions.
+pub mod hash {
+    pub mod crc32c;
+    pub mod sha256;
+}
+
+/// Contains the self-describing share file and multiplexed stream formats, and
+/// directory-tree archives built on them.
+pub mod format {
+    pub mod archive;
+    pub mod multiplex;
+    pub mod share_file;
+}
+
+/// Contains the `ShareStore` trait, its filesystem and in-memory backends and helpers
+/// that store and load whole objects.
+pub mod store {
+    pub mod fs;
+    pub mod hedged;
+    pub mod memory;
+    pub mod placement;
+    pub mod s3;
+    pub mod scrub;
+    pub mod share_store;
 }
 
 /// Contains the Berlekamp Welch Decoder and auxiliary functions
@@ -153,4 +198,214 @@ mod tests {
         // Expect an error due to too many corruptions
         assert!(result_data.is_err());
     }
+
+    #[test]
+    fn test_cauchy_encode_decode_two_corruptions() -> Result<(), Box<dyn std::error::Error>> {
+        let required = 4;
+        let total = 8;
+        let f = FEC::new_with_matrix(required, total, MatrixKind::Cauchy)?;
+
+        let mut shares: Vec<Share> = vec![
+            Share {
+                number: 0,
+                data: vec![]
+            };
+            total
+        ];
+
+        let data = b"hello, world! __".to_vec();
+
+        let output = |s: Share| {
+            shares[s.number] = s.clone();
+        };
+
+        f.encode(&data, output)?;
+
+        // Corrupt a data share and a parity share
+        shares[2].data[0] = b'?';
+        shares[6].data[3] = b'?';
+
+        let result_data = f.decode([].to_vec(), shares)?;
+
+        assert_eq!(String::from_utf8(result_data)?, "hello, world! __");
+        Ok(())
+    }
+
+    #[test]
+    fn test_bitmatrix_backend_byte_identical() -> Result<(), Box<dyn std::error::Error>> {
+        let required = 5;
+        let total = 11;
+        let data: Vec<u8> = (0..5000u32).map(|i| (i * 37 % 251) as u8).collect();
+
+        for matrix in [MatrixKind::Vandermonde, MatrixKind::Cauchy] {
+            let table = FEC::new_with_matrix(required, total, matrix)?;
+            let xor =
+                FEC::new_with_matrix(required, total, matrix)?.with_backend(Backend::BitMatrix);
+
+            let mut expected: Vec<Share> = vec![];
+            table.encode(&data, |s: Share| expected.push(s))?;
+            let mut shares: Vec<Share> = vec![];
+            xor.encode(&data, |s: Share| shares.push(s))?;
+
+            for (a, b) in expected.iter().zip(shares.iter()) {
+                assert_eq!(a.number, b.number);
+                assert_eq!(a.data, b.data);
+            }
+
+            let mut single = vec![0u8; data.len() / required];
+            xor.encode_single(&data, &mut single, total - 1)?;
+            assert_eq!(single, expected[total - 1].data);
+
+            // rebuild from the last `required` shares only
+            let kept: Vec<Share> = shares.split_off(total - required);
+            let mut rebuilt = vec![0u8; data.len()];
+            let piece_len = data.len() / required;
+            xor.rebuild(kept, |s: Share| {
+                rebuilt[s.number * piece_len..(s.number + 1) * piece_len].copy_from_slice(&s.data);
+            })?;
+            assert_eq!(rebuilt, data);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_matches_serial() -> Result<(), Box<dyn std::error::Error>> {
+        let required = 6;
+        let total = 12;
+        let data: Vec<u8> = (0..60_000u32).map(|i| (i * 89 % 253) as u8).collect();
+
+        for backend in [Backend::Table, Backend::BitMatrix] {
+            let serial = FEC::new(required, total)?.with_backend(backend);
+            let parallel = FEC::new(required, total)?
+                .with_backend(backend)
+                .with_min_parallel_len(100);
+
+            let mut expected: Vec<Share> = vec![];
+            serial.encode(&data, |s: Share| expected.push(s))?;
+            let mut shares: Vec<Share> = vec![];
+            parallel.encode(&data, |s: Share| shares.push(s))?;
+            assert_eq!(
+                shares.iter().map(|s| &s.data).collect::<Vec<_>>(),
+                expected.iter().map(|s| &s.data).collect::<Vec<_>>()
+            );
+
+            // corrupt many columns of two shares and drop one data share
+            for j in (0..shares[0].data.len()).step_by(3) {
+                shares[1].data[j] ^= 0x5A;
+                shares[9].data[j] ^= 0xA5;
+            }
+            shares.remove(4);
+
+            let result_data = parallel.decode([].to_vec(), shares)?;
+            assert_eq!(result_data, data);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_parity() -> Result<(), Box<dyn std::error::Error>> {
+        let required = 4;
+        let total = 9;
+        let f = FEC::new(required, total)?;
+
+        let mut data: Vec<u8> = (0..400u32).map(|i| (i * 3) as u8).collect();
+        let mut shares: Vec<Share> = vec![];
+        f.encode(&data, |s: Share| shares.push(s))?;
+
+        // rewrite piece 2 entirely
+        let old = data[200..300].to_vec();
+        let new: Vec<u8> = (0..100u32).map(|i| (i * 11 + 5) as u8).collect();
+        data[200..300].copy_from_slice(&new);
+        f.update_parity(2, &old, &new, &mut shares[required..])?;
+        shares[2].data.copy_from_slice(&new);
+
+        // partial write to piece 0
+        let old = data[10..20].to_vec();
+        data[10..20].fill(0xEE);
+        f.update_parity_range(0, 10, &old, &data[10..20], &mut shares[required..])?;
+        shares[0].data[10..20].fill(0xEE);
+
+        let mut expected: Vec<Share> = vec![];
+        f.encode(&data, |s: Share| expected.push(s))?;
+        for (a, b) in shares.iter().zip(expected.iter()) {
+            assert_eq!(a.data, b.data, "share {}", a.number);
+        }
+
+        assert!(f
+            .update_parity(4, &old, &old, &mut shares[required..])
+            .is_err());
+        assert!(f.update_parity(0, &new, &new, &mut shares[..1]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_rebuild_from_exactly_k_parity_shares() -> Result<(), Box<dyn std::error::Error>> {
+        // every data piece comes from the back of the share list, so the last one taken
+        // leaves the back index below zero
+        let required = 3;
+        let total = 6;
+        let f = FEC::new(required, total)?;
+        let data = b"rebuilt from parity only".to_vec();
+        let mut shares: Vec<Share> = vec![];
+        f.encode(&data, |s: Share| shares.push(s))?;
+        let parity = shares.split_off(required);
+
+        let piece_len = shares[0].data.len();
+        let mut rebuilt = vec![0u8; required * piece_len];
+        f.rebuild(parity, |s: Share| {
+            rebuilt[s.number * piece_len..(s.number + 1) * piece_len].copy_from_slice(&s.data);
+        })?;
+        assert_eq!(&rebuilt[..data.len()], &data[..]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rebuild_outputs_each_missing_piece_once() -> Result<(), Box<dyn std::error::Error>> {
+        // the table backend used to emit a partial piece after every column it added
+        let required = 4;
+        let total = 7;
+        let f = FEC::new(required, total)?;
+        let data = b"each missing piece exactly once".to_vec();
+        let mut shares: Vec<Share> = vec![];
+        f.encode(&data, |s: Share| shares.push(s))?;
+        let expected: Vec<Vec<u8>> = shares[..required].iter().map(|s| s.data.clone()).collect();
+
+        let kept: Vec<Share> = shares
+            .into_iter()
+            .filter(|s| s.number != 1 && s.number != 2)
+            .collect();
+        let mut outputs: Vec<Share> = vec![];
+        f.rebuild(kept, |s: Share| outputs.push(s))?;
+
+        let mut numbers: Vec<usize> = outputs.iter().map(|s| s.number).collect();
+        numbers.sort();
+        assert_eq!(numbers, vec![0, 1, 2, 3]);
+        for s in &outputs {
+            assert_eq!(s.data, expected[s.number]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_cauchy_rebuild_from_parity() -> Result<(), Box<dyn std::error::Error>> {
+        let required = 3;
+        let total = 7;
+        let f = FEC::new_with_matrix(required, total, MatrixKind::Cauchy)?;
+
+        let mut shares: Vec<Share> = vec![];
+        let data = b"cauchy reed solomon!".to_vec();
+        f.encode(&data, |s: Share| shares.push(s))?;
+
+        // Keep only the parity shares
+        let shares: Vec<Share> = shares
+            .into_iter()
+            .filter(|s| s.number >= required)
+            .collect();
+
+        let result_data = f.decode([].to_vec(), shares)?;
+
+        assert_eq!(&result_data[..data.len()], &data[..]);
+        Ok(())
+    }
 }
@@ -1,10 +1,71 @@
 use crate::galois_field::{gf_alg::GfVal, tables::GF_MUL_TABLE};
 
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use crate::math::simd;
+
+/// `addmul()` computes `z ^= y * x` over GF(256).
+///
+/// Uses the AVX2 or SSSE3 split-nibble kernels when the CPU supports them (checked at
+/// runtime) and falls back to `addmul_scalar()` otherwise.
 pub fn addmul(z: &mut [u8], x: &[u8], y: u8) {
     if y == 0 {
         return;
     }
 
+    let x = &x[..z.len()];
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("avx2") {
+            // Safety: AVX2 is available and `x` has the length of `z`.
+            unsafe { simd::addmul_avx2(z, x, y) };
+            return;
+        }
+        if is_x86_feature_detected!("ssse3") {
+            // Safety: SSSE3 is available and `x` has the length of `z`.
+            unsafe { simd::addmul_ssse3(z, x, y) };
+            return;
+        }
+    }
+
+    addmul_scalar(z, x, y);
+}
+
+/// `addmul_multi()` computes `z ^= sum_i ys[i] * xs[i]` over GF(256).
+///
+/// The SIMD kernels fuse the sources so `z` is read and written once per group of
+/// sources rather than once per source.
+pub fn addmul_multi(z: &mut [u8], xs: &[&[u8]], ys: &[u8]) {
+    assert_eq!(xs.len(), ys.len(), "one coefficient per source is required");
+    for x in xs {
+        assert!(x.len() >= z.len(), "sources must be at least as long as z");
+    }
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("avx2") {
+            // Safety: AVX2 is available and the source lengths were checked above.
+            unsafe { simd::addmul_multi_avx2(z, xs, ys) };
+            return;
+        }
+        if is_x86_feature_detected!("ssse3") {
+            // Safety: SSSE3 is available and the source lengths were checked above.
+            unsafe { simd::addmul_multi_ssse3(z, xs, ys) };
+            return;
+        }
+    }
+
+    for (x, &y) in xs.iter().zip(ys.iter()) {
+        addmul_scalar(z, x, y);
+    }
+}
+
+/// Portable `addmul()`: one `GF_MUL_TABLE` lookup per byte.
+pub fn addmul_scalar(z: &mut [u8], x: &[u8], y: u8) {
+    if y == 0 {
+        return;
+    }
+
     // Safety: We assume `x` has the same length as `z`.
     // The bounds check is removed by slicing `x` to the length of `z`.
     let x = &x[..z.len()];
@@ -33,3 +94,85 @@ pub fn addmul_gfval(z: &mut [GfVal], x: &[GfVal], y: GfVal) {
         zi.0 ^= gf_mul_y[xi as usize];
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(len: usize, seed: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 151 + seed * 29 + 7) as u8).collect()
+    }
+
+    #[test]
+    fn test_addmul_matches_scalar() {
+        for y in 0..=255u8 {
+            for len in 0..100 {
+                // odd offsets make the slices unaligned
+                let offset = len % 3;
+                let x = pattern(len + offset, y as usize);
+                let z = pattern(len + offset, len);
+
+                let mut expected = z[offset..].to_vec();
+                addmul_scalar(&mut expected, &x[offset..], y);
+                let mut got = z[offset..].to_vec();
+                addmul(&mut got, &x[offset..], y);
+
+                assert_eq!(got, expected, "y {} len {}", y, len);
+            }
+        }
+    }
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    #[test]
+    fn test_simd_kernels_match_scalar() {
+        let ssse3 = is_x86_feature_detected!("ssse3");
+        let avx2 = is_x86_feature_detected!("avx2");
+
+        for y in 0..=255u8 {
+            for len in [0, 1, 15, 16, 17, 31, 32, 33, 64, 100, 1000] {
+                let x = pattern(len, y as usize);
+                let z = pattern(len, len);
+                let mut expected = z.clone();
+                addmul_scalar(&mut expected, &x, y);
+
+                if ssse3 {
+                    let mut got = z.clone();
+                    unsafe { simd::addmul_ssse3(&mut got, &x, y) };
+                    assert_eq!(got, expected, "ssse3 y {} len {}", y, len);
+                }
+                if avx2 {
+                    let mut got = z.clone();
+                    unsafe { simd::addmul_avx2(&mut got, &x, y) };
+                    assert_eq!(got, expected, "avx2 y {} len {}", y, len);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_addmul_multi_matches_scalar() {
+        for sources in [0, 1, 3, 8, 9, 20] {
+            for len in [0, 5, 16, 32, 47, 250] {
+                let xs: Vec<Vec<u8>> = (0..sources).map(|i| pattern(len, i)).collect();
+                let ys: Vec<u8> = (0..sources).map(|i| (i * 97 + 3) as u8).collect();
+                let refs: Vec<&[u8]> = xs.iter().map(|x| x.as_slice()).collect();
+
+                let mut expected = pattern(len, 1000);
+                for (x, &y) in xs.iter().zip(ys.iter()) {
+                    addmul_scalar(&mut expected, x, y);
+                }
+
+                let mut got = pattern(len, 1000);
+                addmul_multi(&mut got, &refs, &ys);
+                assert_eq!(got, expected, "sources {} len {}", sources, len);
+
+                #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+                if is_x86_feature_detected!("ssse3") {
+                    let mut got = pattern(len, 1000);
+                    unsafe { simd::addmul_multi_ssse3(&mut got, &refs, &ys) };
+                    assert_eq!(got, expected, "ssse3 sources {} len {}", sources, len);
+                }
+            }
+        }
+    }
+}
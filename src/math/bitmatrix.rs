@@ -0,0 +1,330 @@
+use crate::galois_field::tables::GF_MUL_TABLE;
+
+/// Number of bytes of every block that are bit-sliced and processed per pass.
+/// Kept small so the input and output planes of a pass stay in cache.
+const PACKET_SIZE: usize = 2048;
+const PACKET_WORDS: usize = PACKET_SIZE / 64;
+
+/// Returns the 8x8 bit-matrix of multiplication by `c` in GF(256).
+///
+/// Bit `s` of row `r` is set when bit `r` of `c * 2^s` is set, so bit `r` of `c * x` is
+/// the parity of `row[r] & x`.
+pub fn gf_bitmatrix(c: u8) -> [u8; 8] {
+    let mut rows = [0u8; 8];
+    for s in 0..8 {
+        let p = GF_MUL_TABLE[c as usize][1 << s];
+        for (r, row) in rows.iter_mut().enumerate() {
+            if (p >> r) & 1 == 1 {
+                *row |= 1 << s;
+            }
+        }
+    }
+    rows
+}
+
+/// Source of an XOR operation: either a bit-plane of an input block or an output
+/// bit-plane that has already been computed earlier in the schedule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XorSource {
+    Input(usize),
+    Output(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XorOp {
+    /// Clear the output plane.
+    Zero(usize),
+    /// Overwrite the output plane with the source.
+    Copy(usize, XorSource),
+    /// XOR the source into the output plane.
+    Xor(usize, XorSource),
+}
+
+/// `XorSchedule` multiplies blocks by a GF(256) matrix using only XORs.
+///
+/// Every coefficient is expanded into its 8x8 bit-matrix, which turns the
+/// `rows x cols` GF(256) matrix into a `8 rows x 8 cols` binary matrix. The blocks are
+/// bit-sliced into planes (plane `8 * j + s` holds bit `s` of every byte of input `j`),
+/// each output plane is the XOR of the input planes selected by its binary row, and
+/// the output planes are transposed back into bytes. The result is byte-identical to
+/// multiplying with `GF_MUL_TABLE`.
+///
+/// When building the schedule an output plane is derived from an already computed
+/// output plane whenever that needs fewer XORs than starting from the inputs.
+#[derive(Debug, Clone)]
+pub struct XorSchedule {
+    rows: usize,
+    cols: usize,
+    ops: Vec<XorOp>,
+}
+
+fn popcount(set: &[u64]) -> u32 {
+    set.iter().map(|w| w.count_ones()).sum()
+}
+
+fn set_bits(set: &[u64]) -> impl Iterator<Item = usize> + '_ {
+    set.iter().enumerate().flat_map(|(i, &w)| {
+        (0..64)
+            .filter(move |b| (w >> b) & 1 == 1)
+            .map(move |b| i * 64 + b)
+    })
+}
+
+impl XorSchedule {
+    /// Builds the schedule for the row-major `rows x cols` GF(256) `matrix`.
+    pub fn new(matrix: &[u8], rows: usize, cols: usize) -> XorSchedule {
+        let set_words = (cols * 8).div_ceil(64);
+        let mut row_sets: Vec<Vec<u64>> = Vec::with_capacity(rows * 8);
+        for i in 0..rows {
+            let bit_matrices: Vec<[u8; 8]> = matrix[i * cols..(i + 1) * cols]
+                .iter()
+                .map(|&c| gf_bitmatrix(c))
+                .collect();
+            for r in 0..8 {
+                let mut set = vec![0u64; set_words];
+                for (j, bm) in bit_matrices.iter().enumerate() {
+                    for s in 0..8 {
+                        if (bm[r] >> s) & 1 == 1 {
+                            let plane = j * 8 + s;
+                            set[plane / 64] |= 1 << (plane % 64);
+                        }
+                    }
+                }
+                row_sets.push(set);
+            }
+        }
+
+        let mut ops = Vec::new();
+        let mut diff = vec![0u64; set_words];
+        for (dst, set) in row_sets.iter().enumerate() {
+            // cost counted as number of region operations (copy + xors)
+            let mut best: Option<usize> = None;
+            let mut best_cost = popcount(set);
+            for (prev, prev_set) in row_sets[..dst].iter().enumerate() {
+                let cost = 1 + set
+                    .iter()
+                    .zip(prev_set.iter())
+                    .map(|(a, b)| (a ^ b).count_ones())
+                    .sum::<u32>();
+                if cost < best_cost {
+                    best = Some(prev);
+                    best_cost = cost;
+                }
+            }
+
+            match best {
+                Some(prev) => {
+                    for (d, (a, b)) in diff.iter_mut().zip(set.iter().zip(row_sets[prev].iter())) {
+                        *d = a ^ b;
+                    }
+                    ops.push(XorOp::Copy(dst, XorSource::Output(prev)));
+                    ops.extend(set_bits(&diff).map(|p| XorOp::Xor(dst, XorSource::Input(p))));
+                }
+                None => {
+                    let mut planes = set_bits(set);
+                    match planes.next() {
+                        Some(first) => {
+                            ops.push(XorOp::Copy(dst, XorSource::Input(first)));
+                            ops.extend(planes.map(|p| XorOp::Xor(dst, XorSource::Input(p))));
+                        }
+                        None => ops.push(XorOp::Zero(dst)),
+                    }
+                }
+            }
+        }
+
+        XorSchedule { rows, cols, ops }
+    }
+
+    /// Operations of the schedule, in execution order.
+    pub fn ops(&self) -> &[XorOp] {
+        &self.ops
+    }
+
+    /// Number of XOR region operations the schedule performs per packet.
+    pub fn xor_count(&self) -> usize {
+        self.ops
+            .iter()
+            .filter(|op| matches!(op, XorOp::Xor(..)))
+            .count()
+    }
+
+    /// Computes `outputs[i] = sum_j matrix[i][j] * inputs[j]` for equally sized blocks.
+    pub fn apply<I: AsRef<[u8]>, O: AsMut<[u8]>>(&self, inputs: &[I], outputs: &mut [O]) {
+        assert_eq!(inputs.len(), self.cols, "wrong number of inputs");
+        assert_eq!(outputs.len(), self.rows, "wrong number of outputs");
+        let len = match outputs.first_mut() {
+            Some(out) => out.as_mut().len(),
+            None => return,
+        };
+
+        let mut in_planes = vec![0u64; self.cols * 8 * PACKET_WORDS];
+        let mut out_planes = vec![0u64; self.rows * 8 * PACKET_WORDS];
+
+        for start in (0..len).step_by(PACKET_SIZE) {
+            let end = usize::min(start + PACKET_SIZE, len);
+            let words = (end - start).div_ceil(64);
+
+            for (j, input) in inputs.iter().enumerate() {
+                let planes = &mut in_planes[j * 8 * PACKET_WORDS..(j + 1) * 8 * PACKET_WORDS];
+                bitslice(&input.as_ref()[start..end], planes);
+            }
+
+            for op in &self.ops {
+                self.execute(*op, &in_planes, &mut out_planes, words);
+            }
+
+            for (i, output) in outputs.iter_mut().enumerate() {
+                let planes = &out_planes[i * 8 * PACKET_WORDS..(i + 1) * 8 * PACKET_WORDS];
+                unbitslice(planes, &mut output.as_mut()[start..end]);
+            }
+        }
+    }
+
+    fn execute(&self, op: XorOp, in_planes: &[u64], out_planes: &mut [u64], words: usize) {
+        let (dst, src, xor) = match op {
+            XorOp::Zero(dst) => {
+                out_planes[dst * PACKET_WORDS..dst * PACKET_WORDS + words].fill(0);
+                return;
+            }
+            XorOp::Copy(dst, src) => (dst, src, false),
+            XorOp::Xor(dst, src) => (dst, src, true),
+        };
+
+        let (lower, upper) = out_planes.split_at_mut(dst * PACKET_WORDS);
+        let dst = &mut upper[..words];
+        let src = match src {
+            XorSource::Input(p) => &in_planes[p * PACKET_WORDS..p * PACKET_WORDS + words],
+            // outputs are only derived from planes computed before them
+            XorSource::Output(p) => &lower[p * PACKET_WORDS..p * PACKET_WORDS + words],
+        };
+
+        if xor {
+            for (d, s) in dst.iter_mut().zip(src.iter()) {
+                *d ^= s;
+            }
+        } else {
+            dst.copy_from_slice(src);
+        }
+    }
+}
+
+/// Transposes the 8x8 bit-matrix held in `x` (byte `r`, bit `c`) so that byte `c`
+/// holds bit `c` of every input byte.
+fn transpose8(mut x: u64) -> u64 {
+    let t = (x ^ (x >> 7)) & 0x00AA_00AA_00AA_00AA;
+    x ^= t ^ (t << 7);
+    let t = (x ^ (x >> 14)) & 0x0000_CCCC_0000_CCCC;
+    x ^= t ^ (t << 14);
+    let t = (x ^ (x >> 28)) & 0x0000_0000_F0F0_F0F0;
+    x ^= t ^ (t << 28);
+    x
+}
+
+/// Splits up to `PACKET_SIZE` bytes into 8 bit-planes of `PACKET_WORDS` words.
+fn bitslice(bytes: &[u8], planes: &mut [u64]) {
+    for (w, chunk) in bytes.chunks(64).enumerate() {
+        let mut words = [0u64; 8];
+        for (g, group) in chunk.chunks(8).enumerate() {
+            let mut buf = [0u8; 8];
+            buf[..group.len()].copy_from_slice(group);
+            let y = transpose8(u64::from_le_bytes(buf));
+            for (s, word) in words.iter_mut().enumerate() {
+                *word |= ((y >> (8 * s)) & 0xFF) << (8 * g);
+            }
+        }
+        for (s, word) in words.iter().enumerate() {
+            planes[s * PACKET_WORDS + w] = *word;
+        }
+    }
+}
+
+/// Inverse of `bitslice`, writing `bytes.len()` bytes.
+fn unbitslice(planes: &[u64], bytes: &mut [u8]) {
+    for (w, chunk) in bytes.chunks_mut(64).enumerate() {
+        for (g, group) in chunk.chunks_mut(8).enumerate() {
+            let mut x = 0u64;
+            for s in 0..8 {
+                x |= ((planes[s * PACKET_WORDS + w] >> (8 * g)) & 0xFF) << (8 * s);
+            }
+            let y = transpose8(x).to_le_bytes();
+            group.copy_from_slice(&y[..group.len()]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::addmul::addmul;
+
+    #[test]
+    fn test_gf_bitmatrix_matches_mul_table() {
+        for (c, products) in GF_MUL_TABLE.iter().enumerate() {
+            let rows = gf_bitmatrix(c as u8);
+            for (x, &expected) in products.iter().enumerate() {
+                let mut product = 0u8;
+                for (r, row) in rows.iter().enumerate() {
+                    product |= (((row & x as u8).count_ones() & 1) as u8) << r;
+                }
+                assert_eq!(product, expected, "{} * {}", c, x);
+            }
+        }
+    }
+
+    #[test]
+    fn test_bitslice_roundtrip() {
+        let bytes: Vec<u8> = (0..PACKET_SIZE - 13).map(|i| (i * 31 + 7) as u8).collect();
+        let mut planes = vec![0u64; 8 * PACKET_WORDS];
+        bitslice(&bytes, &mut planes);
+
+        // plane s, bit t must be bit s of byte t
+        for (t, byte) in bytes.iter().enumerate().take(200) {
+            for s in 0..8 {
+                let bit = (planes[s * PACKET_WORDS + t / 64] >> (t % 64)) & 1;
+                assert_eq!(bit as u8, (byte >> s) & 1);
+            }
+        }
+
+        let mut out = vec![0u8; bytes.len()];
+        unbitslice(&planes, &mut out);
+        assert_eq!(out, bytes);
+    }
+
+    #[test]
+    fn test_apply_matches_addmul() {
+        let (rows, cols) = (3, 5);
+        let matrix: Vec<u8> = (0..rows * cols).map(|i| (i * 53 + 1) as u8).collect();
+        let schedule = XorSchedule::new(&matrix, rows, cols);
+
+        for len in [0, 1, 63, 64, 100, PACKET_SIZE, PACKET_SIZE * 2 + 17] {
+            let inputs: Vec<Vec<u8>> = (0..cols)
+                .map(|j| (0..len).map(|b| (b * 7 + j * 13) as u8).collect())
+                .collect();
+
+            let mut expected = vec![vec![0u8; len]; rows];
+            for (i, out) in expected.iter_mut().enumerate() {
+                for (j, input) in inputs.iter().enumerate() {
+                    addmul(out, input, matrix[i * cols + j]);
+                }
+            }
+
+            let mut outputs = vec![vec![0xAAu8; len]; rows];
+            schedule.apply(&inputs, &mut outputs);
+            assert_eq!(outputs, expected, "len {}", len);
+        }
+    }
+
+    #[test]
+    fn test_schedule_reuses_outputs() {
+        // two identical rows: the second one should be a single copy
+        let matrix = vec![7, 9, 11, 7, 9, 11];
+        let schedule = XorSchedule::new(&matrix, 2, 3);
+        let single = XorSchedule::new(&matrix[..3], 1, 3);
+
+        assert_eq!(schedule.xor_count(), single.xor_count());
+        assert!(schedule
+            .ops()
+            .contains(&XorOp::Copy(8, XorSource::Output(0))));
+    }
+}
@@ -0,0 +1,86 @@
+use crate::galois_field::tables::{GF_INVERSE, GF_MUL_TABLE};
+
+/// Evaluation point used by the Cauchy construction for share `num`.
+///
+/// Data pieces use the points `0..k` and parity pieces the points `k..n`, so every
+/// share number maps to itself as a field element. All points are distinct, which
+/// is what makes every square submatrix of the Cauchy matrix invertible.
+pub fn cauchy_point(num: usize) -> u8 {
+    num as u8
+}
+
+/// Column multiplier of share `num` when the Cauchy code is viewed as a generalized
+/// Reed-Solomon code over the points given by `cauchy_point`.
+///
+/// For a data piece `j` this is `1 / prod_{j' != j} (y_j + y_j')` and for a parity
+/// piece `i` it is `1 / prod_j (x_i + y_j)`, where `y` are the data points and `x` the
+/// parity points. With these multipliers the codeword `(v_l * P(a_l))` of any
+/// polynomial `P` of degree `< k` is exactly the systematic Cauchy encoding of the
+/// data `(v_j * P(y_j))`.
+pub fn cauchy_multiplier(num: usize, k: usize) -> u8 {
+    let x = cauchy_point(num) as usize;
+    let mut prod = 1u8;
+    for j in 0..k {
+        if j == num {
+            continue;
+        }
+        prod = GF_MUL_TABLE[prod as usize][x ^ cauchy_point(j) as usize];
+    }
+    GF_INVERSE[prod as usize]
+}
+
+/// Fills `enc_matrix` (n rows, k columns) with a systematic Cauchy encoding matrix.
+///
+/// The first k rows are the identity and row `i >= k`, column `j` holds
+/// `1 / (x_i + y_j)`.
+pub fn create_cauchy_matrix(enc_matrix: &mut [u8], k: usize, n: usize) {
+    enc_matrix[..n * k].fill(0);
+
+    for i in 0..k {
+        enc_matrix[i * (k + 1)] = 1;
+    }
+
+    for row in k..n {
+        let x = cauchy_point(row);
+        for col in 0..k {
+            let y = cauchy_point(col);
+            enc_matrix[row * k + col] = GF_INVERSE[(x ^ y) as usize];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::pivot_searcher::invert_matrix;
+
+    #[test]
+    fn test_cauchy_matrix_is_systematic() {
+        let (k, n) = (3, 6);
+        let mut matrix = vec![0u8; n * k];
+        create_cauchy_matrix(&mut matrix, k, n);
+
+        assert_eq!(&matrix[..k * k], &[1, 0, 0, 0, 1, 0, 0, 0, 1]);
+        // 1 / (3 ^ 0) = 1 / 3
+        assert_eq!(matrix[k * k], GF_INVERSE[3]);
+    }
+
+    #[test]
+    fn test_cauchy_square_submatrices_invertible() {
+        let (k, n) = (4, 10);
+        let mut matrix = vec![0u8; n * k];
+        create_cauchy_matrix(&mut matrix, k, n);
+
+        // every choice of k rows must give an invertible matrix
+        for mask in 0u32..(1 << n) {
+            if mask.count_ones() as usize != k {
+                continue;
+            }
+            let mut sub = Vec::with_capacity(k * k);
+            for row in (0..n).filter(|row| mask & (1 << row) != 0) {
+                sub.extend_from_slice(&matrix[row * k..(row + 1) * k]);
+            }
+            assert!(invert_matrix(&mut sub, k).is_ok(), "rows {:b}", mask);
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use crate::math::addmul::addmul_multi;
+
+/// Bytes of all inputs and outputs a tile should occupy together, sized for L2.
+const TILE_BUDGET: usize = 128 * 1024;
+const MIN_TILE_SIZE: usize = 256;
+const MAX_TILE_SIZE: usize = 64 * 1024;
+
+/// `tile_size()` returns the tile width `matmul_tiled()` uses for `blocks` blocks
+/// (inputs plus outputs), so that one tile of every block fits in cache together.
+pub fn tile_size(blocks: usize) -> usize {
+    let tile = TILE_BUDGET / blocks.max(1);
+    tile.clamp(MIN_TILE_SIZE, MAX_TILE_SIZE) / 64 * 64
+}
+
+/// `matmul_tiled()` computes `outputs[i] = sum_j matrix[i * inputs.len() + j] * inputs[j]`.
+///
+/// The blocks are processed in tiles of `tile` bytes and every output is updated for a
+/// tile before moving on, so each input is streamed from memory once no matter how
+/// many outputs there are. Outputs are overwritten.
+pub fn matmul_tiled<O: AsMut<[u8]>>(
+    matrix: &[u8],
+    inputs: &[&[u8]],
+    outputs: &mut [O],
+    tile: usize,
+) {
+    let cols = inputs.len();
+    assert_eq!(
+        matrix.len(),
+        cols * outputs.len(),
+        "matrix does not match blocks"
+    );
+    assert!(tile > 0, "tile size must be positive");
+
+    let len = match outputs.first_mut() {
+        Some(out) => out.as_mut().len(),
+        None => return,
+    };
+
+    let mut tiles: Vec<&[u8]> = inputs.to_vec();
+    for start in (0..len).step_by(tile) {
+        let end = usize::min(start + tile, len);
+        for (t, input) in tiles.iter_mut().zip(inputs.iter()) {
+            *t = &input[start..end];
+        }
+
+        for (i, output) in outputs.iter_mut().enumerate() {
+            let out = &mut output.as_mut()[start..end];
+            out.fill(0);
+            addmul_multi(out, &tiles, &matrix[i * cols..(i + 1) * cols]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::addmul::addmul;
+
+    #[test]
+    fn test_matmul_tiled_matches_row_by_row() {
+        let (rows, cols) = (4, 6);
+        let matrix: Vec<u8> = (0..rows * cols).map(|i| (i * 71 + 5) as u8).collect();
+        let len = 1000;
+        let inputs: Vec<Vec<u8>> = (0..cols)
+            .map(|j| (0..len).map(|b| (b * 13 + j * 41) as u8).collect())
+            .collect();
+        let refs: Vec<&[u8]> = inputs.iter().map(|x| x.as_slice()).collect();
+
+        let mut expected = vec![vec![0u8; len]; rows];
+        for (i, out) in expected.iter_mut().enumerate() {
+            for (j, input) in inputs.iter().enumerate() {
+                addmul(out, input, matrix[i * cols + j]);
+            }
+        }
+
+        for tile in [1, 64, 333, len, 4096] {
+            let mut outputs = vec![vec![0xFFu8; len]; rows];
+            matmul_tiled(&matrix, &refs, &mut outputs, tile);
+            assert_eq!(outputs, expected, "tile {}", tile);
+        }
+    }
+
+    #[test]
+    fn test_tile_size_bounds() {
+        assert_eq!(tile_size(1), MAX_TILE_SIZE);
+        assert_eq!(tile_size(100_000), MIN_TILE_SIZE);
+        assert_eq!(tile_size(24) % 64, 0);
+    }
+}
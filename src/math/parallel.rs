@@ -0,0 +1,65 @@
+use rayon::prelude::*;
+
+/// Default for `FEC::min_parallel_len`: blocks shorter than twice this many bytes are
+/// processed on the calling thread.
+pub const DEFAULT_MIN_PARALLEL_LEN: usize = 64 * 1024;
+
+/// `for_each_column_chunk()` splits equally sized blocks into column ranges of at least
+/// `min_len` bytes and runs `kernel` on every range in parallel.
+///
+/// `kernel` receives the slices of `inputs` and `outputs` for one range. Columns are
+/// independent in every block product used by `FEC`, so the ranges can be processed in
+/// any order.
+pub fn for_each_column_chunk<F>(
+    inputs: &[&[u8]],
+    outputs: &mut [&mut [u8]],
+    min_len: usize,
+    kernel: F,
+) where
+    F: Fn(&[&[u8]], &mut [&mut [u8]]) + Sync,
+{
+    let len = match outputs.first() {
+        Some(out) => out.len(),
+        None => return,
+    };
+    let chunk = usize::max(min_len.max(1), len.div_ceil(rayon::current_num_threads()));
+
+    // transpose the per-block chunks into per-range lists of output slices
+    let mut ranges: Vec<Vec<&mut [u8]>> = (0..len.div_ceil(chunk)).map(|_| Vec::new()).collect();
+    for output in outputs.iter_mut() {
+        for (range, piece) in ranges.iter_mut().zip(output.chunks_mut(chunk)) {
+            range.push(piece);
+        }
+    }
+
+    ranges
+        .into_par_iter()
+        .enumerate()
+        .for_each(|(i, mut outs)| {
+            let start = i * chunk;
+            let end = usize::min(start + chunk, len);
+            let ins: Vec<&[u8]> = inputs.iter().map(|input| &input[start..end]).collect();
+            kernel(&ins, &mut outs);
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_each_column_chunk_covers_every_column() {
+        let input: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+        let mut a = vec![0u8; 1000];
+        let mut b = vec![0u8; 1000];
+
+        for_each_column_chunk(&[&input], &mut [&mut a, &mut b], 7, |ins, outs| {
+            for out in outs.iter_mut() {
+                out.copy_from_slice(ins[0]);
+            }
+        });
+
+        assert_eq!(a, input);
+        assert_eq!(b, input);
+    }
+}
@@ -0,0 +1,158 @@
+//! Split-nibble (`pshufb`) GF(256) multiply-accumulate kernels for x86.
+//!
+//! `y * x` is linear in `x`, so it equals `y * (x & 0x0f) ^ y * (x & 0xf0)`. Both halves
+//! only take 16 values, which fit in one 16-byte shuffle table each: the low nibbles
+//! of 16 (or 32) bytes index `lo`, the high nibbles index `hi`, and the two shuffles are
+//! XORed together.
+
+#[cfg(target_arch = "x86")]
+use std::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+use crate::galois_field::tables::GF_MUL_TABLE;
+use crate::math::addmul::addmul_scalar;
+
+/// Maximum number of sources a fused kernel keeps tables for in registers at once.
+const GROUP: usize = 8;
+
+/// Returns the shuffle tables `(y * i, y * (i << 4))` for `i` in `0..16`.
+pub fn nibble_tables(y: u8) -> ([u8; 16], [u8; 16]) {
+    let gf_mul_y = &GF_MUL_TABLE[y as usize];
+    let mut lo = [0u8; 16];
+    let mut hi = [0u8; 16];
+    for i in 0..16 {
+        lo[i] = gf_mul_y[i];
+        hi[i] = gf_mul_y[i << 4];
+    }
+    (lo, hi)
+}
+
+/// # Safety
+///
+/// The CPU must support SSSE3 and `x` must be at least as long as `z`.
+#[target_feature(enable = "ssse3")]
+pub unsafe fn addmul_ssse3(z: &mut [u8], x: &[u8], y: u8) {
+    let (lo, hi) = nibble_tables(y);
+    let lo = _mm_loadu_si128(lo.as_ptr() as *const __m128i);
+    let hi = _mm_loadu_si128(hi.as_ptr() as *const __m128i);
+    let mask = _mm_set1_epi8(0x0f);
+
+    let chunks = z.len() / 16;
+    for i in 0..chunks {
+        let zp = z.as_mut_ptr().add(i * 16) as *mut __m128i;
+        let xv = _mm_loadu_si128(x.as_ptr().add(i * 16) as *const __m128i);
+        let l = _mm_shuffle_epi8(lo, _mm_and_si128(xv, mask));
+        let h = _mm_shuffle_epi8(hi, _mm_and_si128(_mm_srli_epi64(xv, 4), mask));
+        let acc = _mm_xor_si128(_mm_loadu_si128(zp), _mm_xor_si128(l, h));
+        _mm_storeu_si128(zp, acc);
+    }
+
+    let done = chunks * 16;
+    addmul_scalar(&mut z[done..], &x[done..], y);
+}
+
+/// # Safety
+///
+/// The CPU must support AVX2 and `x` must be at least as long as `z`.
+#[target_feature(enable = "avx2")]
+pub unsafe fn addmul_avx2(z: &mut [u8], x: &[u8], y: u8) {
+    let (lo, hi) = nibble_tables(y);
+    let lo = _mm256_broadcastsi128_si256(_mm_loadu_si128(lo.as_ptr() as *const __m128i));
+    let hi = _mm256_broadcastsi128_si256(_mm_loadu_si128(hi.as_ptr() as *const __m128i));
+    let mask = _mm256_set1_epi8(0x0f);
+
+    let chunks = z.len() / 32;
+    for i in 0..chunks {
+        let zp = z.as_mut_ptr().add(i * 32) as *mut __m256i;
+        let xv = _mm256_loadu_si256(x.as_ptr().add(i * 32) as *const __m256i);
+        let l = _mm256_shuffle_epi8(lo, _mm256_and_si256(xv, mask));
+        let h = _mm256_shuffle_epi8(hi, _mm256_and_si256(_mm256_srli_epi64(xv, 4), mask));
+        let acc = _mm256_xor_si256(_mm256_loadu_si256(zp), _mm256_xor_si256(l, h));
+        _mm256_storeu_si256(zp, acc);
+    }
+
+    let done = chunks * 32;
+    addmul_ssse3(&mut z[done..], &x[done..], y);
+}
+
+/// Fused `z ^= sum_i ys[i] * xs[i]`: every chunk of `z` is loaded and stored once per
+/// group of `GROUP` sources instead of once per source.
+///
+/// # Safety
+///
+/// The CPU must support SSSE3, `xs` and `ys` must have the same length and every
+/// source must be at least as long as `z`.
+#[target_feature(enable = "ssse3")]
+pub unsafe fn addmul_multi_ssse3(z: &mut [u8], xs: &[&[u8]], ys: &[u8]) {
+    let mask = _mm_set1_epi8(0x0f);
+    let chunks = z.len() / 16;
+
+    for (xs, ys) in xs.chunks(GROUP).zip(ys.chunks(GROUP)) {
+        let mut tables = [(_mm_setzero_si128(), _mm_setzero_si128()); GROUP];
+        for (table, &y) in tables.iter_mut().zip(ys.iter()) {
+            let (lo, hi) = nibble_tables(y);
+            *table = (
+                _mm_loadu_si128(lo.as_ptr() as *const __m128i),
+                _mm_loadu_si128(hi.as_ptr() as *const __m128i),
+            );
+        }
+
+        for i in 0..chunks {
+            let zp = z.as_mut_ptr().add(i * 16) as *mut __m128i;
+            let mut acc = _mm_loadu_si128(zp);
+            for (x, &(lo, hi)) in xs.iter().zip(tables.iter()) {
+                let xv = _mm_loadu_si128(x.as_ptr().add(i * 16) as *const __m128i);
+                let l = _mm_shuffle_epi8(lo, _mm_and_si128(xv, mask));
+                let h = _mm_shuffle_epi8(hi, _mm_and_si128(_mm_srli_epi64(xv, 4), mask));
+                acc = _mm_xor_si128(acc, _mm_xor_si128(l, h));
+            }
+            _mm_storeu_si128(zp, acc);
+        }
+
+        let done = chunks * 16;
+        for (x, &y) in xs.iter().zip(ys.iter()) {
+            addmul_scalar(&mut z[done..], &x[done..], y);
+        }
+    }
+}
+
+/// AVX2 version of `addmul_multi_ssse3`.
+///
+/// # Safety
+///
+/// The CPU must support AVX2, `xs` and `ys` must have the same length and every
+/// source must be at least as long as `z`.
+#[target_feature(enable = "avx2")]
+pub unsafe fn addmul_multi_avx2(z: &mut [u8], xs: &[&[u8]], ys: &[u8]) {
+    let mask = _mm256_set1_epi8(0x0f);
+    let chunks = z.len() / 32;
+
+    for (xs, ys) in xs.chunks(GROUP).zip(ys.chunks(GROUP)) {
+        let mut tables = [(_mm256_setzero_si256(), _mm256_setzero_si256()); GROUP];
+        for (table, &y) in tables.iter_mut().zip(ys.iter()) {
+            let (lo, hi) = nibble_tables(y);
+            *table = (
+                _mm256_broadcastsi128_si256(_mm_loadu_si128(lo.as_ptr() as *const __m128i)),
+                _mm256_broadcastsi128_si256(_mm_loadu_si128(hi.as_ptr() as *const __m128i)),
+            );
+        }
+
+        for i in 0..chunks {
+            let zp = z.as_mut_ptr().add(i * 32) as *mut __m256i;
+            let mut acc = _mm256_loadu_si256(zp);
+            for (x, &(lo, hi)) in xs.iter().zip(tables.iter()) {
+                let xv = _mm256_loadu_si256(x.as_ptr().add(i * 32) as *const __m256i);
+                let l = _mm256_shuffle_epi8(lo, _mm256_and_si256(xv, mask));
+                let h = _mm256_shuffle_epi8(hi, _mm256_and_si256(_mm256_srli_epi64(xv, 4), mask));
+                acc = _mm256_xor_si256(acc, _mm256_xor_si256(l, h));
+            }
+            _mm256_storeu_si256(zp, acc);
+        }
+
+        let done = chunks * 32;
+        for (x, &y) in xs.iter().zip(ys.iter()) {
+            addmul_ssse3(&mut z[done..], &x[done..], y);
+        }
+    }
+}
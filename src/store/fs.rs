@@ -0,0 +1,194 @@
+use crate::store::share_store::{check_id, ShareStore};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Extension of the files holding shares.
+const SHARE_EXT: &str = "share";
+
+/// `FsStore` keeps shares as files under one or more root directories, typically one
+/// per disk. Share `number` of object `id` is written to
+/// `<roots[number % roots.len()]>/<id>/<number>.share`, the number padded to three
+/// digits (`obj/004.share`), so the shares of an object are spread over the roots and
+/// losing a root loses at most `ceil(n / roots)` of them.
+///
+/// Reads and listings look in every root, so shares stay readable if the list of roots
+/// changes.
+#[derive(Debug, Clone)]
+pub struct FsStore {
+    roots: Vec<PathBuf>,
+}
+
+impl FsStore {
+    /// `new()` creates a store over `roots`, creating the directories if needed.
+    pub fn new(roots: Vec<PathBuf>) -> io::Result<FsStore> {
+        if roots.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a filesystem store needs at least one root",
+            ));
+        }
+        for root in &roots {
+            fs::create_dir_all(root)?;
+        }
+        Ok(FsStore { roots })
+    }
+
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    /// `path()` returns where share `number` of `id` is written.
+    pub fn path(&self, id: &str, number: usize) -> PathBuf {
+        share_path(&self.roots[number % self.roots.len()], id, number)
+    }
+
+    /// `find()` returns the path of the stored share `number` of `id`, looking in the
+    /// root it is written to first.
+    fn find(&self, id: &str, number: usize) -> Option<PathBuf> {
+        let home = number % self.roots.len();
+        let roots = self.roots[home..].iter().chain(&self.roots[..home]);
+        roots
+            .map(|root| share_path(root, id, number))
+            .find(|path| path.is_file())
+    }
+}
+
+fn share_path(root: &Path, id: &str, number: usize) -> PathBuf {
+    root.join(id).join(format!("{:03}.{}", number, SHARE_EXT))
+}
+
+/// `read_dir_names()` returns the names in `dir`, or nothing if it does not exist.
+fn read_dir_names(dir: &Path) -> io::Result<Vec<String>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut names = Vec::new();
+    for entry in entries {
+        if let Ok(name) = entry?.file_name().into_string() {
+            names.push(name);
+        }
+    }
+    Ok(names)
+}
+
+impl ShareStore for FsStore {
+    /// `put()` writes the share to a temporary file and renames it into place, so a
+    /// share is never seen half written.
+    fn put(&self, id: &str, number: usize, data: &[u8]) -> io::Result<()> {
+        check_id(id)?;
+        let path = self.path(id, number);
+        fs::create_dir_all(path.parent().unwrap())?;
+        let partial = path.with_extension("partial");
+        let mut file = fs::File::create(&partial)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        fs::rename(&partial, &path)?;
+
+        // drop stale copies left in other roots by an earlier list of roots
+        for root in &self.roots {
+            let other = share_path(root, id, number);
+            if other != path && other.is_file() {
+                fs::remove_file(other)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn get(&self, id: &str, number: usize) -> io::Result<Option<Vec<u8>>> {
+        check_id(id)?;
+        match self.find(id, number) {
+            Some(path) => match fs::read(path) {
+                Ok(data) => Ok(Some(data)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn list(&self, id: &str) -> io::Result<Vec<usize>> {
+        check_id(id)?;
+        let mut numbers = BTreeSet::new();
+        for root in &self.roots {
+            for name in read_dir_names(&root.join(id))? {
+                let number = name
+                    .strip_suffix(SHARE_EXT)
+                    .and_then(|n| n.strip_suffix('.'))
+                    .and_then(|n| n.parse::<usize>().ok());
+                numbers.extend(number);
+            }
+        }
+        Ok(numbers.into_iter().collect())
+    }
+
+    fn delete(&self, id: &str, number: usize) -> io::Result<()> {
+        check_id(id)?;
+        for root in &self.roots {
+            match fs::remove_file(share_path(root, id, number)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+            // drop the object directory once it is empty; failing means it is not
+            let _ = fs::remove_dir(root.join(id));
+        }
+        Ok(())
+    }
+
+    fn objects(&self) -> io::Result<Vec<String>> {
+        let mut ids = BTreeSet::new();
+        for root in &self.roots {
+            for name in read_dir_names(root)? {
+                if check_id(&name).is_ok() && root.join(&name).is_dir() {
+                    ids.insert(name);
+                }
+            }
+        }
+        Ok(ids.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fec::fec::FEC;
+    use crate::store::share_store::{get_object, put_object};
+
+    #[test]
+    fn test_fs_store_spreads_shares_over_roots() {
+        let base = std::env::temp_dir().join(format!("rs-fs-store-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        let roots: Vec<PathBuf> = (0..3).map(|i| base.join(format!("disk{}", i))).collect();
+        let store = FsStore::new(roots.clone()).unwrap();
+
+        let fec = FEC::new(4, 6).unwrap();
+        let data: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+        put_object(&store, &fec, "photo.jpg", &data).unwrap();
+        put_object(&store, &fec, "notes", b"short").unwrap();
+        assert!(roots[1].join("photo.jpg/004.share").is_file());
+        assert_eq!(store.list("photo.jpg").unwrap(), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(store.objects().unwrap(), vec!["notes", "photo.jpg"]);
+
+        // losing a whole root loses two of the six shares
+        fs::remove_dir_all(&roots[2]).unwrap();
+        assert_eq!(store.list("photo.jpg").unwrap(), vec![0, 1, 3, 4]);
+        assert_eq!(get_object(&store, "photo.jpg").unwrap(), data);
+
+        // a store with the roots in another order still finds every share
+        let reordered = FsStore::new(vec![roots[1].clone(), roots[0].clone()]).unwrap();
+        assert_eq!(reordered.path("notes", 1), roots[0].join("notes/001.share"));
+        assert_eq!(
+            reordered.get("notes", 1).unwrap(),
+            store.get("notes", 1).unwrap()
+        );
+        reordered.put("notes", 1, b"moved").unwrap();
+        assert!(!roots[1].join("notes/001.share").exists());
+        reordered.delete("notes", 1).unwrap();
+        assert_eq!(store.list("notes").unwrap(), vec![0, 3, 4]);
+
+        let _ = fs::remove_dir_all(&base);
+    }
+}
@@ -0,0 +1,358 @@
+use crate::fec::fec::Share;
+use crate::format::share_file::{ShareFile, ShareHeader};
+use crate::store::share_store::{check_id, decode_stripes, ShareStore};
+use std::error::Error;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// `Cancel` tells a fetch that its share is no longer needed. Fetches that can stop
+/// early, such as one reading in chunks, should check it; the others run to the end
+/// and their result is dropped.
+#[derive(Debug, Clone, Default)]
+pub struct Cancel(Arc<AtomicBool>);
+
+impl Cancel {
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed)
+    }
+}
+
+/// `Fetch` retrieves the share file of one share from one holder, or `None` if the
+/// holder does not have it. Every fetch of a read runs on its own thread.
+pub type Fetch = Box<dyn FnOnce(&Cancel) -> io::Result<Option<Vec<u8>>> + Send>;
+
+/// `HedgedRead` reads an object from several holders of its shares at once, such as a
+/// mix of fast and slow replicas, and decodes it from whichever shares arrive first.
+///
+/// Every fetch is started at once. Each share file that arrives is validated by its
+/// checksum; duplicates of a share number, as held by several replicas, are skipped.
+/// If the shares disagree about the encoding, the most common one wins. As soon as k
+/// shares of that encoding are in hand the read stops, unless `with_extra_shares()`
+/// asks it to wait a while longer for shares that allow correcting errors. The fetches
+/// still running are then cancelled.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HedgedRead {
+    extra_shares: usize,
+    extra_wait: Duration,
+}
+
+/// `HedgedOutcome` is the result of `HedgedRead::read()`.
+#[derive(Debug)]
+pub struct HedgedOutcome {
+    pub data: Vec<u8>,
+    /// Header of the shares the data was decoded from.
+    pub header: ShareHeader,
+    /// Numbers of the shares the data was decoded from, ascending.
+    pub used: Vec<usize>,
+    /// Numbers of the shares in which errors were corrected.
+    pub corrected: Vec<usize>,
+    /// Fetches that failed or returned no usable share, by index, with the reason.
+    pub rejected: Vec<(usize, String)>,
+    /// Number of fetches still running when the read finished.
+    pub cancelled: usize,
+}
+
+impl HedgedRead {
+    pub fn new() -> HedgedRead {
+        HedgedRead::default()
+    }
+
+    /// `with_extra_shares()` makes the read wait up to `max_wait` after the k-th share
+    /// for up to `extra_shares` more, so that up to `extra_shares / 2` shares damaged
+    /// before their checksum was computed can be corrected.
+    pub fn with_extra_shares(mut self, extra_shares: usize, max_wait: Duration) -> HedgedRead {
+        self.extra_shares = extra_shares;
+        self.extra_wait = max_wait;
+        self
+    }
+
+    /// `read()` runs `fetches` and decodes the object from the first shares to arrive.
+    /// It fails if all fetches finished without yielding k usable shares.
+    pub fn read(&self, fetches: Vec<Fetch>) -> Result<HedgedOutcome, Box<dyn Error>> {
+        let cancel = Cancel::default();
+        let (tx, rx) = mpsc::channel();
+        let mut running = fetches.len();
+        for (index, fetch) in fetches.into_iter().enumerate() {
+            let (cancel, tx) = (cancel.clone(), tx.clone());
+            thread::spawn(move || {
+                let result = fetch(&cancel);
+                // the receiver is gone once the read finished
+                let _ = tx.send((index, result));
+            });
+        }
+        drop(tx);
+
+        // valid share files with the index of their fetch
+        let mut files: Vec<(usize, ShareFile)> = Vec::new();
+        let mut rejected = Vec::new();
+        let mut deadline: Option<Instant> = None;
+        while running > 0 {
+            if let Some((header, count)) = majority(&files) {
+                if count >= header.k + self.extra_shares {
+                    break;
+                }
+            }
+            let received = match deadline {
+                Some(deadline) => {
+                    let wait = deadline.saturating_duration_since(Instant::now());
+                    match rx.recv_timeout(wait) {
+                        Ok(received) => received,
+                        // past the deadline, or every fetch has finished
+                        Err(_) => break,
+                    }
+                }
+                None => match rx.recv() {
+                    Ok(received) => received,
+                    Err(_) => break,
+                },
+            };
+            running -= 1;
+
+            let (index, result) = received;
+            let file = match result {
+                Ok(Some(bytes)) => ShareFile::from_bytes(&bytes).map_err(|e| e.to_string()),
+                Ok(None) => Err("share not held".to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+            match file {
+                Ok(file)
+                    if files.iter().any(|(_, f)| {
+                        f.header.number == file.header.number
+                            && f.header.is_compatible(&file.header)
+                    }) => {}
+                Ok(file) => {
+                    files.push((index, file));
+                    let enough = majority(&files).is_some_and(|(h, count)| count >= h.k);
+                    if deadline.is_none() && enough {
+                        deadline = Some(Instant::now() + self.extra_wait);
+                    }
+                }
+                Err(reason) => rejected.push((index, reason)),
+            }
+        }
+        cancel.cancel();
+
+        let header = match majority(&files) {
+            Some((header, count)) if count >= header.k => header,
+            found => {
+                return Err(format!(
+                    "found {} usable shares, not enough to decode ({})",
+                    found.map_or(0, |(_, count)| count),
+                    rejected
+                        .iter()
+                        .map(|(index, reason)| format!("fetch {}: {}", index, reason))
+                        .collect::<Vec<_>>()
+                        .join("; ")
+                )
+                .into())
+            }
+        };
+        let (files, others): (Vec<_>, Vec<_>) = files
+            .into_iter()
+            .partition(|(_, f)| f.header.is_compatible(&header));
+        for (index, _) in others {
+            rejected.push((index, "share of a different encoding".to_string()));
+        }
+        rejected.sort_by_key(|(index, _)| *index);
+
+        let mut shares: Vec<Share> = files.into_iter().map(|(_, f)| f.into_share()).collect();
+        shares.sort_by_key(|s| s.number);
+        let used: Vec<usize> = shares.iter().map(|s| s.number).collect();
+        let mut data = Vec::with_capacity(header.original_len as usize);
+        let corrected = decode_stripes(&header, &shares, |stripe, pieces| {
+            data.extend_from_slice(&pieces[..stripe.len])
+        })?;
+        Ok(HedgedOutcome {
+            data,
+            header,
+            used,
+            corrected,
+            rejected,
+            cancelled: running,
+        })
+    }
+}
+
+/// `majority()` returns the encoding most of `files` agree on, with the number of files
+/// of that encoding.
+fn majority(files: &[(usize, ShareFile)]) -> Option<(ShareHeader, usize)> {
+    files
+        .iter()
+        .map(|(_, f)| {
+            let count = files
+                .iter()
+                .filter(|(_, o)| o.header.is_compatible(&f.header))
+                .count();
+            (f.header, count)
+        })
+        .max_by_key(|(_, count)| *count)
+}
+
+/// `store_fetches()` returns a fetch of each of `numbers` of object `id` from `store`,
+/// to combine with the fetches from the other holders.
+pub fn store_fetches<S>(
+    store: &Arc<S>,
+    id: &str,
+    numbers: impl IntoIterator<Item = usize>,
+) -> io::Result<Vec<Fetch>>
+where
+    S: ShareStore + Send + Sync + ?Sized + 'static,
+{
+    check_id(id)?;
+    Ok(numbers
+        .into_iter()
+        .map(|number| {
+            let (store, id) = (store.clone(), id.to_string());
+            Box::new(move |_: &Cancel| store.get(&id, number)) as Fetch
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fec::fec::FEC;
+    use crate::fec::striped::StripedCodec;
+    use crate::format::share_file::ShareWriter;
+    use crate::store::memory::MemoryStore;
+    use crate::store::share_store::put_object;
+    use crate::stream::encoder::StreamEncoder;
+    use std::io::{Cursor, Write};
+    use std::sync::atomic::AtomicUsize;
+
+    /// `slow()` is a fetch that takes `delay` unless cancelled first, counting the
+    /// cancellations it noticed in `noticed`.
+    fn slow(bytes: Vec<u8>, delay: Duration, noticed: Arc<AtomicUsize>) -> Fetch {
+        Box::new(move |cancel: &Cancel| {
+            let start = Instant::now();
+            while start.elapsed() < delay {
+                if cancel.is_cancelled() {
+                    noticed.fetch_add(1, Ordering::Relaxed);
+                    return Err(io::Error::new(io::ErrorKind::Interrupted, "cancelled"));
+                }
+                thread::sleep(Duration::from_millis(5));
+            }
+            Ok(Some(bytes))
+        })
+    }
+
+    fn ready(result: io::Result<Option<Vec<u8>>>) -> Fetch {
+        Box::new(move |_: &Cancel| result)
+    }
+
+    #[test]
+    fn test_hedged_read_returns_with_the_first_k_shares() {
+        let store = Arc::new(MemoryStore::new());
+        let fec = FEC::new(3, 6).unwrap();
+        let data = b"returned as soon as any three shares arrive".to_vec();
+        put_object(&*store, &fec, "obj", &data).unwrap();
+        let share = |n| store.get("obj", n).unwrap().unwrap();
+
+        let noticed = Arc::new(AtomicUsize::new(0));
+        let delay = |n, ms| slow(share(n), Duration::from_millis(ms), noticed.clone());
+        let fetches = vec![
+            delay(0, 5000),
+            delay(1, 5000),
+            ready(Err(io::Error::new(io::ErrorKind::TimedOut, "replica down"))),
+            ready(Ok(Some(b"garbage".to_vec()))),
+            ready(Ok(Some(share(4)))),
+            // a second replica of share 4
+            delay(4, 100),
+            delay(3, 200),
+            delay(5, 200),
+        ];
+
+        let start = Instant::now();
+        let outcome = HedgedRead::new().read(fetches).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert_eq!(outcome.data, data);
+        assert_eq!(outcome.used, vec![3, 4, 5]);
+        let rejected: Vec<usize> = outcome.rejected.iter().map(|(i, _)| *i).collect();
+        assert_eq!(rejected, vec![2, 3]);
+        assert_eq!(outcome.cancelled, 2);
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(noticed.load(Ordering::Relaxed), 2);
+
+        let outcome = HedgedRead::new()
+            .read(store_fetches(&store, "obj", 0..6).unwrap())
+            .unwrap();
+        assert_eq!(outcome.data, data);
+        let e = HedgedRead::new()
+            .read(store_fetches(&store, "obj", [0, 1, 9]).unwrap())
+            .unwrap_err();
+        assert!(e.to_string().contains("found 2 usable shares"), "{}", e);
+
+        // the first share to arrive is left over from an older encoding of the object
+        put_object(&*store, &FEC::new(2, 4).unwrap(), "old", b"stale").unwrap();
+        let fetches = vec![
+            ready(Ok(Some(store.get("old", 0).unwrap().unwrap()))),
+            delay(0, 100),
+            delay(1, 100),
+            delay(2, 100),
+        ];
+        let outcome = HedgedRead::new().read(fetches).unwrap();
+        assert_eq!(outcome.data, data);
+        assert_eq!(outcome.rejected.len(), 1);
+        assert_eq!(outcome.rejected[0].0, 0);
+    }
+
+    #[test]
+    fn test_hedged_read_waits_for_extra_shares_to_correct() {
+        let fec = FEC::new(3, 6).unwrap();
+        let codec = StripedCodec::new(FEC::new(3, 6).unwrap(), 300).unwrap();
+        let data: Vec<u8> = (0..1000u32).map(|i| (i % 241) as u8).collect();
+        let sinks = (0..6)
+            .map(|n| ShareWriter::new(Cursor::new(Vec::new()), &fec, n, 300).unwrap())
+            .collect();
+        let mut encoder = StreamEncoder::new(codec, sinks).unwrap();
+        encoder.write_all(&data).unwrap();
+        let (sinks, len) = encoder.finish().unwrap();
+        let files: Vec<Vec<u8>> = sinks
+            .into_iter()
+            .map(|w| w.finish(len).unwrap().into_inner())
+            .collect();
+        let fetches = |files: &[Vec<u8>], fast: usize| -> Vec<Fetch> {
+            let noticed = Arc::new(AtomicUsize::new(0));
+            let slow_file = |f: &Vec<u8>| slow(f.clone(), Duration::from_secs(5), noticed.clone());
+            let fast_file = |f: &Vec<u8>| ready(Ok(Some(f.clone())));
+            files[..fast]
+                .iter()
+                .map(fast_file)
+                .chain(files[fast..].iter().map(slow_file))
+                .collect()
+        };
+
+        // with k shares in, the read waits for extra ones until the deadline
+        let read = HedgedRead::new().with_extra_shares(2, Duration::from_millis(200));
+        let start = Instant::now();
+        let outcome = read.read(fetches(&files, 3)).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        assert_eq!(outcome.data, data);
+        assert_eq!((outcome.used.len(), outcome.cancelled), (3, 3));
+
+        // share 0 damaged in its second stripe before its checksum was computed
+        let mut damaged = files.clone();
+        let mut share = ShareFile::from_bytes(&files[0]).unwrap().into_share();
+        share.data[150] ^= 0xFF;
+        damaged[0] = ShareFile::new(&fec, 300, len, share).to_bytes();
+        let outcome = HedgedRead::new().read(fetches(&damaged, 5)).unwrap();
+        assert_eq!(outcome.used.len(), 3);
+        assert_ne!(outcome.data, data);
+
+        let start = Instant::now();
+        let outcome = read.read(fetches(&damaged, 5)).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert_eq!(outcome.data, data);
+        assert_eq!(outcome.used, vec![0, 1, 2, 3, 4]);
+        assert_eq!(outcome.corrected, vec![0]);
+        assert_eq!(outcome.cancelled, 1);
+    }
+}
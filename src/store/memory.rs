@@ -0,0 +1,65 @@
+use crate::store::share_store::{check_id, ShareStore};
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::Mutex;
+
+/// `MemoryStore` keeps shares in memory. It is meant for tests and for caching.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    shares: Mutex<BTreeMap<(String, usize), Vec<u8>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+
+    /// Number of stored shares, over all objects.
+    pub fn len(&self) -> usize {
+        self.shares.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl ShareStore for MemoryStore {
+    fn put(&self, id: &str, number: usize, data: &[u8]) -> io::Result<()> {
+        check_id(id)?;
+        let mut shares = self.shares.lock().unwrap();
+        shares.insert((id.to_string(), number), data.to_vec());
+        Ok(())
+    }
+
+    fn get(&self, id: &str, number: usize) -> io::Result<Option<Vec<u8>>> {
+        check_id(id)?;
+        let shares = self.shares.lock().unwrap();
+        Ok(shares.get(&(id.to_string(), number)).cloned())
+    }
+
+    fn list(&self, id: &str) -> io::Result<Vec<usize>> {
+        check_id(id)?;
+        let shares = self.shares.lock().unwrap();
+        Ok(shares
+            .range((id.to_string(), 0)..=(id.to_string(), usize::MAX))
+            .map(|((_, number), _)| *number)
+            .collect())
+    }
+
+    fn delete(&self, id: &str, number: usize) -> io::Result<()> {
+        check_id(id)?;
+        self.shares
+            .lock()
+            .unwrap()
+            .remove(&(id.to_string(), number));
+        Ok(())
+    }
+
+    fn objects(&self) -> io::Result<Vec<String>> {
+        let shares = self.shares.lock().unwrap();
+        let mut ids: Vec<String> = shares.keys().map(|(id, _)| id.clone()).collect();
+        ids.dedup();
+        Ok(ids)
+    }
+}
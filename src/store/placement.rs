@@ -0,0 +1,370 @@
+use crate::fec::fec::FEC;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+/// `Topology` is the set of locations shares can be placed on, each named by its path
+/// through the failure domains, such as `["rack2", "host7", "disk1"]` for the levels
+/// `["rack", "host", "disk"]`.
+///
+/// A domain at a level is identified by the path down to it, so `host7` of `rack2` and
+/// `host7` of `rack3` are different hosts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Topology {
+    levels: Vec<String>,
+    locations: Vec<Vec<String>>,
+}
+
+impl Topology {
+    /// `new()` creates an empty topology with the given levels, outermost first.
+    pub fn new(levels: &[&str]) -> Result<Topology, Box<dyn Error>> {
+        if levels.is_empty() {
+            return Err("a topology needs at least one level".into());
+        }
+        for (i, level) in levels.iter().enumerate() {
+            if levels[..i].contains(level) {
+                return Err(format!("level {:?} appears twice", level).into());
+            }
+        }
+        Ok(Topology {
+            levels: levels.iter().map(|l| l.to_string()).collect(),
+            locations: Vec::new(),
+        })
+    }
+
+    /// `add()` adds a location and returns its index. `path` names one domain per level.
+    pub fn add(&mut self, path: &[&str]) -> Result<usize, Box<dyn Error>> {
+        if path.len() != self.levels.len() {
+            return Err(format!(
+                "location {:?} has {} parts, the topology has {} levels",
+                path,
+                path.len(),
+                self.levels.len()
+            )
+            .into());
+        }
+        if self.locations.iter().any(|l| l == path) {
+            return Err(format!("location {:?} was already added", path).into());
+        }
+        self.locations
+            .push(path.iter().map(|p| p.to_string()).collect());
+        Ok(self.locations.len() - 1)
+    }
+
+    pub fn levels(&self) -> &[String] {
+        &self.levels
+    }
+
+    pub fn locations(&self) -> &[Vec<String>] {
+        &self.locations
+    }
+
+    /// `domain()` returns the path of the domain at `level` holding `location`.
+    pub fn domain(&self, location: usize, level: usize) -> &[String] {
+        &self.locations[location][..=level]
+    }
+
+    /// `domains()` returns the paths of the domains at `level`, in order of first
+    /// appearance.
+    pub fn domains(&self, level: usize) -> Vec<&[String]> {
+        let mut domains: Vec<&[String]> = Vec::new();
+        for location in 0..self.locations.len() {
+            let domain = self.domain(location, level);
+            if !domains.contains(&domain) {
+                domains.push(domain);
+            }
+        }
+        domains
+    }
+}
+
+/// `Placement` maps each share number to the index of the location that holds it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placement {
+    locations: Vec<usize>,
+}
+
+/// `LevelReport` is how a placement fares against failures at one level.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LevelReport {
+    pub level: String,
+    /// Number of domains at the level.
+    pub domains: usize,
+    /// Most shares held by a single domain.
+    pub max_shares: usize,
+    /// Largest number of domains that can fail at once, whichever they are, leaving at
+    /// least `k` shares.
+    pub tolerated_failures: usize,
+}
+
+/// `PlacementReport` is the result of `Placement::validate()`, one entry per level,
+/// outermost first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlacementReport {
+    pub k: usize,
+    pub n: usize,
+    pub levels: Vec<LevelReport>,
+}
+
+impl PlacementReport {
+    /// `survives_any_single_failure()` returns whether losing any one domain, at any
+    /// level, leaves at least `k` shares.
+    pub fn survives_any_single_failure(&self) -> bool {
+        self.levels.iter().all(|l| l.tolerated_failures >= 1)
+    }
+}
+
+impl Placement {
+    /// `new()` wraps an existing assignment, such as the `number % roots` of `FsStore`,
+    /// so it can be validated.
+    pub fn new(locations: Vec<usize>) -> Placement {
+        Placement { locations }
+    }
+
+    /// `spread()` places the n shares of `fec` on `topology` so that every domain at
+    /// every level holds as few shares as it can: at each domain the shares are split
+    /// as evenly as possible between its subdomains, the larger ones taking the
+    /// remainder. Share numbers alternate between domains, so shares `0..k` are spread
+    /// as well.
+    ///
+    /// It fails if the topology cannot keep a single failed domain at some level from
+    /// taking more than `n - k` shares.
+    pub fn spread(topology: &Topology, fec: &FEC) -> Result<Placement, Box<dyn Error>> {
+        if topology.locations.is_empty() {
+            return Err("the topology has no locations".into());
+        }
+        let all: Vec<usize> = (0..topology.locations.len()).collect();
+        let placement = Placement {
+            locations: spread(topology, &all, 0, fec.n),
+        };
+
+        let report = placement.validate(topology, fec)?;
+        if let Some(level) = report.levels.iter().find(|l| l.tolerated_failures == 0) {
+            return Err(format!(
+                "a single {} holds up to {} of the {} shares, more than the {} that can be lost",
+                level.level,
+                level.max_shares,
+                fec.n,
+                fec.n - fec.k
+            )
+            .into());
+        }
+        Ok(placement)
+    }
+
+    /// `location()` returns the index of the location holding share `number`.
+    pub fn location(&self, number: usize) -> usize {
+        self.locations[number]
+    }
+
+    /// `shares_at()` returns the numbers of the shares held by `location`.
+    pub fn shares_at(&self, location: usize) -> Vec<usize> {
+        (0..self.locations.len())
+            .filter(|&number| self.locations[number] == location)
+            .collect()
+    }
+
+    pub fn as_slice(&self) -> &[usize] {
+        &self.locations
+    }
+
+    /// `validate()` reports, for each level of `topology`, how many domains can fail at
+    /// once without losing more than the `n - k` shares of `fec`.
+    pub fn validate(
+        &self,
+        topology: &Topology,
+        fec: &FEC,
+    ) -> Result<PlacementReport, Box<dyn Error>> {
+        if self.locations.len() != fec.n {
+            return Err(format!(
+                "the placement has {} shares, the encoding has {}",
+                self.locations.len(),
+                fec.n
+            )
+            .into());
+        }
+        if let Some(&bad) = self
+            .locations
+            .iter()
+            .find(|&&l| l >= topology.locations.len())
+        {
+            return Err(format!("the placement uses unknown location {}", bad).into());
+        }
+
+        let levels = (0..topology.levels.len())
+            .map(|level| {
+                let mut counts: BTreeMap<&[String], usize> = topology
+                    .domains(level)
+                    .into_iter()
+                    .map(|d| (d, 0))
+                    .collect();
+                for &location in &self.locations {
+                    *counts.get_mut(topology.domain(location, level)).unwrap() += 1;
+                }
+                let mut counts: Vec<usize> = counts.into_values().collect();
+                counts.sort_unstable_by(|a, b| b.cmp(a));
+
+                // the worst f failures are the f domains holding the most shares
+                let mut lost = 0;
+                let tolerated_failures = counts
+                    .iter()
+                    .take_while(|&&c| {
+                        lost += c;
+                        lost <= fec.n - fec.k
+                    })
+                    .count();
+                LevelReport {
+                    level: topology.levels[level].clone(),
+                    domains: counts.len(),
+                    max_shares: counts[0],
+                    tolerated_failures,
+                }
+            })
+            .collect();
+        Ok(PlacementReport {
+            k: fec.k,
+            n: fec.n,
+            levels,
+        })
+    }
+}
+
+/// `spread()` places `count` shares on `candidates`, which share their path above
+/// `level`, and returns the location of each share.
+fn spread(topology: &Topology, candidates: &[usize], level: usize, count: usize) -> Vec<usize> {
+    if level == topology.levels.len() {
+        // a single location, as paths are unique
+        return vec![candidates[0]; count];
+    }
+
+    let mut children: Vec<(&str, Vec<usize>)> = Vec::new();
+    for &location in candidates {
+        let name = topology.locations[location][level].as_str();
+        match children.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, members)) => members.push(location),
+            None => children.push((name, vec![location])),
+        }
+    }
+
+    // even shares, the remainder going to the children with the most locations
+    let mut order: Vec<usize> = (0..children.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(children[i].1.len()));
+    let mut counts = vec![count / children.len(); children.len()];
+    for &i in order.iter().take(count % children.len()) {
+        counts[i] += 1;
+    }
+
+    let placed: Vec<Vec<usize>> = children
+        .iter()
+        .zip(counts)
+        .map(|((_, members), c)| spread(topology, members, level + 1, c))
+        .collect();
+    let mut interleaved = Vec::with_capacity(count);
+    for i in 0..placed.iter().map(Vec::len).max().unwrap_or(0) {
+        interleaved.extend(placed.iter().filter_map(|p| p.get(i)));
+    }
+    interleaved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn topology(racks: &[usize]) -> Topology {
+        let mut topology = Topology::new(&["rack", "host", "disk"]).unwrap();
+        for (r, &hosts) in racks.iter().enumerate() {
+            for h in 0..hosts {
+                for d in 0..2 {
+                    let path = [format!("r{}", r), format!("h{}", h), format!("d{}", d)];
+                    let path: Vec<&str> = path.iter().map(String::as_str).collect();
+                    topology.add(&path).unwrap();
+                }
+            }
+        }
+        topology
+    }
+
+    #[test]
+    fn test_spread_over_racks_hosts_and_disks() {
+        let topology = topology(&[2, 2, 2]);
+        let fec = FEC::new(6, 9).unwrap();
+        let placement = Placement::spread(&topology, &fec).unwrap();
+
+        // consecutive shares land in different racks
+        let racks: Vec<&str> = (0..3)
+            .map(|n| topology.locations()[placement.location(n)][0].as_str())
+            .collect();
+        assert_eq!(racks, vec!["r0", "r1", "r2"]);
+        assert!((0..12).all(|l| placement.shares_at(l).len() <= 1));
+
+        let report = placement.validate(&topology, &fec).unwrap();
+        let summary: Vec<(&str, usize, usize, usize)> = report
+            .levels
+            .iter()
+            .map(|l| {
+                (
+                    l.level.as_str(),
+                    l.domains,
+                    l.max_shares,
+                    l.tolerated_failures,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![("rack", 3, 3, 1), ("host", 6, 2, 1), ("disk", 12, 1, 3)]
+        );
+        assert!(report.survives_any_single_failure());
+
+        // the rack with more hosts takes the extra share
+        let uneven = topology_with_paths(&[
+            &["a", "h0"],
+            &["b", "h0"],
+            &["b", "h1"],
+            &["c", "h0"],
+            &["c", "h1"],
+        ]);
+        let fec = FEC::new(4, 7).unwrap();
+        let placement = Placement::spread(&uneven, &fec).unwrap();
+        assert_eq!(placement.shares_at(0).len(), 2);
+        assert_eq!(
+            placement.shares_at(1).len() + placement.shares_at(2).len(),
+            3
+        );
+        let report = placement.validate(&uneven, &fec).unwrap();
+        assert_eq!(report.levels[0].max_shares, 3);
+        assert_eq!(report.levels[1].tolerated_failures, 1);
+    }
+
+    fn topology_with_paths(paths: &[&[&str]]) -> Topology {
+        let mut topology = Topology::new(&["rack", "host"]).unwrap();
+        for path in paths {
+            topology.add(path).unwrap();
+        }
+        topology
+    }
+
+    #[test]
+    fn test_spread_rejects_too_few_domains() {
+        // two racks cannot hold six shares with only two to spare
+        let fec = FEC::new(4, 6).unwrap();
+        let e = Placement::spread(&topology(&[3, 3]), &fec).unwrap_err();
+        assert!(e.to_string().contains("single rack"), "{}", e);
+        assert!(Placement::spread(&topology(&[1, 1, 1]), &fec).is_ok());
+
+        // everything in one rack tolerates no failure above the disks
+        let topology = topology(&[2, 2, 2]);
+        let report = Placement::new(vec![0, 1, 2, 3, 0, 1])
+            .validate(&topology, &fec)
+            .unwrap();
+        let tolerated: Vec<usize> = report.levels.iter().map(|l| l.tolerated_failures).collect();
+        assert_eq!(tolerated, vec![0, 0, 1]);
+        assert!(!report.survives_any_single_failure());
+        assert!(Placement::new(vec![0; 5])
+            .validate(&topology, &fec)
+            .is_err());
+        assert!(Placement::new(vec![99; 6])
+            .validate(&topology, &fec)
+            .is_err());
+        assert!(Topology::new(&["rack", "rack"]).is_err());
+    }
+}
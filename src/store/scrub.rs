@@ -0,0 +1,517 @@
+use crate::fec::fec::Share;
+use crate::format::share_file::ShareFile;
+use crate::store::share_store::{decode_stripes, load_object, ShareStore};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// `ScrubOptions` controls a run of `scrub()`.
+#[derive(Debug, Clone, Default)]
+pub struct ScrubOptions {
+    /// Report damage without writing any share back.
+    pub dry_run: bool,
+    /// Upper bound on the share bytes read and written per second, unlimited if `None`.
+    pub bytes_per_sec: Option<u64>,
+    /// Scrub only the objects whose id sorts after this one, as saved by an earlier run.
+    pub start_after: Option<String>,
+    /// Stop after this many objects, leaving the rest to a later run. Must be at least 1.
+    pub max_objects: Option<usize>,
+}
+
+/// `ObjectStatus` is what `scrub()` found of one object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectStatus {
+    /// All n shares were present and consistent.
+    Healthy,
+    /// Damaged shares were regenerated and written back.
+    Repaired,
+    /// Shares are damaged but the run was a dry run.
+    Damaged,
+    /// Too few good shares are left to rebuild the object.
+    Unrecoverable,
+    /// The store failed while reading or writing the object.
+    Failed,
+}
+
+/// `ObjectReport` is the outcome of scrubbing one object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectReport {
+    pub id: String,
+    pub status: ObjectStatus,
+    /// Share numbers with nothing stored.
+    pub missing: Vec<usize>,
+    /// Share numbers whose share file was unreadable or failed its checksum.
+    pub invalid: Vec<usize>,
+    /// Share numbers that passed their checksum but failed the syndrome check.
+    pub corrected: Vec<usize>,
+    /// Share numbers written back.
+    pub rewritten: Vec<usize>,
+    pub error: Option<String>,
+}
+
+impl ObjectReport {
+    fn new(id: &str) -> ObjectReport {
+        ObjectReport {
+            id: id.to_string(),
+            status: ObjectStatus::Healthy,
+            missing: Vec::new(),
+            invalid: Vec::new(),
+            corrected: Vec::new(),
+            rewritten: Vec::new(),
+            error: None,
+        }
+    }
+}
+
+/// `ScrubSummary` is the outcome of a run of `scrub()`.
+#[derive(Debug, Clone, Default)]
+pub struct ScrubSummary {
+    pub dry_run: bool,
+    pub objects: usize,
+    pub healthy: usize,
+    pub repaired: usize,
+    pub damaged: usize,
+    pub unrecoverable: usize,
+    pub failed: usize,
+    pub shares_rewritten: usize,
+    /// Share bytes read and written.
+    pub bytes: u64,
+    pub elapsed: Duration,
+    /// Reports of the objects that were not healthy.
+    pub reports: Vec<ObjectReport>,
+    /// Id of the last object scrubbed if the run stopped before the end, to pass as
+    /// `start_after` to the next run; `None` once every object has been scrubbed.
+    pub next_cursor: Option<String>,
+}
+
+impl fmt::Display for ScrubSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "scrubbed {} objects ({} bytes) in {:.1}s: {} healthy, {} repaired, {} damaged, {} unrecoverable, {} failed; {} shares rewritten",
+            self.objects,
+            self.bytes,
+            self.elapsed.as_secs_f64(),
+            self.healthy,
+            self.repaired,
+            self.damaged,
+            self.unrecoverable,
+            self.failed,
+            self.shares_rewritten
+        )?;
+        if self.dry_run {
+            write!(f, " (dry run)")?;
+        }
+        if let Some(cursor) = &self.next_cursor {
+            write!(f, "; stopped after {:?}", cursor)?;
+        }
+        Ok(())
+    }
+}
+
+/// `RateLimiter` sleeps as needed to keep a byte count under a rate.
+struct RateLimiter {
+    bytes_per_sec: Option<u64>,
+    start: Instant,
+    bytes: u64,
+}
+
+impl RateLimiter {
+    fn new(bytes_per_sec: Option<u64>) -> RateLimiter {
+        RateLimiter {
+            bytes_per_sec,
+            start: Instant::now(),
+            bytes: 0,
+        }
+    }
+
+    fn consume(&mut self, bytes: u64) {
+        self.bytes += bytes;
+        if let Some(rate) = self.bytes_per_sec.filter(|&r| r > 0) {
+            let due = Duration::from_secs_f64(self.bytes as f64 / rate as f64);
+            if let Some(wait) = due.checked_sub(self.start.elapsed()) {
+                thread::sleep(wait);
+            }
+        }
+    }
+}
+
+/// `scrub()` checks the objects of `store` in id order and rewrites their missing,
+/// unreadable and corrupted shares.
+///
+/// The shares of an object that pass their checksum go through the syndrome check of
+/// `FEC::correct`, stripe by stripe, which also catches shares that were damaged before
+/// their checksum was computed. The object is then rebuilt and the damaged shares
+/// regenerated from it.
+/// Failures are recorded per object and do not stop the run; only failing to list the
+/// objects, or a `max_objects` of 0, is an error.
+pub fn scrub<S: ShareStore + ?Sized>(
+    store: &S,
+    options: &ScrubOptions,
+) -> io::Result<ScrubSummary> {
+    if options.max_objects == Some(0) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "max_objects must be at least 1",
+        ));
+    }
+    let start = Instant::now();
+    let mut limiter = RateLimiter::new(options.bytes_per_sec);
+    let mut summary = ScrubSummary {
+        dry_run: options.dry_run,
+        ..ScrubSummary::default()
+    };
+
+    let ids = store.objects()?;
+    let ids: Vec<&String> = ids
+        .iter()
+        .filter(|id| {
+            options
+                .start_after
+                .as_ref()
+                .map_or(true, |after| *id > after)
+        })
+        .collect();
+    for (i, id) in ids.iter().enumerate() {
+        if options.max_objects.is_some_and(|max| i >= max) {
+            summary.next_cursor = Some(ids[i - 1].to_string());
+            break;
+        }
+
+        let report = scrub_object(store, id, options.dry_run, &mut limiter);
+        summary.objects += 1;
+        summary.shares_rewritten += report.rewritten.len();
+        match report.status {
+            ObjectStatus::Healthy => summary.healthy += 1,
+            ObjectStatus::Repaired => summary.repaired += 1,
+            ObjectStatus::Damaged => summary.damaged += 1,
+            ObjectStatus::Unrecoverable => summary.unrecoverable += 1,
+            ObjectStatus::Failed => summary.failed += 1,
+        }
+        if report.status != ObjectStatus::Healthy {
+            summary.reports.push(report);
+        }
+    }
+    summary.bytes = limiter.bytes;
+    summary.elapsed = start.elapsed();
+    Ok(summary)
+}
+
+fn scrub_object<S: ShareStore + ?Sized>(
+    store: &S,
+    id: &str,
+    dry_run: bool,
+    limiter: &mut RateLimiter,
+) -> ObjectReport {
+    let mut report = ObjectReport::new(id);
+    let fail = |mut report: ObjectReport, status, error: String| {
+        report.status = status;
+        report.error = Some(error);
+        report
+    };
+
+    let object = match load_object(store, id) {
+        Ok(object) => object,
+        Err(e) => match e.downcast::<io::Error>() {
+            Ok(e) => return fail(report, ObjectStatus::Failed, e.to_string()),
+            Err(e) => return fail(report, ObjectStatus::Unrecoverable, e.to_string()),
+        },
+    };
+    let header = object.header;
+    let fec = match header.fec() {
+        Ok(fec) => fec,
+        Err(e) => return fail(report, ObjectStatus::Unrecoverable, e.to_string()),
+    };
+    let read: u64 = object.shares.iter().map(|s| s.data.len() as u64).sum();
+    limiter.consume(read);
+
+    let present: BTreeSet<usize> = object
+        .shares
+        .iter()
+        .map(|s| s.number)
+        .chain(object.invalid.iter().copied())
+        .collect();
+    report.missing = (0..fec.n).filter(|n| !present.contains(n)).collect();
+    report.invalid = object.invalid;
+    if object.shares.len() < fec.k {
+        let error = format!(
+            "found {} good shares, at least {} are needed",
+            object.shares.len(),
+            fec.k
+        );
+        return fail(report, ObjectStatus::Unrecoverable, error);
+    }
+
+    // the data pieces keep their padding, so encoding them again yields the same shares
+    let mut stripes = Vec::new();
+    let decoded = decode_stripes(&header, &object.shares, |_, pieces| {
+        stripes.push(pieces.to_vec())
+    });
+    report.corrected = match decoded {
+        Ok(corrected) => corrected,
+        Err(e) => return fail(report, ObjectStatus::Unrecoverable, e.to_string()),
+    };
+
+    let damaged: BTreeSet<usize> = report
+        .missing
+        .iter()
+        .chain(&report.invalid)
+        .chain(&report.corrected)
+        .copied()
+        .collect();
+    if damaged.is_empty() {
+        return report;
+    }
+    if dry_run {
+        report.status = ObjectStatus::Damaged;
+        return report;
+    }
+
+    let mut regenerated: Vec<Share> = damaged
+        .iter()
+        .map(|&number| Share {
+            number,
+            data: Vec::new(),
+        })
+        .collect();
+    for pieces in &stripes {
+        let encoded = fec.encode(pieces, |s| {
+            if let Some(share) = regenerated.iter_mut().find(|r| r.number == s.number) {
+                share.data.extend_from_slice(&s.data);
+            }
+        });
+        if let Err(e) = encoded {
+            return fail(report, ObjectStatus::Unrecoverable, e.to_string());
+        }
+    }
+    for share in regenerated {
+        let file = ShareFile::new(&fec, header.stripe_size, header.original_len, share);
+        let bytes = file.to_bytes();
+        if let Err(e) = store.put(id, file.header.number, &bytes) {
+            return fail(report, ObjectStatus::Failed, e.to_string());
+        }
+        limiter.consume(bytes.len() as u64);
+        report.rewritten.push(file.header.number);
+    }
+    report.status = ObjectStatus::Repaired;
+    report
+}
+
+/// `load_cursor()` reads a cursor saved by `save_cursor()`, or `None` if there is none.
+pub fn load_cursor(path: &Path) -> io::Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(cursor) => Ok(Some(cursor.trim_end().to_string()).filter(|c| !c.is_empty())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// `save_cursor()` saves the `next_cursor` of a run to `path`, replacing it atomically,
+/// or removes the file once the run has reached the end.
+pub fn save_cursor(path: &Path, cursor: Option<&str>) -> io::Result<()> {
+    let Some(cursor) = cursor else {
+        return match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    };
+    let partial = path.with_extension("partial");
+    let mut file = fs::File::create(&partial)?;
+    writeln!(file, "{}", cursor)?;
+    file.sync_all()?;
+    fs::rename(&partial, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fec::fec::FEC;
+    use crate::fec::striped::StripedCodec;
+    use crate::format::share_file::ShareWriter;
+    use crate::store::fs::FsStore;
+    use crate::store::memory::MemoryStore;
+    use crate::store::share_store::{get_object, put_object};
+    use crate::stream::encoder::StreamEncoder;
+    use std::io::Cursor;
+    use std::path::PathBuf;
+
+    /// Id, missing, invalid and corrected shares of an object report.
+    type Damage<'a> = (&'a str, &'a [usize], &'a [usize], &'a [usize]);
+
+    /// `damage_under_checksum()` flips the first payload byte of a share and fixes up
+    /// its checksum, as if the share had been damaged before it was written.
+    fn damage_under_checksum(store: &MemoryStore, id: &str, number: usize) {
+        let bytes = store.get(id, number).unwrap().unwrap();
+        let mut share = ShareFile::from_bytes(&bytes).unwrap().into_share();
+        share.data[0] ^= 0x55;
+        let fec = FEC::new(3, 6).unwrap();
+        let original_len = ShareFile::from_bytes(&bytes).unwrap().header.original_len;
+        let file = ShareFile::new(&fec, 0, original_len, share);
+        store.put(id, number, &file.to_bytes()).unwrap();
+    }
+
+    #[test]
+    fn test_scrub_repairs_damaged_objects() {
+        let store = MemoryStore::new();
+        let fec = FEC::new(3, 6).unwrap();
+        let data: Vec<u8> = (0..900u32).map(|i| (i % 247) as u8).collect();
+        for id in ["a", "b", "c", "d"] {
+            put_object(&store, &fec, id, &data).unwrap();
+        }
+        store.delete("a", 5).unwrap();
+        store.put("b", 1, b"garbage").unwrap();
+        let pristine = store.get("c", 2).unwrap();
+        damage_under_checksum(&store, "c", 2);
+        for number in 0..4 {
+            store.delete("d", number).unwrap();
+        }
+
+        let dry = scrub(
+            &store,
+            &ScrubOptions {
+                dry_run: true,
+                ..ScrubOptions::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            (dry.damaged, dry.unrecoverable, dry.shares_rewritten),
+            (3, 1, 0)
+        );
+        assert_eq!(store.list("a").unwrap().len(), 5);
+
+        let summary = scrub(&store, &ScrubOptions::default()).unwrap();
+        assert_eq!(
+            (summary.objects, summary.repaired, summary.unrecoverable),
+            (4, 3, 1)
+        );
+        let reports: Vec<Damage> = summary
+            .reports
+            .iter()
+            .map(|r| {
+                (
+                    r.id.as_str(),
+                    &r.missing[..],
+                    &r.invalid[..],
+                    &r.corrected[..],
+                )
+            })
+            .collect();
+        assert_eq!(
+            reports,
+            vec![
+                ("a", &[5][..], &[][..], &[][..]),
+                ("b", &[][..], &[1][..], &[][..]),
+                ("c", &[][..], &[][..], &[2][..]),
+                ("d", &[0, 1, 2, 3][..], &[][..], &[][..]),
+            ]
+        );
+        assert_eq!(summary.reports[3].status, ObjectStatus::Unrecoverable);
+        assert_eq!(summary.shares_rewritten, 3);
+        assert_eq!(store.get("c", 2).unwrap(), pristine);
+        for id in ["a", "b", "c"] {
+            assert_eq!(get_object(&store, id).unwrap(), data);
+        }
+
+        let again = scrub(&store, &ScrubOptions::default()).unwrap();
+        assert_eq!((again.healthy, again.unrecoverable), (3, 1));
+        assert!(again.to_string().starts_with("scrubbed 4 objects"));
+    }
+
+    #[test]
+    fn test_scrub_repairs_striped_objects() {
+        let fec = FEC::new(3, 6).unwrap();
+        let codec = StripedCodec::new(FEC::new(3, 6).unwrap(), 300).unwrap();
+        let data: Vec<u8> = (0..1000u32).map(|i| (i % 241) as u8).collect();
+        let sinks = (0..6)
+            .map(|n| ShareWriter::new(Cursor::new(Vec::new()), &fec, n, 300).unwrap())
+            .collect();
+        let mut encoder = StreamEncoder::new(codec, sinks).unwrap();
+        encoder.write_all(&data).unwrap();
+        let (sinks, len) = encoder.finish().unwrap();
+        let store = MemoryStore::new();
+        let mut pristine = Vec::new();
+        for (number, sink) in sinks.into_iter().enumerate() {
+            let bytes = sink.finish(len).unwrap().into_inner();
+            store.put("s", number, &bytes).unwrap();
+            pristine.push(bytes);
+        }
+
+        // share 1 damaged in its second stripe before its checksum was computed
+        let mut share = ShareFile::from_bytes(&pristine[1]).unwrap().into_share();
+        share.data[150] ^= 0xFF;
+        store
+            .put("s", 1, &ShareFile::new(&fec, 300, len, share).to_bytes())
+            .unwrap();
+        store.delete("s", 4).unwrap();
+
+        let summary = scrub(&store, &ScrubOptions::default()).unwrap();
+        assert_eq!(summary.repaired, 1);
+        let report = &summary.reports[0];
+        assert_eq!(
+            (&report.missing[..], &report.corrected[..]),
+            (&[4][..], &[1][..])
+        );
+        assert_eq!(report.rewritten, vec![1, 4]);
+        for (number, bytes) in pristine.iter().enumerate() {
+            assert_eq!(store.get("s", number).unwrap().as_ref(), Some(bytes));
+        }
+    }
+
+    #[test]
+    fn test_scrub_resumes_from_cursor_and_limits_rate() {
+        let base = std::env::temp_dir().join(format!("rs-scrub-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        let roots: Vec<PathBuf> = (0..2).map(|i| base.join(format!("disk{}", i))).collect();
+        let store = FsStore::new(roots.clone()).unwrap();
+        let fec = FEC::new(2, 4).unwrap();
+        for id in ["o1", "o2", "o3"] {
+            put_object(&store, &fec, id, &[7u8; 1000]).unwrap();
+        }
+        fs::remove_dir_all(&roots[1]).unwrap();
+
+        let cursor_path = base.join("scrub.cursor");
+        let options = |start_after| ScrubOptions {
+            max_objects: Some(2),
+            start_after,
+            ..ScrubOptions::default()
+        };
+        let first = scrub(&store, &options(load_cursor(&cursor_path).unwrap())).unwrap();
+        assert_eq!(first.objects, 2);
+        assert_eq!(first.next_cursor.as_deref(), Some("o2"));
+        save_cursor(&cursor_path, first.next_cursor.as_deref()).unwrap();
+
+        let second = scrub(&store, &options(load_cursor(&cursor_path).unwrap())).unwrap();
+        assert_eq!((second.objects, second.repaired), (1, 1));
+        assert_eq!(second.reports[0].rewritten, vec![1, 3]);
+        assert_eq!(second.next_cursor, None);
+        let none = ScrubOptions {
+            max_objects: Some(0),
+            ..ScrubOptions::default()
+        };
+        let e = scrub(&store, &none).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        save_cursor(&cursor_path, None).unwrap();
+        assert_eq!(load_cursor(&cursor_path).unwrap(), None);
+        assert!(roots[1].join("o3/003.share").is_file());
+
+        // three objects of four 500-byte shares at 10 kB/s take at least 0.6s
+        let limited = scrub(
+            &store,
+            &ScrubOptions {
+                bytes_per_sec: Some(10_000),
+                ..ScrubOptions::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(limited.healthy, 3);
+        assert_eq!(limited.bytes, 6000);
+        assert!(limited.elapsed >= Duration::from_millis(600));
+
+        let _ = fs::remove_dir_all(&base);
+    }
+}
@@ -0,0 +1,288 @@
+use crate::fec::fec::{Share, FEC};
+use crate::fec::striped::Stripe;
+use crate::format::share_file::{ShareFile, ShareHeader};
+use std::error::Error;
+use std::io;
+
+/// `ShareStore` persists shares, keyed by object id and share number.
+///
+/// Shares are opaque bytes to a store; `put_object()` and `get_object()` store them as
+/// self-describing share files, so an object can be decoded from its shares alone.
+/// Methods take `&self` so that a store can be shared between threads.
+pub trait ShareStore {
+    /// `put()` stores share `number` of object `id`, replacing any previous one.
+    fn put(&self, id: &str, number: usize, data: &[u8]) -> io::Result<()>;
+
+    /// `get()` returns share `number` of object `id`, or `None` if it is not stored.
+    fn get(&self, id: &str, number: usize) -> io::Result<Option<Vec<u8>>>;
+
+    /// `list()` returns the numbers of the stored shares of object `id`, in ascending
+    /// order.
+    fn list(&self, id: &str) -> io::Result<Vec<usize>>;
+
+    /// `delete()` removes share `number` of object `id`. Removing a share that is not
+    /// stored is not an error.
+    fn delete(&self, id: &str, number: usize) -> io::Result<()>;
+
+    /// `objects()` returns the ids of all objects with at least one stored share, in
+    /// ascending order.
+    fn objects(&self) -> io::Result<Vec<String>>;
+}
+
+/// `check_id()` rejects object ids that are empty, too long or contain anything other
+/// than ASCII letters, digits, `-`, `_` and `.`, or consist only of dots. Backends use
+/// ids as file names and URL path segments.
+pub fn check_id(id: &str) -> io::Result<()> {
+    let valid = !id.is_empty()
+        && id.len() <= 255
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-_.".contains(&b))
+        && !id.bytes().all(|b| b == b'.');
+    if valid {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid object id {:?}", id),
+        ))
+    }
+}
+
+/// `put_object()` encodes `data` with `fec` and stores every share as a share file
+/// under `id`. It returns once all n shares are stored.
+pub fn put_object<S: ShareStore + ?Sized>(
+    store: &S,
+    fec: &FEC,
+    id: &str,
+    data: &[u8],
+) -> Result<(), Box<dyn Error>> {
+    check_id(id)?;
+    let mut files = Vec::with_capacity(fec.n);
+    fec.encode(data, |s| {
+        files.push(ShareFile::new(fec, 0, data.len() as u64, s))
+    })?;
+    for file in files {
+        store.put(id, file.header.number, &file.to_bytes())?;
+    }
+    Ok(())
+}
+
+/// `ObjectShares` is what `load_object()` found of an object.
+#[derive(Debug)]
+pub struct ObjectShares {
+    /// Header shared by the usable shares.
+    pub header: ShareHeader,
+    /// Usable shares, ordered by number.
+    pub shares: Vec<Share>,
+    /// Numbers of the stored shares that could not be parsed, failed their checksum or
+    /// belong to a different encoding.
+    pub invalid: Vec<usize>,
+}
+
+/// `load_object()` reads every stored share of `id`. Shares that cannot be used are
+/// listed in `invalid` rather than failing the read; if the shares disagree about the
+/// encoding, the most common one wins.
+pub fn load_object<S: ShareStore + ?Sized>(
+    store: &S,
+    id: &str,
+) -> Result<ObjectShares, Box<dyn Error>> {
+    check_id(id)?;
+    let mut files = Vec::new();
+    let mut invalid = Vec::new();
+    for number in store.list(id)? {
+        // a share deleted since it was listed is simply missing
+        if let Some(bytes) = store.get(id, number)? {
+            match ShareFile::from_bytes(&bytes) {
+                Ok(file) if file.header.number == number => files.push(file),
+                _ => invalid.push(number),
+            }
+        }
+    }
+
+    let header = files
+        .iter()
+        .max_by_key(|f| {
+            files
+                .iter()
+                .filter(|o| o.header.is_compatible(&f.header))
+                .count()
+        })
+        .map(|f| f.header)
+        .ok_or_else(|| format!("object {:?} has no readable shares", id))?;
+    let mut shares = Vec::with_capacity(files.len());
+    for file in files {
+        if file.header.is_compatible(&header) {
+            shares.push(file.into_share());
+        } else {
+            invalid.push(file.header.number);
+        }
+    }
+    invalid.sort_unstable();
+    Ok(ObjectShares {
+        header,
+        shares,
+        invalid,
+    })
+}
+
+/// `get_object()` reads the shares of `id` and decodes the object, correcting errors
+/// where the number of shares allows. Missing and unreadable shares are erasures.
+pub fn get_object<S: ShareStore + ?Sized>(store: &S, id: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let object = load_object(store, id)?;
+    let fec = object.header.fec()?;
+    if object.shares.len() < fec.k {
+        return Err(format!(
+            "object {:?}: found {} usable shares, at least {} are needed",
+            id,
+            object.shares.len(),
+            fec.k
+        )
+        .into());
+    }
+    let mut data = Vec::with_capacity(object.header.original_len as usize);
+    decode_stripes(&object.header, &object.shares, |stripe, pieces| {
+        data.extend_from_slice(&pieces[..stripe.len])
+    })?;
+    Ok(data)
+}
+
+/// `decode_stripes()` corrects and rebuilds, stripe by stripe, the input encoded into
+/// `shares`, the payloads of share files described by `header`. `output` is called with
+/// every stripe and its k data pieces, padding included. It returns the numbers of the
+/// shares in which errors were corrected.
+pub fn decode_stripes<F>(
+    header: &ShareHeader,
+    shares: &[Share],
+    mut output: F,
+) -> Result<Vec<usize>, Box<dyn Error>>
+where
+    F: FnMut(&Stripe, &[u8]),
+{
+    let fec = header.fec()?;
+    let stripes = header.stripes()?;
+    let share_len: usize = stripes.iter().map(|s| s.share_len).sum();
+    if let Some(share) = shares.iter().find(|s| s.data.len() != share_len) {
+        return Err(format!(
+            "share {} holds {} bytes, expected {}",
+            share.number,
+            share.data.len(),
+            share_len
+        )
+        .into());
+    }
+
+    let mut corrected = Vec::new();
+    let mut pieces = Vec::new();
+    let mut offset = 0;
+    for stripe in &stripes {
+        let len = stripe.share_len;
+        let chunk = |s: &Share| s.data[offset..offset + len].to_vec();
+        let mut chunks: Vec<Share> = shares
+            .iter()
+            .map(|s| Share {
+                number: s.number,
+                data: chunk(s),
+            })
+            .collect();
+        // correct() once, then rebuild() from the corrected chunks: decode() would
+        // search for errors a second time
+        fec.correct(&mut chunks)?;
+        for fixed in &chunks {
+            let changed = shares
+                .iter()
+                .find(|s| s.number == fixed.number)
+                .is_some_and(|s| s.data[offset..offset + len] != fixed.data[..]);
+            if changed && !corrected.contains(&fixed.number) {
+                corrected.push(fixed.number);
+            }
+        }
+        offset += len;
+
+        pieces.clear();
+        pieces.resize(fec.k * len, 0);
+        fec.rebuild(chunks, |s: Share| {
+            pieces[s.number * len..(s.number + 1) * len].copy_from_slice(&s.data);
+        })?;
+        output(stripe, &pieces);
+    }
+    corrected.sort_unstable();
+    Ok(corrected)
+}
+
+/// `delete_object()` removes every stored share of `id`.
+pub fn delete_object<S: ShareStore + ?Sized>(store: &S, id: &str) -> io::Result<()> {
+    check_id(id)?;
+    for number in store.list(id)? {
+        store.delete(id, number)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fec::striped::StripedCodec;
+    use crate::format::share_file::ShareWriter;
+    use crate::store::memory::MemoryStore;
+    use crate::stream::encoder::StreamEncoder;
+    use std::io::{Cursor, Write};
+
+    #[test]
+    fn test_put_and_get_object() {
+        let store = MemoryStore::new();
+        let fec = FEC::new(3, 6).unwrap();
+        let data = b"an object stored as six shares".to_vec();
+        put_object(&store, &fec, "obj-1", &data).unwrap();
+        assert_eq!(store.list("obj-1").unwrap(), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(store.objects().unwrap(), vec!["obj-1".to_string()]);
+
+        // lose one share, garble another and corrupt the payload of a third
+        store.delete("obj-1", 4).unwrap();
+        store.put("obj-1", 0, b"garbage").unwrap();
+        let mut bytes = store.get("obj-1", 2).unwrap().unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xFF;
+        store.put("obj-1", 2, &bytes).unwrap();
+
+        assert_eq!(get_object(&store, "obj-1").unwrap(), data);
+        let object = load_object(&store, "obj-1").unwrap();
+        assert_eq!(object.invalid, vec![0, 2]);
+
+        store.delete("obj-1", 1).unwrap();
+        assert!(get_object(&store, "obj-1").is_err());
+        delete_object(&store, "obj-1").unwrap();
+        assert!(store.objects().unwrap().is_empty());
+        assert!(get_object(&store, "obj-1").is_err());
+
+        // shares written stripe by stripe, one of them damaged in its second stripe
+        let codec = StripedCodec::new(FEC::new(3, 6).unwrap(), 300).unwrap();
+        let data: Vec<u8> = (0..1000u32).map(|i| (i % 241) as u8).collect();
+        let sinks = (0..6)
+            .map(|n| ShareWriter::new(Cursor::new(Vec::new()), &fec, n, 300).unwrap())
+            .collect();
+        let mut encoder = StreamEncoder::new(codec, sinks).unwrap();
+        encoder.write_all(&data).unwrap();
+        let (sinks, len) = encoder.finish().unwrap();
+        for (number, sink) in sinks.into_iter().enumerate() {
+            let bytes = sink.finish(len).unwrap().into_inner();
+            store.put("obj-2", number, &bytes).unwrap();
+        }
+        let bytes = store.get("obj-2", 1).unwrap().unwrap();
+        let mut share = ShareFile::from_bytes(&bytes).unwrap().into_share();
+        share.data[150] ^= 0xFF;
+        let file = ShareFile::new(&fec, 300, len, share);
+        store.put("obj-2", 1, &file.to_bytes()).unwrap();
+        assert_eq!(get_object(&store, "obj-2").unwrap(), data);
+    }
+
+    #[test]
+    fn test_check_id() {
+        for id in ["a", "backup-2024.tar", "A_b.c"] {
+            assert!(check_id(id).is_ok(), "{}", id);
+        }
+        for id in ["", ".", "..", "a/b", "a b", "ü"] {
+            assert!(check_id(id).is_err(), "{}", id);
+        }
+    }
+}
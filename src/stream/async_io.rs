@@ -0,0 +1,690 @@
+use crate::fec::fec::Share;
+use crate::fec::striped::{Stripe, StripedCodec};
+use crate::fec::workspace::Encoder;
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinSet;
+use tokio::time::Sleep;
+
+/// Default number of stripes a sink or source may run ahead of the slowest one.
+pub const DEFAULT_LOOKAHEAD: usize = 4;
+
+/// `AsyncStreamEncoder` is the `AsyncWrite` counterpart of `StreamEncoder`.
+///
+/// Bytes are accumulated into stripes and every complete stripe is encoded into one
+/// chunk per sink. Each sink drains its own queue of chunks, so a slow sink does not
+/// hold back the others until it is `lookahead` stripes behind; only then does
+/// `poll_write` wait. `poll_flush` waits for all queued chunks to be written, and
+/// `poll_shutdown` also encodes the final partial stripe and shuts the sinks down.
+/// After shutdown, `into_inner()` returns the sinks and the true length of the stream.
+#[derive(Debug)]
+pub struct AsyncStreamEncoder<W> {
+    codec: StripedCodec,
+    encoder: Encoder,
+    sinks: Vec<QueuedSink<W>>,
+    lookahead: usize,
+    buf: Vec<u8>,
+    len: u64,
+    finished: bool,
+    shut_down: usize,
+}
+
+#[derive(Debug)]
+struct QueuedSink<W> {
+    sink: W,
+    queue: VecDeque<Vec<u8>>,
+    offset: usize,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncStreamEncoder<W> {
+    /// `new()` creates an encoder writing one share stream to each of the n sinks.
+    pub fn new(codec: StripedCodec, sinks: Vec<W>) -> io::Result<AsyncStreamEncoder<W>> {
+        if sinks.len() != codec.fec().n {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("expected {} sinks, got {}", codec.fec().n, sinks.len()),
+            ));
+        }
+        let encoder = Encoder::new(codec.fec(), codec.stripe_size() / codec.fec().k);
+        let buf = Vec::with_capacity(codec.stripe_size());
+        let sinks = sinks
+            .into_iter()
+            .map(|sink| QueuedSink {
+                sink,
+                queue: VecDeque::new(),
+                offset: 0,
+            })
+            .collect();
+        Ok(AsyncStreamEncoder {
+            codec,
+            encoder,
+            sinks,
+            lookahead: DEFAULT_LOOKAHEAD,
+            buf,
+            len: 0,
+            finished: false,
+            shut_down: 0,
+        })
+    }
+
+    /// `with_lookahead()` sets how many encoded stripes may be queued for a sink
+    /// before writes wait for it.
+    pub fn with_lookahead(mut self, lookahead: usize) -> AsyncStreamEncoder<W> {
+        self.lookahead = lookahead.max(1);
+        self
+    }
+
+    /// Number of bytes written to the stream so far.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn codec(&self) -> &StripedCodec {
+        &self.codec
+    }
+
+    /// `into_inner()` returns the sinks and the true length of the stream. Chunks
+    /// still queued are lost, so the encoder should be shut down first.
+    pub fn into_inner(self) -> (Vec<W>, u64) {
+        (self.sinks.into_iter().map(|s| s.sink).collect(), self.len)
+    }
+
+    fn encode_stripe(&mut self) -> io::Result<()> {
+        let sinks = &mut self.sinks;
+        self.encoder
+            .encode(self.codec.fec(), &self.buf, |number, data| {
+                sinks[number].queue.push_back(data.to_vec())
+            })
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        self.buf.clear();
+        Ok(())
+    }
+
+    fn encode_last_stripe(&mut self) -> io::Result<()> {
+        if !self.buf.is_empty() {
+            let stripe = Stripe {
+                index: (self.len / self.codec.stripe_size() as u64) as usize,
+                offset: 0,
+                len: self.buf.len(),
+                share_len: self.buf.len().div_ceil(self.codec.fec().k),
+            };
+            let sinks = &mut self.sinks;
+            self.codec
+                .encode_stripe(&stripe, &self.buf, |s| {
+                    sinks[s.number].queue.push_back(s.data)
+                })
+                .map_err(|e| io::Error::other(e.to_string()))?;
+            self.buf.clear();
+        }
+        self.finished = true;
+        Ok(())
+    }
+
+    /// `poll_sinks()` writes queued chunks to every sink until it would block, and is
+    /// ready once no sink has more than `max_queued` chunks left.
+    fn poll_sinks(&mut self, cx: &mut Context<'_>, max_queued: usize) -> Poll<io::Result<()>> {
+        let mut ready = true;
+        for s in self.sinks.iter_mut() {
+            while let Some(chunk) = s.queue.front() {
+                match Pin::new(&mut s.sink).poll_write(cx, &chunk[s.offset..]) {
+                    Poll::Ready(Ok(0)) => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                    Poll::Ready(Ok(written)) => {
+                        s.offset += written;
+                        if s.offset == chunk.len() {
+                            s.queue.pop_front();
+                            s.offset = 0;
+                        }
+                    }
+                    Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                    Poll::Pending => break,
+                }
+            }
+            ready &= s.queue.len() <= max_queued;
+        }
+        if ready {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for AsyncStreamEncoder<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.finished {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "stream encoder was shut down",
+            )));
+        }
+        if data.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        ready!(this.poll_sinks(cx, this.lookahead - 1))?;
+
+        let take = usize::min(this.codec.stripe_size() - this.buf.len(), data.len());
+        this.buf.extend_from_slice(&data[..take]);
+        this.len += take as u64;
+        if this.buf.len() == this.codec.stripe_size() {
+            this.encode_stripe()?;
+        }
+        Poll::Ready(Ok(take))
+    }
+
+    /// Writes all encoded stripes and flushes the sinks. A partial stripe stays
+    /// buffered until it is complete or the encoder is shut down.
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_sinks(cx, 0))?;
+        for s in this.sinks.iter_mut() {
+            ready!(Pin::new(&mut s.sink).poll_flush(cx))?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.finished {
+            this.encode_last_stripe()?;
+        }
+        ready!(this.poll_sinks(cx, 0))?;
+        while this.shut_down < this.sinks.len() {
+            ready!(Pin::new(&mut this.sinks[this.shut_down].sink).poll_shutdown(cx))?;
+            this.shut_down += 1;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// `AsyncStreamDecoder` is the `AsyncRead` counterpart of `StreamDecoder`.
+///
+/// On the first read, every source gets its own task that reads the source stripe by
+/// stripe and hands the chunks over, running at most `lookahead` stripes ahead of the
+/// decoder. A stripe is rebuilt as soon as chunks from `k + extra_shares` sources have
+/// arrived, or from at least k of them once every live source has answered, so slow
+/// or stalled sources do not hold up the stream. With the default of no extra shares
+/// the first k chunks win and corrupted data goes undetected; extra shares give
+/// `FEC::correct` something to correct with.
+///
+/// A source that fails or ends early is dropped as an erasure. All state lives in the
+/// decoder, so a cancelled read loses nothing, and dropping the decoder aborts the
+/// reading tasks.
+#[derive(Debug)]
+pub struct AsyncStreamDecoder<R> {
+    codec: StripedCodec,
+    stripes: Vec<Stripe>,
+    lookahead: usize,
+    extra_shares: usize,
+    extra_wait: Duration,
+    /// Deadline for the extra chunks of the current stripe, set once it has k.
+    extra_deadline: Option<Pin<Box<Sleep>>>,
+    pending_sources: Option<Vec<Option<R>>>,
+    state: Option<Readers>,
+    /// Per share number, whether the source may still deliver chunks.
+    alive: Vec<bool>,
+    /// Per share number, the index of the next stripe expected from the source.
+    next_chunk: Vec<usize>,
+    chunks: BTreeMap<usize, Vec<(Share, OwnedSemaphorePermit)>>,
+    next_stripe: usize,
+    dropped: Vec<(usize, io::Error)>,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+#[derive(Debug)]
+struct Readers {
+    rx: mpsc::Receiver<Message>,
+    _tasks: JoinSet<()>,
+}
+
+#[derive(Debug)]
+enum Message {
+    Chunk {
+        number: usize,
+        stripe: usize,
+        data: Vec<u8>,
+        permit: OwnedSemaphorePermit,
+    },
+    Failed {
+        number: usize,
+        error: io::Error,
+    },
+}
+
+impl<R> AsyncStreamDecoder<R>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    /// `new()` creates a decoder for a stream of `len` bytes encoded with `codec`.
+    /// There must be one entry in `sources` per share number.
+    pub fn new(
+        codec: StripedCodec,
+        sources: Vec<Option<R>>,
+        len: u64,
+    ) -> io::Result<AsyncStreamDecoder<R>> {
+        let n = codec.fec().n;
+        if sources.len() != n {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("expected {} sources, got {}", n, sources.len()),
+            ));
+        }
+        let stripes = codec.layout(len as usize).stripes;
+        let alive = sources.iter().map(Option::is_some).collect();
+        Ok(AsyncStreamDecoder {
+            codec,
+            stripes,
+            lookahead: DEFAULT_LOOKAHEAD,
+            extra_shares: 0,
+            extra_wait: Duration::ZERO,
+            extra_deadline: None,
+            pending_sources: Some(sources),
+            state: None,
+            alive,
+            next_chunk: vec![0; n],
+            chunks: BTreeMap::new(),
+            next_stripe: 0,
+            dropped: Vec::new(),
+            buf: Vec::new(),
+            pos: 0,
+        })
+    }
+
+    /// `with_lookahead()` sets how many stripes a source may be read ahead of the
+    /// stripe being decoded.
+    pub fn with_lookahead(mut self, lookahead: usize) -> AsyncStreamDecoder<R> {
+        self.lookahead = lookahead.max(1);
+        self
+    }
+
+    /// `with_extra_shares()` sets how many chunks beyond k to wait for before a stripe
+    /// is decoded, so that up to `extra_shares / 2` corrupted chunks can be corrected.
+    /// Once a stripe has k chunks, it is decoded with what has arrived after at most
+    /// `max_wait`, so a stalled source cannot hold it back. The wait needs a tokio
+    /// runtime with the time driver enabled.
+    pub fn with_extra_shares(
+        mut self,
+        extra_shares: usize,
+        max_wait: Duration,
+    ) -> AsyncStreamDecoder<R> {
+        self.extra_shares = extra_shares;
+        self.extra_wait = max_wait;
+        self
+    }
+
+    /// Length of the original stream.
+    pub fn len(&self) -> u64 {
+        self.stripes.iter().map(|s| s.len as u64).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stripes.is_empty()
+    }
+
+    /// Share numbers of the sources that were dropped so far, with the reason.
+    pub fn dropped(&self) -> &[(usize, io::Error)] {
+        &self.dropped
+    }
+
+    fn start(&mut self) {
+        let sources = self.pending_sources.take().unwrap_or_default();
+        let (tx, rx) = mpsc::channel(sources.len() * (self.lookahead + 1));
+        let share_lens: Arc<[usize]> = self.stripes.iter().map(|s| s.share_len).collect();
+        let mut tasks = JoinSet::new();
+        for (number, source) in sources.into_iter().enumerate() {
+            if let Some(source) = source {
+                let semaphore = Arc::new(Semaphore::new(self.lookahead));
+                tasks.spawn(read_source(
+                    number,
+                    source,
+                    share_lens.clone(),
+                    semaphore,
+                    tx.clone(),
+                ));
+            }
+        }
+        self.state = Some(Readers { rx, _tasks: tasks });
+    }
+
+    fn receive(&mut self, message: Message) {
+        match message {
+            Message::Chunk {
+                number,
+                stripe,
+                data,
+                permit,
+            } => {
+                self.next_chunk[number] = stripe + 1;
+                // chunks of stripes that were already decoded are not needed
+                if stripe >= self.next_stripe {
+                    self.chunks
+                        .entry(stripe)
+                        .or_default()
+                        .push((Share { number, data }, permit));
+                }
+            }
+            Message::Failed { number, error } => {
+                self.alive[number] = false;
+                self.dropped.push((number, error));
+            }
+        }
+    }
+
+    /// `stripe_status()` returns `Some(true)` if the current stripe can be decoded now,
+    /// `Some(false)` if it never can be, and `None` if more chunks are needed.
+    fn stripe_status(&self, finished: bool) -> Option<bool> {
+        let k = self.codec.fec().k;
+        let have = self.chunks.get(&self.next_stripe).map_or(0, Vec::len);
+        let outstanding = if finished {
+            0
+        } else {
+            (0..self.alive.len())
+                .filter(|&i| self.alive[i] && self.next_chunk[i] <= self.next_stripe)
+                .count()
+        };
+
+        if have >= k + self.extra_shares || (outstanding == 0 && have >= k) {
+            Some(true)
+        } else if have + outstanding < k {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    /// `extra_wait_elapsed()` starts the wait for extra chunks once the current stripe
+    /// has k of them, and returns whether it is over.
+    fn extra_wait_elapsed(&mut self, cx: &mut Context<'_>) -> bool {
+        let have = self.chunks.get(&self.next_stripe).map_or(0, Vec::len);
+        if have < self.codec.fec().k {
+            return false;
+        }
+        let wait = self.extra_wait;
+        let deadline = self
+            .extra_deadline
+            .get_or_insert_with(|| Box::pin(tokio::time::sleep(wait)));
+        deadline.as_mut().poll(cx).is_ready()
+    }
+
+    fn decode_stripe(&mut self) -> io::Result<()> {
+        let stripe = self.stripes[self.next_stripe];
+        self.extra_deadline = None;
+        let shares = self
+            .chunks
+            .remove(&self.next_stripe)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(share, _permit)| share)
+            .collect();
+        self.next_stripe += 1;
+        self.buf = self.codec.decode_stripe(&stripe, shares).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("stripe {}: {}", stripe.index, e),
+            )
+        })?;
+        self.pos = 0;
+        Ok(())
+    }
+
+    fn not_enough_shares(&self) -> io::Error {
+        let have = self.chunks.get(&self.next_stripe).map_or(0, Vec::len);
+        io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!(
+                "stripe {}: only {} of the {} required shares are readable",
+                self.next_stripe,
+                have,
+                self.codec.fec().k
+            ),
+        )
+    }
+}
+
+impl<R> AsyncRead for AsyncStreamDecoder<R>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        out: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.pos == this.buf.len() {
+            if this.next_stripe == this.stripes.len() {
+                return Poll::Ready(Ok(()));
+            }
+            if this.state.is_none() {
+                this.start();
+            }
+
+            let mut finished = false;
+            loop {
+                match this.stripe_status(finished) {
+                    Some(true) => break,
+                    Some(false) => return Poll::Ready(Err(this.not_enough_shares())),
+                    None if this.extra_wait_elapsed(cx) => break,
+                    None => {}
+                }
+                let readers = this.state.as_mut().expect("readers are started");
+                match readers.rx.poll_recv(cx) {
+                    Poll::Ready(Some(message)) => this.receive(message),
+                    Poll::Ready(None) => finished = true,
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+            this.decode_stripe()?;
+        }
+
+        let take = usize::min(out.remaining(), this.buf.len() - this.pos);
+        out.put_slice(&this.buf[this.pos..this.pos + take]);
+        this.pos += take;
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// `read_source()` reads the chunks of share `number` stripe by stripe, holding a
+/// permit from `semaphore` for every chunk until the decoder is done with it.
+async fn read_source<R>(
+    number: usize,
+    mut source: R,
+    share_lens: Arc<[usize]>,
+    semaphore: Arc<Semaphore>,
+    tx: mpsc::Sender<Message>,
+) where
+    R: AsyncRead + Unpin,
+{
+    for (stripe, &share_len) in share_lens.iter().enumerate() {
+        let Ok(permit) = semaphore.clone().acquire_owned().await else {
+            return;
+        };
+        let mut data = vec![0u8; share_len];
+        let message = match source.read_exact(&mut data).await {
+            Ok(_) => Message::Chunk {
+                number,
+                stripe,
+                data,
+                permit,
+            },
+            Err(error) => Message::Failed { number, error },
+        };
+        let failed = matches!(message, Message::Failed { .. });
+        if tx.send(message).await.is_err() || failed {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fec::fec::FEC;
+    use std::time::Duration;
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+    fn codec() -> StripedCodec {
+        StripedCodec::new(FEC::new(4, 8).unwrap(), 64).unwrap()
+    }
+
+    async fn encode(data: &[u8]) -> Vec<Vec<u8>> {
+        let mut encoder = AsyncStreamEncoder::new(codec(), vec![Vec::new(); 8])
+            .unwrap()
+            .with_lookahead(2);
+        encoder.write_all(data).await.unwrap();
+        encoder.shutdown().await.unwrap();
+        let (sinks, len) = encoder.into_inner();
+        assert_eq!(len, data.len() as u64);
+        sinks
+    }
+
+    /// Serves `data` through a duplex stream from a separate task.
+    fn serve(data: Vec<u8>) -> DuplexStream {
+        let (mut writer, reader) = duplex(16);
+        tokio::spawn(async move {
+            let _ = writer.write_all(&data).await;
+        });
+        reader
+    }
+
+    #[tokio::test]
+    async fn test_async_encoder_matches_stream_encoder() {
+        use crate::stream::encoder::StreamEncoder;
+        use std::io::Write;
+
+        let data: Vec<u8> = (0..1000u32).map(|i| (i * 3 + 1) as u8).collect();
+        let mut sync = StreamEncoder::new(codec(), vec![Vec::new(); 8]).unwrap();
+        sync.write_all(&data).unwrap();
+        let (expected, _) = sync.finish().unwrap();
+
+        assert_eq!(encode(&data).await, expected);
+    }
+
+    #[tokio::test]
+    async fn test_async_decoder_ignores_stalled_sources() {
+        let data: Vec<u8> = (0..777u32).map(|i| (i * 7 + 5) as u8).collect();
+        let sinks = encode(&data).await;
+
+        // sources 0 and 3 never send anything, but the writers stay open
+        let mut stalled = Vec::new();
+        let sources = sinks
+            .into_iter()
+            .enumerate()
+            .map(|(i, sink)| {
+                if i == 0 || i == 3 {
+                    let (writer, reader) = duplex(16);
+                    stalled.push(writer);
+                    Some(reader)
+                } else {
+                    Some(serve(sink))
+                }
+            })
+            .collect();
+
+        let mut decoder = AsyncStreamDecoder::new(codec(), sources, data.len() as u64).unwrap();
+        let mut out = Vec::new();
+        decoder.read_to_end(&mut out).await.unwrap();
+        assert_eq!(out, data);
+    }
+
+    #[tokio::test]
+    async fn test_async_decoder_stops_waiting_for_extra_shares() {
+        let data: Vec<u8> = (0..300u32).map(|i| (i * 13 + 2) as u8).collect();
+        let sinks = encode(&data).await;
+
+        // only six sources ever deliver, but the decoder would like all eight
+        let mut stalled = Vec::new();
+        let sources = sinks
+            .into_iter()
+            .enumerate()
+            .map(|(i, sink)| {
+                if i >= 6 {
+                    let (writer, reader) = duplex(16);
+                    stalled.push(writer);
+                    Some(reader)
+                } else {
+                    Some(serve(sink))
+                }
+            })
+            .collect();
+
+        let mut decoder = AsyncStreamDecoder::new(codec(), sources, data.len() as u64)
+            .unwrap()
+            .with_extra_shares(4, Duration::from_millis(20));
+        let mut out = Vec::new();
+        tokio::time::timeout(Duration::from_secs(5), decoder.read_to_end(&mut out))
+            .await
+            .expect("the extra shares are waited for a bounded time")
+            .unwrap();
+        assert_eq!(out, data);
+    }
+
+    #[tokio::test]
+    async fn test_async_decoder_corrects_and_drops() {
+        let data: Vec<u8> = (0..500u32).map(|i| (i * 11) as u8).collect();
+        let mut sinks = encode(&data).await;
+        sinks[1][70] ^= 0x55;
+        sinks[6].truncate(100);
+
+        let mut sources: Vec<Option<DuplexStream>> =
+            sinks.into_iter().map(|s| Some(serve(s))).collect();
+        sources[4] = None;
+
+        let mut decoder = AsyncStreamDecoder::new(codec(), sources, data.len() as u64)
+            .unwrap()
+            .with_extra_shares(2, Duration::from_secs(5))
+            .with_lookahead(1);
+        let mut out = Vec::new();
+        decoder.read_to_end(&mut out).await.unwrap();
+        assert_eq!(out, data);
+        assert_eq!(decoder.dropped().len(), 1);
+        assert_eq!(decoder.dropped()[0].0, 6);
+    }
+
+    #[tokio::test]
+    async fn test_async_decoder_cancellation() {
+        let mut writers = Vec::new();
+        let sources = (0..8)
+            .map(|_| {
+                let (writer, reader) = duplex(16);
+                writers.push(writer);
+                Some(reader)
+            })
+            .collect();
+
+        let mut decoder = AsyncStreamDecoder::new(codec(), sources, 256).unwrap();
+        let mut out = [0u8; 16];
+        let read = tokio::time::timeout(Duration::from_millis(20), decoder.read(&mut out)).await;
+        assert!(read.is_err());
+
+        // dropping the decoder aborts the tasks, which closes the read halves
+        drop(decoder);
+        for mut writer in writers {
+            let mut closed = false;
+            for _ in 0..100 {
+                if writer.write_all(&[0u8; 16]).await.is_err() {
+                    closed = true;
+                    break;
+                }
+                tokio::task::yield_now().await;
+            }
+            assert!(closed);
+        }
+    }
+}
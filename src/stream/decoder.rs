@@ -0,0 +1,191 @@
+use crate::fec::fec::Share;
+use crate::fec::striped::{Stripe, StripedCodec};
+use std::io::{self, Read};
+
+/// `StreamDecoder` reconstructs a stream written by `StreamEncoder` from its share
+/// streams and exposes the original bytes through `std::io::Read`.
+///
+/// `sources[i]` reads share stream `i`; missing streams are `None`. Stripes are read
+/// one at a time: the chunk of the stripe is read from every live source, the chunks
+/// are corrected with `FEC::correct` and rebuilt. A source that fails or ends early is
+/// dropped and treated as an erasure from then on, so decoding continues as long as
+/// at least k sources remain.
+#[derive(Debug)]
+pub struct StreamDecoder<R: Read> {
+    codec: StripedCodec,
+    sources: Vec<Option<R>>,
+    len: u64,
+    next_stripe: u64,
+    stripes: u64,
+    buf: Vec<u8>,
+    pos: usize,
+    dropped: Vec<(usize, io::Error)>,
+}
+
+impl<R: Read> StreamDecoder<R> {
+    /// `new()` creates a decoder for a stream of `len` bytes (as returned by
+    /// `StreamEncoder::finish`) encoded with `codec`. There must be one entry in
+    /// `sources` per share number.
+    pub fn new(
+        codec: StripedCodec,
+        sources: Vec<Option<R>>,
+        len: u64,
+    ) -> io::Result<StreamDecoder<R>> {
+        if sources.len() != codec.fec().n {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("expected {} sources, got {}", codec.fec().n, sources.len()),
+            ));
+        }
+        let stripes = len.div_ceil(codec.stripe_size() as u64);
+        Ok(StreamDecoder {
+            codec,
+            sources,
+            len,
+            next_stripe: 0,
+            stripes,
+            buf: Vec::new(),
+            pos: 0,
+            dropped: Vec::new(),
+        })
+    }
+
+    /// Length of the original stream.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Share numbers of the sources that were dropped so far, with the reason.
+    pub fn dropped(&self) -> &[(usize, io::Error)] {
+        &self.dropped
+    }
+
+    /// Returns the sources, `None` for those that were missing or dropped.
+    pub fn into_sources(self) -> Vec<Option<R>> {
+        self.sources
+    }
+
+    fn next_stripe(&self) -> Stripe {
+        let stripe_size = self.codec.stripe_size() as u64;
+        let offset = self.next_stripe * stripe_size;
+        let len = u64::min(stripe_size, self.len - offset) as usize;
+        Stripe {
+            index: self.next_stripe as usize,
+            offset: offset as usize,
+            len,
+            share_len: len.div_ceil(self.codec.fec().k),
+        }
+    }
+
+    fn decode_stripe(&mut self) -> io::Result<()> {
+        let stripe = self.next_stripe();
+
+        let mut shares = Vec::with_capacity(self.sources.len());
+        for (number, slot) in self.sources.iter_mut().enumerate() {
+            let Some(source) = slot else { continue };
+            let mut data = vec![0u8; stripe.share_len];
+            match source.read_exact(&mut data) {
+                Ok(()) => shares.push(Share { number, data }),
+                Err(e) => {
+                    *slot = None;
+                    self.dropped.push((number, e));
+                }
+            }
+        }
+
+        if shares.len() < self.codec.fec().k {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "stripe {}: only {} of the {} required shares are readable",
+                    stripe.index,
+                    shares.len(),
+                    self.codec.fec().k
+                ),
+            ));
+        }
+
+        self.buf = self.codec.decode_stripe(&stripe, shares).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("stripe {}: {}", stripe.index, e),
+            )
+        })?;
+        self.pos = 0;
+        self.next_stripe += 1;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for StreamDecoder<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.buf.len() {
+            if self.next_stripe == self.stripes {
+                return Ok(0);
+            }
+            self.decode_stripe()?;
+        }
+
+        let take = usize::min(out.len(), self.buf.len() - self.pos);
+        out[..take].copy_from_slice(&self.buf[self.pos..self.pos + take]);
+        self.pos += take;
+        Ok(take)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fec::fec::FEC;
+    use crate::stream::encoder::StreamEncoder;
+    use std::io::{Cursor, Write};
+
+    fn encode(data: &[u8]) -> Vec<Vec<u8>> {
+        let codec = StripedCodec::new(FEC::new(4, 8).unwrap(), 64).unwrap();
+        let mut stream = StreamEncoder::new(codec, vec![Vec::new(); 8]).unwrap();
+        stream.write_all(data).unwrap();
+        stream.finish().unwrap().0
+    }
+
+    #[test]
+    fn test_stream_decoder_recovers_with_failing_sources() {
+        let data: Vec<u8> = (0..1001u32).map(|i| (i * 5 + 2) as u8).collect();
+        let mut sinks = encode(&data);
+
+        // one corrupted stream, one cut off half way, one missing
+        sinks[2][100] ^= 0xFF;
+        sinks[5].truncate(60);
+        let mut sources: Vec<Option<Cursor<Vec<u8>>>> =
+            sinks.into_iter().map(|s| Some(Cursor::new(s))).collect();
+        sources[7] = None;
+
+        let codec = StripedCodec::new(FEC::new(4, 8).unwrap(), 64).unwrap();
+        let mut decoder = StreamDecoder::new(codec, sources, data.len() as u64).unwrap();
+        let mut out = Vec::new();
+        decoder.read_to_end(&mut out).unwrap();
+
+        assert_eq!(out, data);
+        assert_eq!(decoder.dropped().len(), 1);
+        assert_eq!(decoder.dropped()[0].0, 5);
+    }
+
+    #[test]
+    fn test_stream_decoder_not_enough_sources() {
+        let data = vec![7u8; 300];
+        let sinks = encode(&data);
+        let sources: Vec<Option<Cursor<Vec<u8>>>> = sinks
+            .into_iter()
+            .enumerate()
+            .map(|(i, s)| if i < 3 { Some(Cursor::new(s)) } else { None })
+            .collect();
+
+        let codec = StripedCodec::new(FEC::new(4, 8).unwrap(), 64).unwrap();
+        let mut decoder = StreamDecoder::new(codec, sources, data.len() as u64).unwrap();
+        let err = decoder.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,149 @@
+use crate::fec::striped::{Stripe, StripedCodec};
+use crate::fec::workspace::Encoder;
+use std::io::{self, Write};
+
+/// `StreamEncoder` erasure-codes a byte stream as it is written.
+///
+/// Bytes are accumulated into stripes of `stripe_size` bytes (see `StripedCodec`).
+/// Whenever a stripe is complete it is encoded and share `i` of the stripe is appended
+/// to `sinks[i]`, so sink `i` ends up holding share `i` of every stripe back to back.
+/// `finish()` encodes the final, possibly shorter, stripe and returns the true length
+/// of the stream, which together with the codec is all a `StreamDecoder` needs to
+/// recover the stripe boundaries.
+#[derive(Debug)]
+pub struct StreamEncoder<W: Write> {
+    codec: StripedCodec,
+    encoder: Encoder,
+    sinks: Vec<W>,
+    buf: Vec<u8>,
+    len: u64,
+}
+
+impl<W: Write> StreamEncoder<W> {
+    /// `new()` creates a stream encoder writing one share stream to each of the n sinks.
+    pub fn new(codec: StripedCodec, sinks: Vec<W>) -> io::Result<StreamEncoder<W>> {
+        if sinks.len() != codec.fec().n {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("expected {} sinks, got {}", codec.fec().n, sinks.len()),
+            ));
+        }
+        let encoder = Encoder::new(codec.fec(), codec.stripe_size() / codec.fec().k);
+        let buf = Vec::with_capacity(codec.stripe_size());
+        Ok(StreamEncoder {
+            codec,
+            encoder,
+            sinks,
+            buf,
+            len: 0,
+        })
+    }
+
+    /// Number of bytes written to the stream so far.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn codec(&self) -> &StripedCodec {
+        &self.codec
+    }
+
+    /// `finish()` encodes the buffered partial stripe, if any, flushes the sinks and
+    /// returns them along with the true length of the stream.
+    pub fn finish(mut self) -> io::Result<(Vec<W>, u64)> {
+        if !self.buf.is_empty() {
+            let stripe = Stripe {
+                index: (self.len / self.codec.stripe_size() as u64) as usize,
+                offset: 0,
+                len: self.buf.len(),
+                share_len: self.buf.len().div_ceil(self.codec.fec().k),
+            };
+            let mut result = Ok(());
+            let sinks = &mut self.sinks;
+            self.codec
+                .encode_stripe(&stripe, &self.buf, |s| {
+                    if result.is_ok() {
+                        result = sinks[s.number].write_all(&s.data);
+                    }
+                })
+                .map_err(|e| io::Error::other(e.to_string()))?;
+            result?;
+        }
+        for sink in self.sinks.iter_mut() {
+            sink.flush()?;
+        }
+        Ok((self.sinks, self.len))
+    }
+
+    fn encode_stripe(&mut self) -> io::Result<()> {
+        let mut result = Ok(());
+        let sinks = &mut self.sinks;
+        self.encoder
+            .encode(self.codec.fec(), &self.buf, |number, data| {
+                if result.is_ok() {
+                    result = sinks[number].write_all(data);
+                }
+            })
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        self.buf.clear();
+        result
+    }
+}
+
+impl<W: Write> Write for StreamEncoder<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let take = usize::min(self.codec.stripe_size() - self.buf.len(), data.len());
+        self.buf.extend_from_slice(&data[..take]);
+        self.len += take as u64;
+        if self.buf.len() == self.codec.stripe_size() {
+            self.encode_stripe()?;
+        }
+        Ok(take)
+    }
+
+    /// Flushes the sinks. A partial stripe stays buffered until it is complete or
+    /// `finish()` is called.
+    fn flush(&mut self) -> io::Result<()> {
+        for sink in self.sinks.iter_mut() {
+            sink.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fec::fec::FEC;
+
+    #[test]
+    fn test_stream_encoder_matches_striped_codec() {
+        let data: Vec<u8> = (0..1000u32).map(|i| (i * 13 + 1) as u8).collect();
+        let codec = StripedCodec::new(FEC::new(3, 5).unwrap(), 96).unwrap();
+
+        let mut expected = vec![Vec::new(); 5];
+        codec
+            .encode(&data, |_, s| expected[s.number].extend_from_slice(&s.data))
+            .unwrap();
+
+        let mut stream = StreamEncoder::new(codec, vec![Vec::new(); 5]).unwrap();
+        for chunk in data.chunks(37) {
+            stream.write_all(chunk).unwrap();
+        }
+        assert_eq!(stream.len(), 1000);
+        let (sinks, len) = stream.finish().unwrap();
+
+        assert_eq!(len, 1000);
+        assert_eq!(sinks, expected);
+    }
+
+    #[test]
+    fn test_stream_encoder_requires_n_sinks() {
+        let codec = StripedCodec::new(FEC::new(3, 5).unwrap(), 96).unwrap();
+        assert!(StreamEncoder::new(codec, vec![Vec::<u8>::new(); 4]).is_err());
+    }
+}
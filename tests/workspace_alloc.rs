@@ -0,0 +1,65 @@
+//! The allocation counter replaces the global allocator, so it lives in its own test
+//! binary instead of the library's unit tests.
+
+use reed_solomon_rs::fec::fec::{Share, FEC};
+use reed_solomon_rs::fec::workspace::{with_decoder, with_encoder};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+/// Counts the allocations made by the current thread.
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|a| a.set(a.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(|a| a.get())
+}
+
+#[test]
+fn test_steady_state_does_not_allocate() {
+    let fec = FEC::new(6, 14).unwrap();
+    let data: Vec<u8> = (0..6000u32).map(|i| (i * 13) as u8).collect();
+    let mut shares: Vec<Share> = vec![];
+    fec.encode(&data, |s: Share| shares.push(s)).unwrap();
+    let mut checksum = 0u8;
+
+    // warm up the per-thread pools
+    with_encoder(&fec, 1000, |e| {
+        e.encode(&fec, &data, |_, d| checksum ^= d[0])
+    })
+    .unwrap();
+    with_decoder(&fec, 1000, |d| d.decode(&fec, &mut shares).map(|_| ())).unwrap();
+
+    let before = allocations();
+    for round in 0..10 {
+        with_encoder(&fec, 1000, |e| {
+            e.encode(&fec, &data, |_, d| checksum ^= d[0])
+        })
+        .unwrap();
+
+        shares[round].data[round * 7] ^= 0xFF;
+        shares[13].data[round] ^= 0x0F;
+        with_decoder(&fec, 1000, |d| {
+            let decoded = d.decode(&fec, &mut shares).unwrap();
+            assert!(decoded == &data[..]);
+        });
+    }
+    assert_eq!(allocations(), before);
+    std::hint::black_box(checksum);
+}